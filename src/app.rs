@@ -1,17 +1,39 @@
-use ratatui::widgets::ListState;
+use ratatui::layout::Rect;
+use ratatui::widgets::{ListState, TableState};
+use rodio::source::SeekError;
+use serde::{Deserialize, Serialize};
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::path::Path;
 use std::time::Duration;
-use std::{sync::mpsc, time::Instant};
+use std::{sync::mpsc, thread, time::Instant};
 
-use crate::audyo::service::AudioEvent;
-use crate::ui::donut::Donut;
-use crate::{AudioFolder, AudioService, Focus, downloader::facade::YoutubeFacade};
+use crate::audyo::equalizer::{EqPreset, MAX_BAND_GAIN_DB};
+use crate::audyo::lyrics::Lyrics;
+use crate::audyo::mixer::SAMPLE_RATE;
+use crate::audyo::service::{AudioEvent, WaveFormData};
+use crate::audyo::sleep::SleepTimer;
+use crate::config::Config;
+use crate::library;
+use crate::library_index::{self, LibraryIndex, Refresh};
+use crate::session::Session;
+use crate::ui::{
+    artwork::{self, Artwork, Image, graphics::Graphics},
+    donut::Donut,
+    spectrum::{FFT_SIZE, Spectrum},
+};
+use crate::watcher::{LibraryChange, Watcher};
+use crate::{
+    AudioFolder, AudioService, Focus, downloader::facade::YoutubeFacade, formart_duration,
+};
 
 pub struct App<'a> {
-    pub folder_state: ListState,
+    pub folder_state: TableState,
+    pub queue_state: ListState,
 
     pub audio_service: AudioService,
     pub audio_folder: AudioFolder,
-    pub buttons: Vec<&'a str>,
+    pub buttons: Vec<Cow<'a, str>>,
     pub button_index: usize,
     pub focus: Focus,
     pub tick_rate: Duration,
@@ -26,8 +48,33 @@ pub struct App<'a> {
     pub tx: mpsc::Sender<SignalMessage>,
     rx: mpsc::Receiver<SignalMessage>,
     pub show_help: bool,
-    pub sparkline_points: Signal<RandomSignal>,
     pub donut: Donut,
+    pub spectrum: Spectrum,
+    pub main_view: MainView,
+    pub artwork: Artwork,
+    pub config: Config,
+    pub message: Option<String>,
+    pub last_message: Instant,
+    pub progress_area: Rect,
+    pub show_waveform: bool,
+    pub show_equalizer: bool,
+    pub eq_band: usize,
+    /// Output devices listed in the device picker.
+    pub devices: Vec<String>,
+    pub device_state: ListState,
+    waveform_path: Option<String>,
+    pub show_lyrics: bool,
+    pub lyrics: Option<Lyrics>,
+    /// Track the lyrics belong to, set as soon as loading starts.
+    lyrics_path: Option<String>,
+    /// Line picked in the lyrics panel while it has focus.
+    pub lyrics_line: Option<usize>,
+    watcher: Option<Watcher>,
+    /// Number of the latest library scan; older ones finishing late are
+    /// ignored.
+    scan_generation: u64,
+    /// Track whose play was last counted in the library index.
+    counted_track: Option<String>,
 }
 
 pub struct TextInput {
@@ -43,45 +90,23 @@ impl TextInput {
         }
     }
 
-    fn insert(&mut self, c: char) {
+    pub fn insert(&mut self, c: char) {
         self.content.insert(self.cursor, c);
         self.cursor += 1;
     }
 
-    fn insert_str(&mut self, s: &str) {
+    pub fn insert_str(&mut self, s: &str) {
         self.content.insert_str(self.cursor, s);
         self.cursor += s.len();
     }
 
-    fn delete_back(&mut self) {
+    pub fn delete_back(&mut self) {
         if self.cursor > 0 {
             self.cursor -= 1;
             self.content.remove(self.cursor);
         }
     }
 
-    fn delete_forward(&mut self) {
-        if self.cursor < self.content.len() {
-            self.content.remove(self.cursor);
-        }
-    }
-
-    fn move_left(&mut self) {
-        self.cursor = self.cursor.saturating_sub(1);
-    }
-
-    fn move_right(&mut self) {
-        self.cursor = (self.cursor + 1).min(self.content.len());
-    }
-
-    fn move_start(&mut self) {
-        self.cursor = 0;
-    }
-
-    fn move_end(&mut self) {
-        self.cursor = self.content.len();
-    }
-
     pub fn clear(&mut self) {
         self.content.clear();
         self.cursor = 0;
@@ -96,59 +121,630 @@ impl App<'_> {
     pub fn new() -> Self {
         let ytb_facade = YoutubeFacade::new();
 
-        let audio_folder = AudioFolder::new().path(format!(
-            "{}/*",
-            ytb_facade.output_dir.display().to_string().clone()
-        ));
-        let mut folder_state = ListState::default();
-        folder_state.select(Some(0));
-
+        let (config, config_error) = Config::load();
+        // The download folder only exists after the first download.
+        let mut roots: Vec<_> = Some(ytb_facade.output_dir.clone())
+            .filter(|dir| dir.exists())
+            .into_iter()
+            .collect();
+        roots.extend(config.library_dirs.iter().cloned());
         let (tx, rx) = mpsc::channel();
-        let audio_service = AudioService::new();
+        // Without inotify the library still updates on `r` and downloads.
+        let watcher = Watcher::start(&roots, tx.clone()).ok();
+        let audio_folder = AudioFolder::new().roots(roots).index(LibraryIndex::load());
+        let mut folder_state = TableState::default();
+        folder_state.select(Some(0));
 
-        let mut vec_signal = RandomSignal::new(0, 100);
-        let sparkline_points = vec_signal.by_ref().take(200).collect::<Vec<_>>();
+        let audio_service = AudioService::new(&config);
 
         Self {
             folder_state,
-            audio_service: audio_service,
-            audio_folder: audio_folder,
+            queue_state: ListState::default(),
+            audio_service,
+            audio_folder,
             buttons: vec![],
             button_index: 0,
             focus: Focus::FolderList,
             tick_rate: Duration::from_millis(80),
             should_quit: false,
             text: TextInput::new(),
-            ytb_facade: ytb_facade,
+            ytb_facade,
             loop_mode: LoopMode::Single,
             volume: Volume::Normal,
             mute_sound: MuteSound::Off,
             last_toggle_volume: Instant::now(),
-            tx: tx,
-            rx: rx,
+            tx,
+            rx,
             show_help: false,
-            sparkline_points: Signal {
-                source: vec_signal,
-                points: sparkline_points,
-                tick_rate: 2,
-            },
             donut: Donut::new(),
+            spectrum: Spectrum::new(),
+            main_view: MainView::Donut,
+            artwork: Artwork::new(config.artwork_graphics.unwrap_or_else(Graphics::detect)),
+            show_waveform: config.waveform_seekbar,
+            config,
+            show_equalizer: false,
+            eq_band: 0,
+            devices: Vec::new(),
+            device_state: ListState::default(),
+            waveform_path: None,
+            show_lyrics: false,
+            lyrics: None,
+            lyrics_path: None,
+            lyrics_line: None,
+            watcher,
+            scan_generation: 0,
+            counted_track: None,
+            message: config_error,
+            last_message: Instant::now(),
+            progress_area: Rect::default(),
         }
     }
+    /// Rescans the library folders in the background; the result arrives as
+    /// `SignalMessage::Scanned`. On startup the library is listed from the
+    /// index in the meantime.
     pub fn load_folder(&mut self) {
-        self.audio_folder.load_mp3_file();
-        self.audio_service.playlist = self.audio_folder.files.clone();
+        let output_dir = &self.ytb_facade.output_dir;
+        if output_dir.exists() && !self.audio_folder.roots.contains(output_dir) {
+            // Created by the first download.
+            self.audio_folder.roots.insert(0, output_dir.clone());
+            if let Some(watcher) = &self.watcher {
+                watcher.watch(output_dir);
+            }
+        }
+        if self.audio_folder.files.is_empty() {
+            self.audio_folder.load_index();
+            self.library_changed(true, None);
+        }
+        self.scan_generation += 1;
+        let generation = self.scan_generation;
+        let roots = self.audio_folder.roots.clone();
+        let stamps = self.audio_folder.index.stamps();
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            let refresh = library_index::refresh(&roots, &stamps);
+            let _ = tx.send(SignalMessage::Scanned(generation, refresh));
+        });
+    }
+    fn apply_refresh(&mut self, generation: u64, refresh: Refresh) {
+        if generation != self.scan_generation {
+            return;
+        }
+        let first_load = self.audio_folder.files.is_empty();
+        let selected = self.selected_file();
+        self.audio_folder.apply_refresh(refresh);
+        if let Some(errors) = library::describe_errors(&self.audio_folder.errors) {
+            self.notify(errors);
+        }
+        if let Err(e) = self.audio_folder.index.save() {
+            self.notify(format!("Saving library index failed: {}", e));
+        }
+        self.library_changed(first_load, selected.as_deref());
+    }
+    /// Passes the new library on to the service, keeping `selected` picked.
+    fn library_changed(&mut self, first_load: bool, selected: Option<&str>) {
+        self.audio_service
+            .set_library(self.audio_folder.files.clone(), &self.audio_folder.index);
+        // On startup the whole library is queued so it plays in order.
+        if first_load && self.audio_service.queue.is_empty() {
+            self.audio_service.queue = self.audio_folder.files.clone();
+            self.queue_state.select(Some(0));
+        }
+        self.reselect_file(selected);
+    }
+    /// Selects `path` in the library again after it changed, or the nearest
+    /// row when it is gone.
+    fn reselect_file(&mut self, path: Option<&str>) {
+        let files = &self.audio_folder.files;
+        let row = path
+            .and_then(|p| files.iter().position(|f| f == p))
+            .or(self.folder_state.selected())
+            .map(|i| i.min(files.len().saturating_sub(1)));
+        self.folder_state.select(row);
+    }
+    /// Applies what the watcher saw change on disk, keeping the selected
+    /// rows and the current track where they are.
+    fn apply_library_changes(&mut self, changes: Vec<LibraryChange>) {
+        let mut selected = self.selected_file();
+        let mut gone = HashSet::new();
+        for change in changes {
+            match change {
+                LibraryChange::Added(path, entry) => {
+                    gone.remove(&path);
+                    self.audio_folder.add(path, entry);
+                }
+                LibraryChange::Removed(path) => gone.extend(self.audio_folder.remove(&path)),
+                LibraryChange::Renamed(from, to) => {
+                    for (old, new) in self.audio_folder.rename(&from, &to) {
+                        self.audio_service.rename_track(&old, &new);
+                        if selected.as_ref() == Some(&old) {
+                            selected = Some(new);
+                        }
+                    }
+                }
+                LibraryChange::Rescan => {
+                    self.load_folder();
+                    return;
+                }
+            }
+        }
+        if !gone.is_empty() {
+            let removed = self.audio_service.forget_tracks(&gone);
+            if let Some(row) = self.queue_state.selected() {
+                let before = removed.iter().filter(|&&i| i < row).count();
+                let len = self.audio_service.queue.len();
+                self.queue_state
+                    .select((len > 0).then(|| (row - before).min(len - 1)));
+            }
+        }
+        self.audio_folder.sort();
+        self.library_changed(false, selected.as_deref());
+    }
+    /// Picks up where the last session left off: the same queue and track
+    /// at the same position, volume and loop mode.
+    pub fn restore_session(&mut self) {
+        let Some(session) = Session::load() else {
+            return;
+        };
+        self.audio_service.set_resume_points(session.resume_points);
+        self.audio_service.set_volume(session.volume);
+        if session.muted {
+            self.toggle_mute();
+        }
+        self.loop_mode = session.loop_mode;
+        self.audio_service.set_loop_mode(session.loop_mode);
+
+        // Files may have been deleted since.
+        let queue: Vec<String> = session
+            .queue
+            .into_iter()
+            .filter(|p| Path::new(p).exists())
+            .collect();
+        if !queue.is_empty() {
+            let index = session
+                .current
+                .as_ref()
+                .and_then(|c| queue.iter().position(|p| p == c))
+                .unwrap_or(session.queue_index.min(queue.len() - 1));
+            if session.current.as_ref() == Some(&queue[index]) {
+                self.audio_service.start_at(
+                    queue[index].clone(),
+                    Duration::from_secs_f64(session.position_secs.max(0.0)),
+                );
+            }
+            self.audio_service.queue = queue;
+            self.audio_service.queue_index = index;
+            if session.playing {
+                self.audio_service.audio_event = AudioEvent::Play;
+                self.audio_service.play();
+            }
+        }
+        let files = self.audio_folder.files.len();
+        if let Some(row) = session.library_row.filter(|&r| r < files) {
+            self.folder_state.select(Some(row));
+        }
+        let queued = self.audio_service.queue.len();
+        self.queue_state
+            .select(session.queue_row.filter(|&r| r < queued));
+    }
+    pub fn save_session(&mut self) -> std::io::Result<()> {
+        self.audio_service.remember_position();
+        let service = &self.audio_service;
+        Session {
+            current: service.current_audio.clone(),
+            position_secs: service.get_current_position().as_secs_f64(),
+            playing: service.audio_event == AudioEvent::Play,
+            volume: service.current_volume,
+            muted: self.mute_sound == MuteSound::On,
+            loop_mode: self.loop_mode,
+            queue: service.queue.clone(),
+            queue_index: service.queue_index,
+            library_row: self.folder_state.selected(),
+            queue_row: self.queue_state.selected(),
+            resume_points: service.resume_points().clone(),
+        }
+        .save()
+    }
+    fn selected_file(&self) -> Option<String> {
+        let i = self.folder_state.selected()?;
+        self.audio_folder.files.get(i).cloned()
+    }
+    /// Replaces the queue with the library from the selected track onwards.
+    pub fn play_from_here(&mut self) {
+        if let Some(i) = self.folder_state.selected()
+            && i < self.audio_folder.files.len()
+        {
+            self.audio_service
+                .replace_queue(self.audio_folder.files[i..].to_vec());
+            self.queue_state.select(Some(0));
+        }
+    }
+    pub fn queue_play_next(&mut self) {
+        if let Some(path) = self.selected_file() {
+            self.audio_service.play_next(path);
+            self.notify("Playing next");
+        }
+    }
+    pub fn queue_add(&mut self) {
+        if let Some(path) = self.selected_file() {
+            self.audio_service.enqueue(path);
+            self.notify("Added to queue");
+        }
+    }
+    pub fn next_queue_entry(&mut self) {
+        let len = self.audio_service.queue.len();
+        if len > 0 {
+            let i = self.queue_state.selected().map_or(0, |i| (i + 1) % len);
+            self.queue_state.select(Some(i));
+        }
+    }
+    pub fn prev_queue_entry(&mut self) {
+        let len = self.audio_service.queue.len();
+        if len > 0 {
+            let i = self
+                .queue_state
+                .selected()
+                .map_or(0, |i| if i == 0 { len - 1 } else { i - 1 });
+            self.queue_state.select(Some(i));
+        }
+    }
+    pub fn play_queue_entry(&mut self) {
+        if let Some(i) = self.queue_state.selected() {
+            self.audio_service.play_queue_from(i);
+        }
+    }
+    pub fn remove_queue_entry(&mut self) {
+        if let Some(i) = self.queue_state.selected() {
+            self.audio_service.remove_from_queue(i);
+            let len = self.audio_service.queue.len();
+            self.queue_state.select((len > 0).then(|| i.min(len - 1)));
+        }
+    }
+    /// Moves the selected queue entry up (`-1`) or down (`1`).
+    pub fn move_queue_entry(&mut self, delta: isize) {
+        let len = self.audio_service.queue.len();
+        if let Some(i) = self.queue_state.selected()
+            && let Some(to) = i.checked_add_signed(delta)
+            && to < len
+        {
+            self.audio_service.move_in_queue(i, to);
+            self.queue_state.select(Some(to));
+        }
+    }
+    pub fn clear_queue(&mut self) {
+        self.audio_service.clear_queue();
+        self.queue_state.select(None);
+        self.notify("Queue cleared");
     }
     pub fn toggle_mode(&mut self) {
         self.loop_mode = self.loop_mode.next();
-        self.audio_service.loop_mode = self.loop_mode;
+        self.audio_service.set_loop_mode(self.loop_mode);
+        self.notify(self.loop_mode.name());
     }
     pub fn poll_msg(&mut self) {
         while let Ok(msg) = self.rx.try_recv() {
             match msg {
                 SignalMessage::Downloaded => self.load_folder(),
-                SignalMessage::UpdateIndex(index) => self.folder_state.select(Some(index)),
+                SignalMessage::Library(changes) => self.apply_library_changes(changes),
+                SignalMessage::Scanned(generation, refresh) => {
+                    self.apply_refresh(generation, refresh)
+                }
+                SignalMessage::UpdateIndex(index) => self.queue_state.select(Some(index)),
+                SignalMessage::Waveform(path, waveform) => {
+                    if self.audio_service.current_audio.as_ref() == Some(&path) {
+                        self.audio_service.waveform = waveform;
+                    }
+                }
+                SignalMessage::Artwork(path, image) => self.artwork.loaded(&path, image),
+                SignalMessage::Lyrics(path, lyrics) => {
+                    if self.lyrics_path.as_ref() == Some(&path) {
+                        self.lyrics = lyrics;
+                    }
+                }
+            }
+        }
+    }
+    /// Shows `message` under the progress bar for a few seconds.
+    pub fn notify(&mut self, message: impl Into<String>) {
+        self.message = Some(message.into());
+        self.last_message = Instant::now();
+    }
+    pub fn report_seek(&mut self, result: Result<(), SeekError>) {
+        if let Err(e) = result {
+            self.notify(format!("Seek failed: {}", e));
+        }
+    }
+    /// Decodes the current track's envelope in the background; the result
+    /// arrives as `SignalMessage::Waveform`.
+    fn load_waveform(&mut self) {
+        self.audio_service.waveform = WaveFormData::default();
+        self.waveform_path = self.audio_service.current_audio.clone();
+        let Some(path) = self.waveform_path.clone() else {
+            return;
+        };
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            if let Some(waveform) = WaveFormData::load(&path) {
+                let _ = tx.send(SignalMessage::Waveform(path, waveform));
+            }
+        });
+    }
+    /// Loads the current track's artwork in the background; it arrives as
+    /// `SignalMessage::Artwork`.
+    fn load_artwork(&mut self) {
+        self.artwork
+            .start_loading(self.audio_service.current_audio.clone());
+        let Some(path) = self.artwork.path().cloned() else {
+            return;
+        };
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            let image = artwork::load(&path);
+            let _ = tx.send(SignalMessage::Artwork(path, image));
+        });
+    }
+    /// Reads the current track's lyrics in the background; they arrive as
+    /// `SignalMessage::Lyrics`.
+    fn load_lyrics(&mut self) {
+        self.lyrics = None;
+        self.lyrics_line = None;
+        self.lyrics_path = self.audio_service.current_audio.clone();
+        let Some(path) = self.lyrics_path.clone() else {
+            return;
+        };
+        let tx = self.tx.clone();
+        thread::spawn(move || {
+            let lyrics = Lyrics::load(&path);
+            let _ = tx.send(SignalMessage::Lyrics(path, lyrics));
+        });
+    }
+    pub fn toggle_lyrics(&mut self) {
+        self.show_lyrics = !self.show_lyrics;
+        if !self.show_lyrics && self.focus == Focus::Lyrics {
+            self.focus = Focus::FolderList;
+        }
+    }
+    /// Timing fix of the current track's lyrics, in milliseconds.
+    pub fn lyrics_offset(&self) -> i64 {
+        self.audio_service
+            .current_audio
+            .as_ref()
+            .and_then(|path| self.config.lyrics_offsets.get(path))
+            .copied()
+            .unwrap_or(0)
+    }
+    /// Line of the current track's lyrics being sung.
+    pub fn current_lyric(&self) -> Option<usize> {
+        self.lyrics.as_ref()?.current(
+            self.audio_service.get_current_position(),
+            self.lyrics_offset(),
+        )
+    }
+    /// Shifts the current track's lyrics by `delta_ms`; positive shows them
+    /// earlier.
+    pub fn shift_lyrics(&mut self, delta_ms: i64) {
+        let Some(path) = self.audio_service.current_audio.clone() else {
+            self.notify("Nothing is playing");
+            return;
+        };
+        let offset = self.lyrics_offset() + delta_ms;
+        if offset == 0 {
+            self.config.lyrics_offsets.remove(&path);
+        } else {
+            self.config.lyrics_offsets.insert(path, offset);
+        }
+        self.notify(format!("Lyrics offset: {:+.1}s", offset as f64 / 1000.0));
+        if let Err(e) = self.config.save() {
+            self.notify(format!("Saving config failed: {}", e));
+        }
+    }
+    /// Moves the lyrics selection, starting from the line being sung.
+    pub fn move_lyrics_line(&mut self, delta: isize) {
+        let Some(len) = self.lyrics.as_ref().map(|l| l.lines.len()) else {
+            return;
+        };
+        let from = self.lyrics_line.or(self.current_lyric());
+        let to = match from {
+            Some(i) => i.saturating_add_signed(delta).min(len.saturating_sub(1)),
+            None => 0,
+        };
+        self.lyrics_line = (len > 0).then_some(to);
+    }
+    /// Seeks to the selected lyrics line.
+    pub fn play_lyrics_line(&mut self) {
+        let offset = self.lyrics_offset();
+        let time = match (&self.lyrics, self.lyrics_line) {
+            (Some(lyrics), Some(i)) => lyrics.line_time(i, offset),
+            _ => return,
+        };
+        match time {
+            Some(time) => {
+                let result = self.audio_service.seek_to(time);
+                self.report_seek(result);
+                self.lyrics_line = None;
             }
+            None => self.notify("These lyrics have no timing"),
+        }
+    }
+    /// Counts plays and records loudness scans in the library index.
+    fn update_index(&mut self) {
+        let service = &mut self.audio_service;
+        if service.audio_event == AudioEvent::Play && service.current_audio != self.counted_track {
+            self.counted_track = service.current_audio.clone();
+            if let Some(path) = &self.counted_track {
+                self.audio_folder.index.played(path);
+            }
+        }
+        for (path, lufs) in service.take_measured_loudness() {
+            self.audio_folder.index.set_loudness(&path, lufs);
+        }
+    }
+    pub fn toggle_normalization(&mut self) {
+        let normalization = self.audio_service.normalization().next();
+        self.audio_service.set_normalization(normalization);
+        self.notify(format!("Normalization: {}", normalization.text()));
+    }
+    /// The current track when it has its own equalizer preset; the panel
+    /// edits the global preset otherwise.
+    fn eq_target(&self) -> Option<String> {
+        self.audio_service
+            .current_audio
+            .clone()
+            .filter(|path| self.audio_service.equalizer().has_override(path))
+    }
+    pub fn eq_preset(&self) -> EqPreset {
+        self.audio_service
+            .equalizer()
+            .preset_for(self.eq_target().as_deref())
+    }
+    pub fn eq_has_override(&self) -> bool {
+        self.eq_target().is_some()
+    }
+    fn set_eq_preset(&mut self, preset: EqPreset) {
+        self.audio_service
+            .equalizer()
+            .set_preset(self.eq_target().as_deref(), preset);
+        self.save_eq();
+    }
+    pub fn adjust_eq_band(&mut self, delta: f32) {
+        let mut preset = self.eq_preset();
+        let gain = &mut preset.gains[self.eq_band];
+        *gain = (*gain + delta).clamp(-MAX_BAND_GAIN_DB, MAX_BAND_GAIN_DB);
+        preset.name = "custom".to_string();
+        self.set_eq_preset(preset);
+    }
+    pub fn next_eq_preset(&mut self) {
+        let presets: Vec<EqPreset> = EqPreset::built_in()
+            .into_iter()
+            .chain(self.config.eq_presets.iter().cloned())
+            .collect();
+        let name = self.eq_preset().name;
+        let next = presets
+            .iter()
+            .position(|p| p.name == name)
+            .map_or(0, |i| (i + 1) % presets.len());
+        self.set_eq_preset(presets[next].clone());
+    }
+    /// Gives the current track its own preset, starting from the global
+    /// one, or drops it again.
+    pub fn toggle_eq_override(&mut self) {
+        let Some(path) = self.audio_service.current_audio.clone() else {
+            self.notify("Nothing is playing");
+            return;
+        };
+        let equalizer = self.audio_service.equalizer().clone();
+        if equalizer.has_override(&path) {
+            equalizer.set_override(&path, None);
+            self.notify("Equalizer: track uses the global preset");
+        } else {
+            equalizer.set_override(&path, Some(equalizer.global()));
+            self.notify("Equalizer: track has its own preset");
+        }
+        self.save_eq();
+    }
+    pub fn save_eq_preset_as(&mut self, name: &str) {
+        let name = name.trim();
+        if name.is_empty() || EqPreset::built_in().iter().any(|p| p.name == name) {
+            self.notify(format!("Invalid preset name: {}", name));
+            return;
+        }
+        let mut preset = self.eq_preset();
+        preset.name = name.to_string();
+        self.config.eq_presets.retain(|p| p.name != name);
+        self.config.eq_presets.push(preset.clone());
+        self.set_eq_preset(preset);
+    }
+    fn save_eq(&mut self) {
+        let equalizer = self.audio_service.equalizer();
+        self.config.equalizer = equalizer.global();
+        self.config.eq_overrides = equalizer.overrides();
+        if let Err(e) = self.config.save() {
+            self.notify(format!("Saving config failed: {}", e));
+        }
+    }
+    pub fn open_device_picker(&mut self) {
+        self.devices = self.audio_service.output_devices();
+        let current = self.audio_service.output_device();
+        let selected = self
+            .devices
+            .iter()
+            .position(|d| Some(d.as_str()) == current);
+        self.device_state.select(selected.or(Some(0)));
+        self.focus = Focus::Devices;
+    }
+    pub fn next_device(&mut self) {
+        if !self.devices.is_empty() {
+            let i = self.device_state.selected().map_or(0, |i| i + 1);
+            self.device_state.select(Some(i % self.devices.len()));
+        }
+    }
+    pub fn prev_device(&mut self) {
+        if !self.devices.is_empty() {
+            let i = self.device_state.selected().unwrap_or(0);
+            self.device_state
+                .select(Some(i.checked_sub(1).unwrap_or(self.devices.len() - 1)));
+        }
+    }
+    pub fn choose_device(&mut self) {
+        self.focus = Focus::FolderList;
+        let Some(name) = self
+            .device_state
+            .selected()
+            .and_then(|i| self.devices.get(i).cloned())
+        else {
+            return;
+        };
+        if self.audio_service.set_output_device(Some(name.clone())) {
+            self.notify(format!("Output: {}", name));
+        } else {
+            self.notify(format!("Can't open {}, using the default device", name));
+        }
+        self.config.output_device = Some(name);
+        if let Err(e) = self.config.save() {
+            self.notify(format!("Saving config failed: {}", e));
+        }
+    }
+    pub fn mark_ab(&mut self) {
+        self.audio_service.mark_ab();
+        let message = match (self.audio_service.ab_loop(), self.audio_service.ab_start()) {
+            (Some(region), _) => format!(
+                "Looping {}-{}",
+                formart_duration(region.a),
+                formart_duration(region.b)
+            ),
+            (None, Some(a)) => format!("A at {}", formart_duration(a)),
+            (None, None) => "A-B loop off".to_string(),
+        };
+        self.notify(message);
+    }
+    pub fn save_ab_section(&mut self) {
+        match self.audio_service.save_ab_section() {
+            Ok(true) => self.notify("A-B loop saved"),
+            Ok(false) => self.notify("No A-B loop to save"),
+            Err(e) => self.notify(format!("Saving A-B loop failed: {}", e)),
+        }
+    }
+    pub fn next_saved_section(&mut self) {
+        match self.audio_service.next_saved_section() {
+            Ok(Some(section)) => self.notify(format!(
+                "Looping {}-{}",
+                formart_duration(section.a()),
+                formart_duration(section.b())
+            )),
+            Ok(None) => self.notify("No saved A-B loops for this track"),
+            Err(e) => self.notify(format!("Seek failed: {}", e)),
+        }
+    }
+    /// Applies what was typed into the sleep timer prompt.
+    pub fn set_sleep_timer(&mut self, input: &str) {
+        let input = input.trim();
+        if input.is_empty() || input == "off" {
+            self.audio_service.set_sleep_timer(None);
+            self.notify("Sleep timer off");
+            return;
+        }
+        match SleepTimer::parse(input) {
+            Some(timer) => self.audio_service.set_sleep_timer(Some(timer)),
+            None => self.notify(format!("Invalid sleep timer: {}", input)),
         }
     }
     pub fn toggle_mute(&mut self) {
@@ -171,43 +767,122 @@ impl App<'_> {
         self.last_toggle_volume = Instant::now();
     }
     pub fn audio_tick(&mut self) {
-        if self.audio_service.audio_event == AudioEvent::Play {
-            self.donut.tick();
-        }
-        match self.loop_mode {
-            LoopMode::Single => {
-                self.audio_service.single_mode();
+        match self.main_view {
+            // Without artwork the donut spins in its place.
+            MainView::Donut | MainView::Artwork
+                if self.audio_service.audio_event == AudioEvent::Play
+                    && (self.main_view == MainView::Donut || !self.artwork.has_image()) =>
+            {
+                self.donut.tick();
             }
-            LoopMode::Playlist | LoopMode::Shuffle => {
-                let updated_idx = self.audio_service.playlist_mode();
-                if let Some(idx) = updated_idx {
-                    let _ = self.tx.send(SignalMessage::UpdateIndex(idx));
-                }
+            MainView::Spectrum => {
+                let samples = if self.audio_service.audio_event == AudioEvent::Play {
+                    self.audio_service.tap().latest(FFT_SIZE)
+                } else {
+                    vec![0.0; FFT_SIZE]
+                };
+                self.spectrum.update(&samples, SAMPLE_RATE);
             }
+            _ => {}
+        }
+        if self.audio_service.tick_sleep_timer() {
+            self.notify("Sleep timer: paused");
+        }
+        match self.audio_service.check_output() {
+            Some(Some(device)) => self.notify(format!("Output lost, switched to {}", device)),
+            Some(None) => self.notify("No output device"),
+            None => {}
+        }
+        if let Some(idx) = self.audio_service.playlist_mode() {
+            let _ = self.tx.send(SignalMessage::UpdateIndex(idx));
+        }
+        if self.loop_mode != self.audio_service.loop_mode() {
+            self.loop_mode = self.audio_service.loop_mode();
+            self.notify(self.loop_mode.name());
+        }
+        self.update_index();
+        if self.show_waveform && self.audio_service.current_audio != self.waveform_path {
+            self.load_waveform();
+        }
+        if self.show_lyrics && self.audio_service.current_audio != self.lyrics_path {
+            self.load_lyrics();
+        }
+        if self.main_view == MainView::Artwork
+            && self.audio_service.current_audio.as_ref() != self.artwork.path()
+        {
+            self.load_artwork();
         }
     }
 }
 
-#[derive(Debug, Copy, Clone, PartialEq)]
+#[derive(Debug, Copy, Clone, PartialEq, Serialize, Deserialize)]
 pub enum LoopMode {
     Single,
     Playlist,
     Shuffle,
+    /// Plays to the end of the queue, then stops.
+    ListOnce,
+    /// Plays the current track once, then stops on it.
+    TrackOnce,
+    /// Stops once the current track ends, ready to play the next one, and
+    /// goes back to the mode the track was playing in.
+    StopAfterCurrent,
+    /// Plays each track this many times, then moves on.
+    Repeat(u32),
 }
 
 impl LoopMode {
     pub fn next(&self) -> Self {
         match self {
-            Self::Single => Self::Playlist,
+            Self::Single => Self::Repeat(2),
+            Self::Repeat(2) => Self::Repeat(3),
+            Self::Repeat(_) => Self::Playlist,
             Self::Playlist => Self::Shuffle,
-            Self::Shuffle => Self::Single,
+            Self::Shuffle => Self::ListOnce,
+            Self::ListOnce => Self::TrackOnce,
+            Self::TrackOnce => Self::StopAfterCurrent,
+            Self::StopAfterCurrent => Self::Single,
         }
     }
-    pub fn text(&self) -> &'static str {
+    pub fn text(&self) -> String {
         match self {
-            Self::Single => "🔂",
-            Self::Playlist => "🔁",
-            Self::Shuffle => "🔀",
+            Self::Single => "🔂".to_string(),
+            Self::Playlist => "🔁".to_string(),
+            Self::Shuffle => "🔀".to_string(),
+            Self::ListOnce => "📃".to_string(),
+            Self::TrackOnce => "1️⃣".to_string(),
+            Self::StopAfterCurrent => "⏹️".to_string(),
+            Self::Repeat(n) => format!("🔂×{}", n),
+        }
+    }
+    pub fn name(&self) -> String {
+        match self {
+            Self::Single => "Repeat track".to_string(),
+            Self::Playlist => "Repeat queue".to_string(),
+            Self::Shuffle => "Shuffle".to_string(),
+            Self::ListOnce => "Play queue once".to_string(),
+            Self::TrackOnce => "Play track once".to_string(),
+            Self::StopAfterCurrent => "Stop after current track".to_string(),
+            Self::Repeat(n) => format!("Play each track {} times", n),
+        }
+    }
+}
+
+/// What fills the area above the progress bar.
+#[derive(Debug, Copy, Clone, PartialEq)]
+pub enum MainView {
+    Donut,
+    Spectrum,
+    /// Cover art or video thumbnail, the donut when there is none.
+    Artwork,
+}
+
+impl MainView {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Donut => Self::Spectrum,
+            Self::Spectrum => Self::Artwork,
+            Self::Artwork => Self::Donut,
         }
     }
 }
@@ -228,9 +903,6 @@ impl PartialEq for Volume {
                 | (Volume::Normal, Volume::Normal)
         )
     }
-    fn ne(&self, other: &Self) -> bool {
-        !self.eq(other)
-    }
 }
 impl Volume {
     pub fn up(&self) -> Self {
@@ -279,41 +951,9 @@ impl PartialEq for MuteSound {
 pub enum SignalMessage {
     Downloaded,
     UpdateIndex(usize),
-}
-
-pub struct Signal<I: Iterator> {
-    source: I,
-    pub points: Vec<I::Item>,
-    tick_rate: usize,
-}
-impl<I> Signal<I>
-where
-    I: Iterator,
-{
-    fn on_tick(&mut self) {
-        self.points.drain(0..self.tick_rate);
-        self.points
-            .extend(self.source.by_ref().take(self.tick_rate));
-    }
-}
-
-#[derive(Clone)]
-pub struct RandomSignal {
-    distribution: rand::distr::Uniform<u64>,
-    rng: rand::rngs::ThreadRng,
-}
-impl RandomSignal {
-    pub fn new(lower: u64, upper: u64) -> Self {
-        Self {
-            distribution: rand::distr::Uniform::new(lower, upper).expect("invalid range"),
-            rng: rand::rng(),
-        }
-    }
-}
-impl Iterator for RandomSignal {
-    type Item = u64;
-    fn next(&mut self) -> Option<u64> {
-        use rand::distr::Distribution;
-        Some(self.distribution.sample(&mut self.rng))
-    }
+    Waveform(String, WaveFormData),
+    Artwork(String, Option<Image>),
+    Lyrics(String, Option<Lyrics>),
+    Library(Vec<LibraryChange>),
+    Scanned(u64, Refresh),
 }
@@ -0,0 +1,61 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::config::data_dir;
+
+/// A-B section saved for practicing, in seconds from the start of the file.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+pub struct AbSection {
+    pub a: f64,
+    pub b: f64,
+}
+
+impl AbSection {
+    pub fn new(a: Duration, b: Duration) -> Self {
+        Self {
+            a: a.as_secs_f64(),
+            b: b.as_secs_f64(),
+        }
+    }
+    pub fn a(&self) -> Duration {
+        Duration::from_secs_f64(self.a.max(0.0))
+    }
+    pub fn b(&self) -> Duration {
+        Duration::from_secs_f64(self.b.max(0.0))
+    }
+}
+
+/// Saved sections of every file, kept in `~/.audyo_plaier/ab_loops.json`.
+#[derive(Debug, Default, Serialize, Deserialize)]
+pub struct AbSections {
+    sections: HashMap<String, Vec<AbSection>>,
+}
+
+impl AbSections {
+    fn path() -> PathBuf {
+        data_dir().join("ab_loops.json")
+    }
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|s| serde_json::from_str(&s).ok())
+            .unwrap_or_default()
+    }
+    fn save(&self) -> io::Result<()> {
+        fs::create_dir_all(data_dir())?;
+        fs::write(Self::path(), serde_json::to_string_pretty(self)?)
+    }
+    pub fn get(&self, path: &str) -> &[AbSection] {
+        self.sections.get(path).map_or(&[], Vec::as_slice)
+    }
+    /// Remembers `section` for `path`, unless it is already saved.
+    pub fn add(&mut self, path: &str, section: AbSection) -> io::Result<()> {
+        let sections = self.sections.entry(path.to_string()).or_default();
+        if !sections.contains(&section) {
+            sections.push(section);
+            sections.sort_by(|x, y| x.a.total_cmp(&y.a));
+        }
+        self.save()
+    }
+}
@@ -0,0 +1,49 @@
+use rodio::Source;
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audyo::{null_output::NullBackend, output::RodioBackend},
+    config::Config,
+};
+
+pub type PipelineSource = Box<dyn Source<Item = f32> + Send>;
+
+/// Whatever consumes the mixed audio: a sound card, or nothing at all.
+pub trait AudioBackend {
+    /// Starts pulling audio from `source`. A backend plays a single source
+    /// for its whole life.
+    fn start(&mut self, source: PipelineSource);
+    fn play(&self);
+    fn pause(&self);
+    fn is_paused(&self) -> bool;
+    fn volume(&self) -> f32;
+    fn set_volume(&self, volume: f32);
+    /// Stops pulling audio for good.
+    fn stop(&self);
+    /// Name of the device played on, `None` when not a sound card.
+    fn device(&self) -> Option<&str>;
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendKind {
+    #[default]
+    Rodio,
+    /// No sound card, see `NullBackend`.
+    Null,
+}
+
+/// Opens the backend chosen in `config`, playing on `device` if it is a
+/// sound card. Without any usable device playback goes on silently.
+pub fn open_backend(config: &Config, device: Option<&str>) -> Box<dyn AudioBackend> {
+    match config.backend {
+        BackendKind::Rodio => match RodioBackend::open(device) {
+            Some(rodio) => Box::new(rodio),
+            None => Box::new(NullBackend::new(1.0, None)),
+        },
+        BackendKind::Null => Box::new(NullBackend::new(
+            config.null_clock,
+            config.render_wav.clone(),
+        )),
+    }
+}
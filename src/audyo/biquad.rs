@@ -0,0 +1,57 @@
+use std::f64::consts::PI;
+
+/// Second-order IIR section, direct form I.
+#[derive(Clone)]
+pub struct Biquad {
+    b: [f64; 3],
+    a: [f64; 2],
+    x: [f64; 2],
+    y: [f64; 2],
+}
+
+impl Biquad {
+    /// Coefficients normalized so `a0` is 1.
+    pub fn new(b: [f64; 3], a: [f64; 2]) -> Self {
+        Self {
+            b,
+            a,
+            x: [0.0; 2],
+            y: [0.0; 2],
+        }
+    }
+    /// Peaking filter boosting or cutting `gain_db` around `frequency`
+    /// (RBJ audio EQ cookbook).
+    pub fn peaking(sample_rate: f64, frequency: f64, gain_db: f64, q: f64) -> Self {
+        let a = 10f64.powf(gain_db / 40.0);
+        let w0 = 2.0 * PI * frequency / sample_rate;
+        let alpha = w0.sin() / (2.0 * q);
+        let cos = w0.cos();
+        let a0 = 1.0 + alpha / a;
+        Self::new(
+            [
+                (1.0 + alpha * a) / a0,
+                -2.0 * cos / a0,
+                (1.0 - alpha * a) / a0,
+            ],
+            [-2.0 * cos / a0, (1.0 - alpha / a) / a0],
+        )
+    }
+    /// Takes over `other`'s coefficients but keeps the filter's history, so
+    /// the response can change mid-stream without a click.
+    pub fn retune(&mut self, other: &Biquad) {
+        self.b = other.b;
+        self.a = other.a;
+    }
+    pub fn reset(&mut self) {
+        self.x = [0.0; 2];
+        self.y = [0.0; 2];
+    }
+    pub fn process(&mut self, x: f64) -> f64 {
+        let y = self.b[0] * x + self.b[1] * self.x[0] + self.b[2] * self.x[1]
+            - self.a[0] * self.y[0]
+            - self.a[1] * self.y[1];
+        self.x = [x, self.x[0]];
+        self.y = [y, self.y[0]];
+        y
+    }
+}
@@ -0,0 +1,95 @@
+use std::{fs, path::PathBuf, time::UNIX_EPOCH};
+
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::config::data_dir;
+
+/// Identifies the current contents of `path` by its location, size and
+/// modification time, so a cached result is dropped once the file changes.
+pub fn file_key(path: &str) -> Option<String> {
+    let meta = fs::metadata(path).ok()?;
+    let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+    let mut hash = Fnv::default();
+    hash.write(path.as_bytes());
+    hash.write(&meta.len().to_le_bytes());
+    hash.write(&mtime.as_secs().to_le_bytes());
+    hash.write(&mtime.subsec_nanos().to_le_bytes());
+    Some(format!("{:016x}", hash.0))
+}
+
+/// 64-bit FNV-1a. Unlike `DefaultHasher` it gives the same keys across Rust
+/// versions, so the cache survives a rebuild.
+struct Fnv(u64);
+
+impl Default for Fnv {
+    fn default() -> Self {
+        Self(0xcbf2_9ce4_8422_2325)
+    }
+}
+
+impl Fnv {
+    fn write(&mut self, bytes: &[u8]) {
+        for &b in bytes {
+            self.0 = (self.0 ^ b as u64).wrapping_mul(0x0100_0000_01b3);
+        }
+    }
+}
+
+fn entry_path(kind: &str, key: &str) -> PathBuf {
+    data_dir()
+        .join("cache")
+        .join(kind)
+        .join(format!("{}.json", key))
+}
+
+pub fn load<T: DeserializeOwned>(kind: &str, key: &str) -> Option<T> {
+    let content = fs::read_to_string(entry_path(kind, key)).ok()?;
+    serde_json::from_str(&content).ok()
+}
+
+/// Best effort: a cache that can't be written only costs a recomputation.
+pub fn store<T: Serialize>(kind: &str, key: &str, value: &T) {
+    let path = entry_path(kind, key);
+    if let Some(parent) = path.parent()
+        && fs::create_dir_all(parent).is_err()
+    {
+        return;
+    }
+    if let Ok(content) = serde_json::to_string(value) {
+        let _ = fs::write(path, content);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn fnv(bytes: &[u8]) -> u64 {
+        let mut hash = Fnv::default();
+        hash.write(bytes);
+        hash.0
+    }
+
+    #[test]
+    fn fnv_matches_the_reference_values() {
+        assert_eq!(fnv(b""), 0xcbf2_9ce4_8422_2325);
+        assert_eq!(fnv(b"a"), 0xaf63_dc4c_8601_ec8c);
+        assert_eq!(fnv(b"foobar"), 0x8594_4171_f739_67e8);
+    }
+
+    #[test]
+    fn keys_change_with_the_file() {
+        let path = std::env::temp_dir().join(format!("audyo-cache-key-{}", std::process::id()));
+        let path_str = path.to_str().unwrap();
+        fs::write(&path, b"one").unwrap();
+        let first = file_key(path_str);
+        let again = file_key(path_str);
+        fs::write(&path, b"longer").unwrap();
+        let changed = file_key(path_str);
+        let _ = fs::remove_file(&path);
+        assert!(first.is_some());
+        assert_eq!(first, again);
+        assert_ne!(first, changed);
+        assert_eq!(file_key(path_str), None);
+    }
+}
@@ -0,0 +1,360 @@
+use std::{
+    collections::HashMap,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Duration,
+};
+
+use rodio::{Sample, Source, source::SeekError};
+use serde::{Deserialize, Serialize};
+
+use crate::audyo::biquad::Biquad;
+
+/// Centre frequencies of the graphic bands, in Hz.
+pub const BAND_FREQUENCIES: [f32; BANDS] = [
+    31.0, 62.0, 125.0, 250.0, 500.0, 1000.0, 2000.0, 4000.0, 8000.0, 16000.0,
+];
+pub const BANDS: usize = 10;
+pub const MAX_BAND_GAIN_DB: f32 = 12.0;
+/// One octave wide, so neighbouring graphic bands blend into a smooth curve.
+const GRAPHIC_Q: f32 = 1.41;
+
+/// Extra peaking band at an arbitrary frequency.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ParametricBand {
+    pub frequency: f32,
+    pub gain_db: f32,
+    pub q: f32,
+}
+
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct EqPreset {
+    pub name: String,
+    /// Gain of each of the `BAND_FREQUENCIES`, in dB.
+    pub gains: [f32; BANDS],
+    pub parametric: Vec<ParametricBand>,
+}
+
+impl Default for EqPreset {
+    fn default() -> Self {
+        Self::new("flat", [0.0; BANDS])
+    }
+}
+
+impl EqPreset {
+    fn new(name: &str, gains: [f32; BANDS]) -> Self {
+        Self {
+            name: name.to_string(),
+            gains,
+            parametric: Vec::new(),
+        }
+    }
+    pub fn built_in() -> Vec<Self> {
+        vec![
+            Self::default(),
+            Self::new(
+                "bass boost",
+                [6.0, 5.0, 4.0, 2.0, 0.0, 0.0, 0.0, 0.0, 0.0, 0.0],
+            ),
+            Self::new(
+                "vocal",
+                [-3.0, -2.0, -1.0, 0.0, 2.0, 3.0, 3.0, 2.0, 0.0, -1.0],
+            ),
+            Self::new(
+                "podcast",
+                [-8.0, -5.0, -2.0, 0.0, 1.0, 2.0, 3.0, 2.0, 0.0, -3.0],
+            ),
+        ]
+    }
+    /// Frequency, gain and Q of every band that changes the sound.
+    fn bands(&self) -> impl Iterator<Item = (f32, f32, f32)> + '_ {
+        BAND_FREQUENCIES
+            .iter()
+            .zip(self.gains)
+            .map(|(&f, g)| (f, g, GRAPHIC_Q))
+            .chain(
+                self.parametric
+                    .iter()
+                    .map(|b| (b.frequency, b.gain_db, b.q)),
+            )
+            .filter(|&(_, gain, _)| gain != 0.0)
+    }
+    /// Cut in front of the bands by the largest boost, so boosted
+    /// frequencies have headroom instead of clipping.
+    pub fn preamp_db(&self) -> f32 {
+        -self.bands().map(|(_, gain, _)| gain).fold(0.0, f32::max)
+    }
+}
+
+struct EqState {
+    global: EqPreset,
+    overrides: HashMap<String, EqPreset>,
+}
+
+/// Shared equalizer settings. Every playing track reads them through its
+/// `Equalized` source, so edits are heard immediately.
+#[derive(Clone)]
+pub struct Equalizer {
+    state: Arc<Mutex<EqState>>,
+    version: Arc<AtomicU64>,
+}
+
+impl Equalizer {
+    pub fn new(global: EqPreset, overrides: HashMap<String, EqPreset>) -> Self {
+        Self {
+            state: Arc::new(Mutex::new(EqState { global, overrides })),
+            version: Arc::new(AtomicU64::new(0)),
+        }
+    }
+    /// The preset `path` plays with: its override, or the global one.
+    pub fn preset_for(&self, path: Option<&str>) -> EqPreset {
+        let state = self.state.lock().unwrap();
+        path.and_then(|p| state.overrides.get(p))
+            .unwrap_or(&state.global)
+            .clone()
+    }
+    pub fn has_override(&self, path: &str) -> bool {
+        self.state.lock().unwrap().overrides.contains_key(path)
+    }
+    /// Replaces the preset of `path`, or the global one with `None`. A track
+    /// without an override stays on the global preset.
+    pub fn set_preset(&self, path: Option<&str>, preset: EqPreset) {
+        let mut state = self.state.lock().unwrap();
+        match path {
+            Some(path) if state.overrides.contains_key(path) => {
+                state.overrides.insert(path.to_string(), preset);
+            }
+            _ => state.global = preset,
+        }
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn set_override(&self, path: &str, preset: Option<EqPreset>) {
+        let mut state = self.state.lock().unwrap();
+        match preset {
+            Some(preset) => state.overrides.insert(path.to_string(), preset),
+            None => state.overrides.remove(path),
+        };
+        self.version.fetch_add(1, Ordering::Relaxed);
+    }
+    pub fn global(&self) -> EqPreset {
+        self.state.lock().unwrap().global.clone()
+    }
+    pub fn overrides(&self) -> HashMap<String, EqPreset> {
+        self.state.lock().unwrap().overrides.clone()
+    }
+    pub fn apply<S>(&self, path: &str, input: S) -> Equalized<S>
+    where
+        S: Source,
+        S::Item: Sample,
+    {
+        let channels = input.channels().max(1) as usize;
+        let mut source = Equalized {
+            input,
+            equalizer: self.clone(),
+            path: path.to_string(),
+            version: u64::MAX,
+            channels,
+            channel: 0,
+            filters: vec![Vec::new(); channels],
+            preamp: 1.0,
+        };
+        source.update_filters();
+        source
+    }
+}
+
+/// Runs a track through the equalizer bands of its preset.
+pub struct Equalized<S> {
+    input: S,
+    equalizer: Equalizer,
+    path: String,
+    version: u64,
+    channels: usize,
+    channel: usize,
+    /// One chain of band filters per channel.
+    filters: Vec<Vec<Biquad>>,
+    /// Linear gain of the preset's preamp.
+    preamp: f64,
+}
+
+impl<S> Equalized<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn update_filters(&mut self) {
+        self.version = self.equalizer.version.load(Ordering::Relaxed);
+        let preset = self.equalizer.preset_for(Some(&self.path));
+        let sample_rate = self.input.sample_rate() as f64;
+        self.preamp = 10f64.powf(preset.preamp_db() as f64 / 20.0);
+        let chain: Vec<Biquad> = preset
+            .bands()
+            .filter(|&(f, _, _)| (f as f64) < sample_rate / 2.0)
+            .map(|(f, gain, q)| Biquad::peaking(sample_rate, f as f64, gain as f64, q as f64))
+            .collect();
+        for filters in &mut self.filters {
+            if filters.len() == chain.len() {
+                for (filter, tuned) in filters.iter_mut().zip(&chain) {
+                    filter.retune(tuned);
+                }
+            } else {
+                *filters = chain.clone();
+            }
+        }
+    }
+}
+
+impl<S> Iterator for Equalized<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?.to_f32();
+        if self.channel == 0 && self.equalizer.version.load(Ordering::Relaxed) != self.version {
+            self.update_filters();
+        }
+        let filters = &mut self.filters[self.channel];
+        self.channel = (self.channel + 1) % self.channels;
+        if filters.is_empty() {
+            return Some(sample);
+        }
+        let out = filters
+            .iter_mut()
+            .fold(sample as f64 * self.preamp, |x, filter| filter.process(x));
+        Some(out as f32)
+    }
+}
+
+impl<S> Source for Equalized<S>
+where
+    S: Source,
+    S::Item: Sample,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+    fn try_seek(&mut self, pos: Duration) -> Result<(), SeekError> {
+        self.input.try_seek(pos)?;
+        self.channel = 0;
+        for filter in self.filters.iter_mut().flatten() {
+            filter.reset();
+        }
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    const RATE: u32 = 44_100;
+
+    fn sine(hz: f32) -> Vec<f32> {
+        (0..RATE as usize / 2)
+            .map(|i| 0.25 * (2.0 * std::f32::consts::PI * hz * i as f32 / RATE as f32).sin())
+            .collect()
+    }
+
+    fn run(equalizer: &Equalizer, samples: Vec<f32>) -> Vec<f32> {
+        equalizer
+            .apply("a.mp3", SamplesBuffer::new(1, RATE, samples))
+            .collect()
+    }
+
+    /// Level of the second half of `samples`, after filters settle, in dB.
+    fn level_db(samples: &[f32]) -> f32 {
+        let tail = &samples[samples.len() / 2..];
+        let rms = (tail.iter().map(|s| s * s).sum::<f32>() / tail.len() as f32).sqrt();
+        20.0 * rms.log10()
+    }
+
+    fn preset(band: usize, gain_db: f32) -> EqPreset {
+        let mut gains = [0.0; BANDS];
+        gains[band] = gain_db;
+        EqPreset::new("test", gains)
+    }
+
+    #[test]
+    fn flat_preset_passes_samples_through() {
+        let equalizer = Equalizer::new(EqPreset::default(), HashMap::new());
+        let input = sine(440.0);
+        assert_eq!(run(&equalizer, input.clone()), input);
+    }
+
+    #[test]
+    fn preamp_makes_room_for_the_largest_boost() {
+        assert_eq!(EqPreset::default().preamp_db(), 0.0);
+        assert_eq!(preset(3, -6.0).preamp_db(), 0.0);
+        let mut boosted = preset(3, 4.0);
+        boosted.parametric.push(ParametricBand {
+            frequency: 3000.0,
+            gain_db: 9.0,
+            q: 2.0,
+        });
+        assert_eq!(boosted.preamp_db(), -9.0);
+    }
+
+    #[test]
+    fn boosted_band_is_levelled_by_the_preamp() {
+        // +12 dB at 1 kHz with the preamp 12 dB down: 1 kHz keeps its
+        // level and frequencies far from the band drop by the preamp.
+        let equalizer = Equalizer::new(preset(5, 12.0), HashMap::new());
+        let reference = level_db(&sine(1000.0));
+        let at_band = level_db(&run(&equalizer, sine(1000.0))) - reference;
+        let far = level_db(&run(&equalizer, sine(10_000.0))) - reference;
+        assert!(at_band.abs() < 0.2, "{at_band} dB at the band");
+        assert!((far + 12.0).abs() < 0.5, "{far} dB far from it");
+    }
+
+    #[test]
+    fn cut_band_attenuates_its_frequency() {
+        let equalizer = Equalizer::new(preset(4, -12.0), HashMap::new());
+        let change = level_db(&run(&equalizer, sine(500.0))) - level_db(&sine(500.0));
+        assert!((change + 12.0).abs() < 0.2, "{change} dB");
+    }
+
+    #[test]
+    fn overrides_take_the_place_of_the_global_preset() {
+        let equalizer = Equalizer::new(EqPreset::default(), HashMap::new());
+        equalizer.set_override("a.mp3", Some(preset(5, -12.0)));
+        // Without an override the global preset changes.
+        equalizer.set_preset(Some("b.mp3"), preset(0, 3.0));
+        assert_eq!(equalizer.preset_for(Some("b.mp3")), preset(0, 3.0));
+        assert_eq!(equalizer.preset_for(Some("a.mp3")), preset(5, -12.0));
+        equalizer.set_preset(Some("a.mp3"), preset(5, -6.0));
+        assert_eq!(equalizer.global(), preset(0, 3.0));
+        assert_eq!(equalizer.preset_for(Some("a.mp3")), preset(5, -6.0));
+        equalizer.set_override("a.mp3", None);
+        assert!(!equalizer.has_override("a.mp3"));
+    }
+
+    #[test]
+    fn playing_sources_hear_edits() {
+        let equalizer = Equalizer::new(EqPreset::default(), HashMap::new());
+        let input = sine(1000.0);
+        let mut source = equalizer.apply("a.mp3", SamplesBuffer::new(1, RATE, input.clone()));
+        let before: Vec<f32> = source.by_ref().take(input.len() / 2).collect();
+        assert_eq!(before, input[..input.len() / 2]);
+        equalizer.set_preset(None, preset(5, -12.0));
+        let after: Vec<f32> = source.collect();
+        let change = level_db(&after) - level_db(&input);
+        assert!((change + 12.0).abs() < 0.3, "{change} dB");
+    }
+}
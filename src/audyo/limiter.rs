@@ -0,0 +1,105 @@
+use std::{
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+
+use rodio::Source;
+
+/// Time for the gain to recover after a peak.
+const RELEASE: Duration = Duration::from_millis(150);
+
+/// Peak limiter keeping normalized audio from clipping. Gain drops the
+/// moment a frame would exceed `threshold` and recovers smoothly afterwards.
+/// Channels share one gain so the stereo image doesn't shift.
+pub struct Limiter<S> {
+    input: S,
+    enabled: Arc<AtomicBool>,
+    threshold: f32,
+    release: f32,
+    gain: f32,
+    frame: Vec<f32>,
+    pos: usize,
+}
+
+impl<S> Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, threshold_db: f32, enabled: Arc<AtomicBool>) -> Self {
+        let channels = input.channels().max(1) as usize;
+        let release_frames = RELEASE.as_secs_f32() * input.sample_rate() as f32;
+        Self {
+            input,
+            enabled,
+            threshold: 10f32.powf(threshold_db / 20.0),
+            release: 1.0 - (-1.0 / release_frames).exp(),
+            gain: 1.0,
+            frame: Vec::with_capacity(channels),
+            pos: 0,
+        }
+    }
+
+    fn read_frame(&mut self) -> bool {
+        self.frame.clear();
+        self.pos = 0;
+        for _ in 0..self.input.channels().max(1) {
+            match self.input.next() {
+                Some(s) => self.frame.push(s),
+                None => break,
+            }
+        }
+        if self.frame.is_empty() {
+            return false;
+        }
+        if !self.enabled.load(Ordering::Relaxed) {
+            self.gain = 1.0;
+            return true;
+        }
+        self.gain += (1.0 - self.gain) * self.release;
+        let peak = self.frame.iter().fold(0.0, |p: f32, s| p.max(s.abs()));
+        if peak * self.gain > self.threshold {
+            self.gain = self.threshold / peak;
+        }
+        for s in &mut self.frame {
+            *s *= self.gain;
+        }
+        true
+    }
+}
+
+impl<S> Iterator for Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pos >= self.frame.len() && !self.read_frame() {
+            return None;
+        }
+        let sample = self.frame[self.pos];
+        self.pos += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for Limiter<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
@@ -0,0 +1,379 @@
+use std::{
+    collections::{HashSet, VecDeque},
+    f64::consts::PI,
+    fs::File,
+    io::BufReader,
+    sync::{Arc, Condvar, Mutex, mpsc},
+    thread,
+};
+
+use rodio::{Decoder, Source};
+use serde::{Deserialize, Serialize};
+
+use crate::audyo::{biquad::Biquad, cache, metadata::read_album};
+
+/// Blocks quieter than this are silence and never count (EBU R128 absolute gate).
+const ABSOLUTE_GATE: f64 = -70.0;
+/// Blocks more than this below the ungated loudness are ignored.
+const RELATIVE_GATE: f64 = -10.0;
+/// Histogram resolution in LU; block loudness is kept from -70 to +5 LUFS.
+const BIN_WIDTH: f64 = 0.1;
+const BINS: usize = 750;
+
+/// How per-track gain is chosen.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Normalization {
+    #[default]
+    Off,
+    /// Every track is brought to the target loudness.
+    Track,
+    /// Tracks keep their level relative to the rest of their album.
+    Album,
+}
+
+impl Normalization {
+    pub fn next(&self) -> Self {
+        match self {
+            Self::Off => Self::Track,
+            Self::Track => Self::Album,
+            Self::Album => Self::Off,
+        }
+    }
+    pub fn text(&self) -> &'static str {
+        match self {
+            Self::Off => "off",
+            Self::Track => "track",
+            Self::Album => "album",
+        }
+    }
+}
+
+/// Result of an EBU R128 scan of one file.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Loudness {
+    /// Integrated loudness in LUFS.
+    pub integrated: f64,
+    /// Number of gating blocks per loudness bin, so several tracks can be
+    /// gated together to get an album's loudness.
+    histogram: Vec<u32>,
+}
+
+impl Loudness {
+    const CACHE: &'static str = "loudness";
+
+    /// Reads the scan from the on-disk cache, decoding the whole file on a miss.
+    pub fn load(path: &str) -> Option<Self> {
+        let key = cache::file_key(path);
+        if let Some(key) = &key
+            && let Some(loudness) = cache::load(Self::CACHE, key)
+        {
+            return Some(loudness);
+        }
+        let loudness = Self::scan(path)?;
+        if let Some(key) = &key {
+            cache::store(Self::CACHE, key, &loudness);
+        }
+        Some(loudness)
+    }
+
+    fn scan(path: &str) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let source = Decoder::new(BufReader::new(file)).ok()?;
+        let channels = source.channels().max(1) as usize;
+        let sample_rate = source.sample_rate() as f64;
+        // Mean square is collected per 100ms; a gating block is four of them.
+        let step = (sample_rate / 10.0).round() as usize;
+
+        let mut filters = vec![KWeighting::new(sample_rate); channels];
+        let mut histogram = vec![0u32; BINS];
+        let mut steps: VecDeque<f64> = VecDeque::with_capacity(4);
+        let (mut sum, mut frames, mut channel) = (0.0f64, 0, 0);
+        for sample in source {
+            let s = sample as f32 / i16::MAX as f32;
+            let y = filters[channel].process(s as f64);
+            sum += y * y;
+            channel += 1;
+            if channel < channels {
+                continue;
+            }
+            channel = 0;
+            frames += 1;
+            if frames < step {
+                continue;
+            }
+            if steps.len() == 4 {
+                steps.pop_front();
+            }
+            steps.push_back(sum / step as f64);
+            if steps.len() == 4 {
+                let energy = steps.iter().sum::<f64>() / 4.0;
+                if let Some(bin) = bin_of(energy) {
+                    histogram[bin] += 1;
+                }
+            }
+            sum = 0.0;
+            frames = 0;
+        }
+        Some(Self {
+            integrated: integrated(&histogram)?,
+            histogram,
+        })
+    }
+
+    /// Loudness of several tracks played as one programme.
+    pub fn combined<'a>(tracks: impl Iterator<Item = &'a Loudness>) -> Option<f64> {
+        let mut histogram = vec![0u32; BINS];
+        for track in tracks {
+            for (total, n) in histogram.iter_mut().zip(&track.histogram) {
+                *total += n;
+            }
+        }
+        integrated(&histogram)
+    }
+}
+
+fn energy_to_lufs(energy: f64) -> f64 {
+    -0.691 + 10.0 * energy.log10()
+}
+
+fn lufs_to_energy(lufs: f64) -> f64 {
+    10f64.powf((lufs + 0.691) / 10.0)
+}
+
+fn bin_of(energy: f64) -> Option<usize> {
+    let lufs = energy_to_lufs(energy);
+    if lufs <= ABSOLUTE_GATE {
+        return None;
+    }
+    Some((((lufs - ABSOLUTE_GATE) / BIN_WIDTH) as usize).min(BINS - 1))
+}
+
+fn bin_energy(bin: usize) -> f64 {
+    lufs_to_energy(ABSOLUTE_GATE + (bin as f64 + 0.5) * BIN_WIDTH)
+}
+
+/// Integrated loudness of the gated blocks in `histogram`, `None` when the
+/// audio is entirely below the absolute gate.
+fn integrated(histogram: &[u32]) -> Option<f64> {
+    let mean = |from: usize| {
+        let (energy, count) = histogram[from..]
+            .iter()
+            .enumerate()
+            .fold((0.0, 0u64), |(e, c), (i, &n)| {
+                (e + bin_energy(from + i) * n as f64, c + n as u64)
+            });
+        (count > 0).then(|| energy / count as f64)
+    };
+    let threshold = energy_to_lufs(mean(0)?) + RELATIVE_GATE;
+    let first = ((threshold - ABSOLUTE_GATE) / BIN_WIDTH).max(0.0).ceil() as usize;
+    mean(first.min(BINS - 1)).map(energy_to_lufs)
+}
+
+/// The K-weighting of ITU-R BS.1770: a high shelf modelling the head
+/// followed by a high-pass, derived for any sample rate.
+#[derive(Clone)]
+struct KWeighting {
+    shelf: Biquad,
+    high_pass: Biquad,
+}
+
+impl KWeighting {
+    fn new(sample_rate: f64) -> Self {
+        let (f0, gain_db, q) = (1681.974450955533, 3.999843853973347, 0.7071752369554196);
+        let k = (PI * f0 / sample_rate).tan();
+        let vh = 10f64.powf(gain_db / 20.0);
+        let vb = vh.powf(0.4996667741545416);
+        let a0 = 1.0 + k / q + k * k;
+        let shelf = Biquad::new(
+            [
+                (vh + vb * k / q + k * k) / a0,
+                2.0 * (k * k - vh) / a0,
+                (vh - vb * k / q + k * k) / a0,
+            ],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+
+        let (f0, q) = (38.13547087602444, 0.5003270373238773);
+        let k = (PI * f0 / sample_rate).tan();
+        let a0 = 1.0 + k / q + k * k;
+        let high_pass = Biquad::new(
+            [1.0, -2.0, 1.0],
+            [2.0 * (k * k - 1.0) / a0, (1.0 - k / q + k * k) / a0],
+        );
+        Self { shelf, high_pass }
+    }
+    fn process(&mut self, x: f64) -> f64 {
+        self.high_pass.process(self.shelf.process(x))
+    }
+}
+
+pub struct ScanResult {
+    pub path: String,
+    pub loudness: Option<Loudness>,
+    pub album: Option<String>,
+}
+
+/// Files waiting for a scan. `queued` holds the same paths as `order`, so
+/// requesting the whole library doesn't search the queue for each file.
+/// Moving a file to the front leaves a stale copy behind, skipped when
+/// reached.
+#[derive(Default)]
+struct Pending {
+    order: VecDeque<String>,
+    queued: HashSet<String>,
+}
+
+impl Pending {
+    /// Returns `false` when `path` was already waiting and stays where it is.
+    fn push(&mut self, path: &str, urgent: bool) -> bool {
+        if !self.queued.insert(path.to_string()) && !urgent {
+            return false;
+        }
+        if urgent {
+            self.order.push_front(path.to_string());
+        } else {
+            self.order.push_back(path.to_string());
+        }
+        true
+    }
+    fn pop(&mut self) -> Option<String> {
+        while let Some(path) = self.order.pop_front() {
+            if self.queued.remove(&path) {
+                return Some(path);
+            }
+        }
+        None
+    }
+}
+
+/// Scans files on a background thread, one at a time, so a library scan
+/// never competes with playback for more than one core.
+pub struct LoudnessScanner {
+    queue: Arc<(Mutex<Pending>, Condvar)>,
+    results: mpsc::Receiver<ScanResult>,
+}
+
+impl LoudnessScanner {
+    pub fn new() -> Self {
+        let queue = Arc::new((Mutex::new(Pending::default()), Condvar::new()));
+        let (tx, results) = mpsc::channel();
+        let worker_queue = queue.clone();
+        thread::spawn(move || {
+            let (lock, cvar) = &*worker_queue;
+            loop {
+                let path = {
+                    let mut queue = lock.lock().unwrap();
+                    loop {
+                        if let Some(path) = queue.pop() {
+                            break path;
+                        }
+                        queue = cvar.wait(queue).unwrap();
+                    }
+                };
+                let result = ScanResult {
+                    loudness: Loudness::load(&path),
+                    album: read_album(&path),
+                    path,
+                };
+                if tx.send(result).is_err() {
+                    return;
+                }
+            }
+        });
+        Self { queue, results }
+    }
+    /// Queues `path`; `urgent` scans jump ahead of the background backlog.
+    pub fn request(&self, path: &str, urgent: bool) {
+        let (lock, cvar) = &*self.queue;
+        if lock.lock().unwrap().push(path, urgent) {
+            cvar.notify_one();
+        }
+    }
+    pub fn try_recv(&self) -> Option<ScanResult> {
+        self.results.try_recv().ok()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{f64::consts::TAU, fs, path::PathBuf};
+
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use super::*;
+
+    const RATE: u32 = 48_000;
+
+    /// Mono WAV of sine sections, each `(frequency, dBFS, seconds)`.
+    fn sines(name: &str, sections: &[(f64, f64, u32)]) -> PathBuf {
+        let path = std::env::temp_dir().join(format!("audyo-{}-{}.wav", name, std::process::id()));
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: RATE,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for &(frequency, db, secs) in sections {
+            let amplitude = 10f64.powf(db / 20.0) * i16::MAX as f64;
+            for i in 0..RATE * secs {
+                let t = i as f64 / RATE as f64;
+                writer
+                    .write_sample((amplitude * (TAU * frequency * t).sin()).round() as i16)
+                    .unwrap();
+            }
+        }
+        writer.finalize().unwrap();
+        path
+    }
+
+    fn integrated(name: &str, sections: &[(f64, f64, u32)]) -> f64 {
+        let path = sines(name, sections);
+        let loudness = Loudness::scan(&path.display().to_string()).unwrap();
+        fs::remove_file(path).unwrap();
+        loudness.integrated
+    }
+
+    #[test]
+    fn sine_reads_at_its_reference_level() {
+        // BS.1770: a 997 Hz sine in one channel reads 3.01 dB below its level.
+        let lufs = integrated("sine", &[(997.0, -20.0, 5)]);
+        assert!((lufs + 23.01).abs() < 0.15, "{lufs}");
+    }
+
+    #[test]
+    fn gates_out_silence_and_quiet_passages() {
+        let lufs = integrated(
+            "gated",
+            &[(997.0, -20.0, 5), (997.0, -40.0, 5), (997.0, -90.0, 5)],
+        );
+        // Ungated it would read about 3 dB lower; only the blocks straddling
+        // the drop pull it down a little.
+        assert!((lufs + 23.01).abs() < 0.25, "{lufs}");
+        let silent = sines("silent", &[(997.0, -90.0, 2)]);
+        assert!(Loudness::scan(&silent.display().to_string()).is_none());
+        fs::remove_file(silent).unwrap();
+    }
+
+    #[test]
+    fn k_weighting_lifts_highs_and_cuts_lows() {
+        let mid = integrated("k-mid", &[(997.0, -20.0, 3)]);
+        let high = integrated("k-high", &[(10_000.0, -20.0, 3)]);
+        let low = integrated("k-low", &[(20.0, -20.0, 3)]);
+        assert!((3.0..4.5).contains(&(high - mid)), "{}", high - mid);
+        assert!(mid - low > 10.0, "{}", mid - low);
+    }
+
+    #[test]
+    fn pending_keeps_one_copy_and_moves_urgent_ones_ahead() {
+        let mut pending = Pending::default();
+        assert!(pending.push("a", false));
+        assert!(pending.push("b", false));
+        assert!(!pending.push("a", false));
+        assert!(pending.push("b", true));
+        assert!(pending.push("c", false));
+        let order: Vec<_> = std::iter::from_fn(|| pending.pop()).collect();
+        assert_eq!(order, ["b", "a", "c"]);
+    }
+}
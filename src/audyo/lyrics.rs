@@ -0,0 +1,482 @@
+use std::{
+    fs::{self, File},
+    io::Read,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use crate::audyo::metadata::read_lyrics;
+
+/// SYLT timestamps counted in milliseconds rather than MPEG frames.
+const SYLT_MILLISECONDS: u8 = 2;
+
+#[derive(Debug, Clone, PartialEq)]
+pub struct LyricLine {
+    /// When the line starts; `None` for lyrics without timing.
+    pub time: Option<Duration>,
+    pub text: String,
+}
+
+/// Lyrics of a track, from an `.lrc` file next to it or embedded in it.
+#[derive(Debug, Clone, Default)]
+pub struct Lyrics {
+    pub lines: Vec<LyricLine>,
+    /// The file's own `[offset:]`, in milliseconds; positive shows lines
+    /// earlier.
+    offset_ms: i64,
+}
+
+impl Lyrics {
+    /// The `.lrc` file read for `audio`.
+    pub fn path_for(audio: &Path) -> PathBuf {
+        audio.with_extension("lrc")
+    }
+    /// Lyrics of `audio`: its `.lrc` file, captions saved as `.srt`,
+    /// synchronized lyrics embedded as ID3 `SYLT`, then plain embedded
+    /// lyrics, which may be LRC themselves.
+    pub fn load(audio: &str) -> Option<Self> {
+        let path = Path::new(audio);
+        let read = |path: PathBuf| {
+            fs::read(path)
+                .ok()
+                .map(|data| String::from_utf8_lossy(&data).into_owned())
+        };
+        let lyrics = if let Some(text) = read(Self::path_for(path)) {
+            Self::parse(&text)
+        } else if let Some(text) = read(path.with_extension("srt")) {
+            Self::parse_srt(&text)
+        } else {
+            read_sylt(path).or_else(|| read_lyrics(audio).map(|text| Self::parse(&text)))?
+        };
+        (!lyrics.lines.is_empty()).then_some(lyrics)
+    }
+    /// Parses SubRip subtitles, one line per cue.
+    pub fn parse_srt(text: &str) -> Self {
+        let mut lyrics = Self::default();
+        let text = text.replace("\r\n", "\n");
+        for cue in text.split("\n\n") {
+            let mut lines = cue
+                .lines()
+                .map(str::trim)
+                .skip_while(|l| !l.contains("-->"));
+            let Some(time) = lines
+                .next()
+                .and_then(|l| l.split("-->").next())
+                .and_then(|start| parse_srt_time(start.trim()))
+            else {
+                continue;
+            };
+            let text = lines.collect::<Vec<_>>().join(" ");
+            lyrics.lines.push(LyricLine {
+                time: Some(time),
+                text: strip_markup(&text),
+            });
+        }
+        lyrics.lines.sort_by_key(|l| l.time);
+        lyrics
+    }
+    /// Parses LRC text. Lines can carry several timestamps and are sorted
+    /// by time; without any timestamps the text is kept as it is, unsynced.
+    pub fn parse(text: &str) -> Self {
+        let mut lyrics = Self::default();
+        let mut plain = Vec::new();
+        for line in text.lines() {
+            let mut rest = line.trim();
+            let mut times = Vec::new();
+            let mut metadata = false;
+            while let Some(tag) = rest.strip_prefix('[')
+                && let Some(end) = tag.find(']')
+            {
+                let tag = &tag[..end];
+                if let Some(time) = parse_time(tag) {
+                    times.push(time);
+                } else if let Some((key, value)) = tag.split_once(':')
+                    && !key.is_empty()
+                    && key.chars().all(|c| c.is_ascii_alphabetic())
+                {
+                    // `[ar:...]` and the like; only the offset matters here.
+                    if key == "offset" {
+                        lyrics.offset_ms = value.trim().parse().unwrap_or(0);
+                    }
+                    metadata = true;
+                } else {
+                    // Part of the text, like `[Chorus]`.
+                    break;
+                }
+                rest = rest[end + 2..].trim_start();
+            }
+            let text = strip_word_times(rest);
+            if times.is_empty() {
+                if !(metadata && text.is_empty()) {
+                    plain.push(text);
+                }
+                continue;
+            }
+            for time in times {
+                lyrics.lines.push(LyricLine {
+                    time: Some(time),
+                    text: text.clone(),
+                });
+            }
+        }
+        if lyrics.lines.is_empty() {
+            // Drop the blank lines around plain lyrics.
+            while plain.last().is_some_and(|l| l.is_empty()) {
+                plain.pop();
+            }
+            let start = plain.iter().position(|l| !l.is_empty()).unwrap_or(0);
+            lyrics.lines = plain[start..]
+                .iter()
+                .map(|text| LyricLine {
+                    time: None,
+                    text: text.clone(),
+                })
+                .collect();
+        } else {
+            lyrics.lines.sort_by_key(|l| l.time);
+        }
+        lyrics
+    }
+    pub fn is_synced(&self) -> bool {
+        self.lines.first().is_some_and(|l| l.time.is_some())
+    }
+    /// Playback position at which line `index` shows, shifted by `offset_ms`
+    /// on top of the file's own offset.
+    pub fn line_time(&self, index: usize, offset_ms: i64) -> Option<Duration> {
+        let time = self.lines.get(index)?.time?.as_millis() as i64;
+        let at = time
+            .saturating_sub(self.offset_ms)
+            .saturating_sub(offset_ms);
+        Some(Duration::from_millis(at.max(0) as u64))
+    }
+    /// Line sung at `position`, shifted by `offset_ms`. `None` before the
+    /// first line and for unsynced lyrics.
+    pub fn current(&self, position: Duration, offset_ms: i64) -> Option<usize> {
+        let at = (position.as_millis() as i64)
+            .saturating_add(self.offset_ms)
+            .saturating_add(offset_ms);
+        let next = self
+            .lines
+            .partition_point(|l| l.time.is_some_and(|t| t.as_millis() as i64 <= at));
+        next.checked_sub(1).filter(|_| self.is_synced())
+    }
+}
+
+/// `mm:ss`, `mm:ss.xx` or `mm:ss:xx`.
+fn parse_time(tag: &str) -> Option<Duration> {
+    let (minutes, rest) = tag.split_once(':')?;
+    let minutes: u64 = minutes.trim().parse().ok()?;
+    let (seconds, fraction) = match rest.find(['.', ':']) {
+        Some(i) => (&rest[..i], &rest[i + 1..]),
+        None => (rest, ""),
+    };
+    let seconds: u64 = seconds.trim().parse().ok()?;
+    let millis = match fraction.len() {
+        0 => 0,
+        // Hundredths in most files, thousandths in some.
+        len @ 1..=3 => fraction.parse::<u64>().ok()? * 10u64.pow(3 - len as u32),
+        _ => return None,
+    };
+    Some(Duration::from_millis(
+        (minutes * 60 + seconds) * 1000 + millis,
+    ))
+}
+
+/// `hh:mm:ss,mmm`.
+fn parse_srt_time(time: &str) -> Option<Duration> {
+    let (hours, rest) = time.split_once(':')?;
+    let hours: u64 = hours.parse().ok()?;
+    let rest = parse_time(&rest.replace(',', "."))?;
+    Some(Duration::from_secs(hours * 3600) + rest)
+}
+
+/// Removes tags like `<i>` from subtitle text.
+fn strip_markup(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut in_tag = false;
+    for c in text.chars() {
+        match c {
+            '<' => in_tag = true,
+            '>' if in_tag => in_tag = false,
+            c if !in_tag => out.push(c),
+            _ => {}
+        }
+    }
+    out.trim().to_string()
+}
+
+/// Removes the per-word `<mm:ss.xx>` times of enhanced LRC.
+fn strip_word_times(text: &str) -> String {
+    let mut out = String::with_capacity(text.len());
+    let mut rest = text;
+    while let Some(start) = rest.find('<') {
+        match rest[start..].find('>') {
+            Some(end) if parse_time(&rest[start + 1..start + end]).is_some() => {
+                out.push_str(&rest[..start]);
+                rest = &rest[start + end + 1..];
+            }
+            _ => {
+                out.push_str(&rest[..=start]);
+                rest = &rest[start + 1..];
+            }
+        }
+    }
+    out.push_str(rest);
+    out.trim().to_string()
+}
+
+/// Synchronized lyrics from the `SYLT` frame of an ID3v2 tag at the start of
+/// `path`, which symphonia skips.
+fn read_sylt(path: &Path) -> Option<Lyrics> {
+    let mut file = File::open(path).ok()?;
+    let mut header = [0; 10];
+    file.read_exact(&mut header).ok()?;
+    let version = header[3];
+    if &header[..3] != b"ID3" || !(3..=4).contains(&version) {
+        return None;
+    }
+    // The size comes from the file; don't trust it past the file's end.
+    let size = syncsafe(&header[6..10]) as u64;
+    if size > file.metadata().ok()?.len() {
+        return None;
+    }
+    let mut tag = vec![0; size as usize];
+    file.read_exact(&mut tag).ok()?;
+    // Tags unsynchronised as a whole are rare and not worth undoing.
+    if header[5] & 0x80 != 0 {
+        return None;
+    }
+    let mut frames = &tag[..];
+    if header[5] & 0x40 != 0 {
+        let size = u32::from_be_bytes(frames.get(..4)?.try_into().unwrap());
+        let size = if version == 4 {
+            syncsafe(&frames[..4])
+        } else {
+            size + 4
+        };
+        frames = frames.get(size as usize..)?;
+    }
+    while frames.len() >= 10 && frames[0] != 0 {
+        let id = &frames[..4];
+        let size = if version == 4 {
+            syncsafe(&frames[4..8])
+        } else {
+            u32::from_be_bytes(frames[4..8].try_into().unwrap())
+        } as usize;
+        let body = frames.get(10..10 + size)?;
+        if id == b"SYLT" {
+            return parse_sylt(body);
+        }
+        frames = &frames[10 + size..];
+    }
+    None
+}
+
+fn syncsafe(bytes: &[u8]) -> u32 {
+    bytes.iter().fold(0, |n, &b| n << 7 | (b & 0x7f) as u32)
+}
+
+/// A `SYLT` body: encoding, language, timestamp format, content type and
+/// descriptor, then text and a timestamp for each line.
+fn parse_sylt(body: &[u8]) -> Option<Lyrics> {
+    let encoding = *body.first()?;
+    if body.get(4) != Some(&SYLT_MILLISECONDS) {
+        // Timestamps in MPEG frames would need the stream's frame rate.
+        return None;
+    }
+    let (_, mut rest) = split_text(body.get(6..)?, encoding)?;
+    let mut lyrics = Lyrics::default();
+    while !rest.is_empty() {
+        let (text, after) = split_text(rest, encoding)?;
+        let time = after.get(..4)?;
+        let time = u32::from_be_bytes(time.try_into().unwrap());
+        lyrics.lines.push(LyricLine {
+            time: Some(Duration::from_millis(time as u64)),
+            // Lines often start with the newline that separates them.
+            text: text.trim().to_string(),
+        });
+        rest = &after[4..];
+    }
+    lyrics.lines.sort_by_key(|l| l.time);
+    Some(lyrics)
+}
+
+/// Decodes the terminated string at the start of `data` and returns it with
+/// what follows the terminator.
+fn split_text(data: &[u8], encoding: u8) -> Option<(String, &[u8])> {
+    if matches!(encoding, 1 | 2) {
+        let end = data.chunks_exact(2).position(|c| c == [0, 0])? * 2;
+        let mut units: Vec<u16> = data[..end]
+            .chunks_exact(2)
+            .map(|c| u16::from_be_bytes([c[0], c[1]]))
+            .collect();
+        match units.first() {
+            Some(0xfffe) => {
+                units = units.iter().map(|u| u.swap_bytes()).skip(1).collect();
+            }
+            Some(0xfeff) => {
+                units.remove(0);
+            }
+            _ => {}
+        }
+        Some((String::from_utf16_lossy(&units), &data[end + 2..]))
+    } else {
+        let end = data.iter().position(|&b| b == 0)?;
+        let text = if encoding == 3 {
+            String::from_utf8_lossy(&data[..end]).into_owned()
+        } else {
+            data[..end].iter().map(|&b| b as char).collect()
+        };
+        Some((text, &data[end + 1..]))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn ms(ms: u64) -> Option<Duration> {
+        Some(Duration::from_millis(ms))
+    }
+
+    #[test]
+    fn parses_lrc_with_repeated_times_and_metadata() {
+        let lyrics = Lyrics::parse(
+            "[ar:Someone]\n[offset:+500]\n[00:12.00][01:00.50]Chorus\n\
+             [00:05.5]<00:05.50>First <00:06.00>line\n[Intro] not a time",
+        );
+        let lines: Vec<_> = lyrics
+            .lines
+            .iter()
+            .map(|l| (l.time, l.text.as_str()))
+            .collect();
+        assert_eq!(
+            lines,
+            [
+                (ms(5_500), "First line"),
+                (ms(12_000), "Chorus"),
+                (ms(60_500), "Chorus"),
+            ]
+        );
+        assert_eq!(lyrics.offset_ms, 500);
+        assert!(lyrics.is_synced());
+    }
+
+    #[test]
+    fn keeps_plain_lyrics_unsynced() {
+        let lyrics = Lyrics::parse("[ti:Song]\n\nOne\n\nTwo\n\n");
+        let texts: Vec<_> = lyrics.lines.iter().map(|l| l.text.as_str()).collect();
+        assert_eq!(texts, ["One", "", "Two"]);
+        assert!(!lyrics.is_synced());
+        assert_eq!(lyrics.current(Duration::from_secs(10), 0), None);
+    }
+
+    #[test]
+    fn parses_srt_cues() {
+        let lyrics = Lyrics::parse_srt(
+            "2\r\n00:00:04,250 --> 00:00:06,000\r\n<i>Second</i>\r\n\r\n\
+             1\r\n00:00:01,000 --> 00:00:03,000\r\nFirst\r\nline\r\n\r\nnot a cue",
+        );
+        let lines: Vec<_> = lyrics
+            .lines
+            .iter()
+            .map(|l| (l.time, l.text.as_str()))
+            .collect();
+        assert_eq!(lines, [(ms(1_000), "First line"), (ms(4_250), "Second")]);
+    }
+
+    #[test]
+    fn current_line_follows_both_offsets() {
+        let lyrics = Lyrics::parse("[offset:1000]\n[00:02.00]A\n[00:04.00]B");
+        let at = |secs, offset| lyrics.current(Duration::from_secs(secs), offset);
+        assert_eq!(at(0, 0), None);
+        assert_eq!(at(1, 0), Some(0));
+        assert_eq!(at(3, 0), Some(1));
+        assert_eq!(at(3, -2000), Some(0));
+        assert_eq!(lyrics.line_time(1, 0), ms(3_000));
+        assert_eq!(lyrics.line_time(0, 5000), ms(0));
+    }
+
+    #[test]
+    fn extreme_offsets_saturate() {
+        let lyrics = Lyrics::parse(&format!("[offset:{}]\n[00:01.00]A", i64::MAX));
+        assert_eq!(lyrics.current(Duration::from_secs(5), i64::MAX), Some(0));
+        assert_eq!(lyrics.line_time(0, i64::MAX), ms(0));
+        let lyrics = Lyrics::parse(&format!("[offset:{}]\n[00:01.00]A", i64::MIN));
+        assert_eq!(lyrics.current(Duration::from_secs(5), i64::MIN), None);
+    }
+
+    struct Temp(PathBuf);
+
+    impl Temp {
+        fn new(name: &str, data: &[u8]) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("audyo-{}-{}.mp3", name, std::process::id()));
+            fs::write(&path, data).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for Temp {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn syncsafe_bytes(n: u32) -> [u8; 4] {
+        [
+            (n >> 21) as u8 & 0x7f,
+            (n >> 14) as u8 & 0x7f,
+            (n >> 7) as u8 & 0x7f,
+            n as u8 & 0x7f,
+        ]
+    }
+
+    /// An ID3v2.4 tag holding one frame, followed by a little audio.
+    fn id3(id: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut frame = id.to_vec();
+        frame.extend(syncsafe_bytes(body.len() as u32));
+        frame.extend([0, 0]);
+        frame.extend(body);
+        let mut tag = b"ID3\x04\x00\x00".to_vec();
+        tag.extend(syncsafe_bytes(frame.len() as u32));
+        tag.extend(frame);
+        tag.extend([0xff; 16]);
+        tag
+    }
+
+    fn sylt(format: u8) -> Vec<u8> {
+        let mut body = vec![3, b'e', b'n', b'g', format, 1];
+        body.extend(b"desc\0");
+        body.extend(b"\nSecond\0");
+        body.extend(2500u32.to_be_bytes());
+        body.extend(b"First\0");
+        body.extend(1000u32.to_be_bytes());
+        body
+    }
+
+    #[test]
+    fn reads_sylt_frames() {
+        let file = Temp::new("sylt", &id3(b"SYLT", &sylt(SYLT_MILLISECONDS)));
+        let lyrics = read_sylt(&file.0).unwrap();
+        let lines: Vec<_> = lyrics
+            .lines
+            .iter()
+            .map(|l| (l.time, l.text.as_str()))
+            .collect();
+        assert_eq!(lines, [(ms(1_000), "First"), (ms(2_500), "Second")]);
+
+        let file = Temp::new("sylt-frames", &id3(b"SYLT", &sylt(1)));
+        assert!(read_sylt(&file.0).is_none());
+        let file = Temp::new("sylt-none", &id3(b"TIT2", b"\x03Title"));
+        assert!(read_sylt(&file.0).is_none());
+    }
+
+    #[test]
+    fn ignores_tag_sizes_past_the_end_of_the_file() {
+        let mut data = id3(b"SYLT", &sylt(SYLT_MILLISECONDS));
+        data[6..10].copy_from_slice(&[0x7f; 4]);
+        let file = Temp::new("sylt-huge", &data);
+        assert!(read_sylt(&file.0).is_none());
+    }
+}
@@ -0,0 +1,195 @@
+use std::{fs::File, path::Path, time::Duration};
+
+use serde::{Deserialize, Serialize};
+use symphonia::core::{
+    formats::{FormatOptions, FormatReader},
+    io::MediaSourceStream,
+    meta::{MetadataOptions, MetadataRevision, StandardTagKey, StandardVisualKey, Tag},
+    probe::{Hint, ProbeResult},
+};
+
+/// Tags embedded in an audio file, as far as they are set.
+#[derive(Debug, Clone, Default, PartialEq, Serialize, Deserialize)]
+pub struct TrackTags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub album: Option<String>,
+    pub duration: Option<Duration>,
+}
+
+impl TrackTags {
+    /// "Artist – Title", or just the title without an artist. `None` when
+    /// the file has no title tag.
+    pub fn display_title(&self) -> Option<String> {
+        let title = self.title.as_deref()?;
+        Some(match &self.artist {
+            Some(artist) => format!("{} – {}", artist, title),
+            None => title.to_string(),
+        })
+    }
+    /// Fills in whatever `tags` have that is still missing.
+    fn merge(&mut self, tags: &[Tag]) {
+        for tag in tags {
+            let field = match tag.std_key {
+                Some(StandardTagKey::TrackTitle) => &mut self.title,
+                Some(StandardTagKey::Artist) => &mut self.artist,
+                Some(StandardTagKey::Album) => &mut self.album,
+                _ => continue,
+            };
+            if field.is_none() {
+                *field = text(tag);
+            }
+        }
+        // Compilations often only credit the album artist.
+        if self.artist.is_none() {
+            self.artist = tags
+                .iter()
+                .filter(|t| t.std_key == Some(StandardTagKey::AlbumArtist))
+                .find_map(text);
+        }
+    }
+}
+
+/// The trimmed value of `tag`, `None` when blank.
+fn text(tag: &Tag) -> Option<String> {
+    let value = tag.value.to_string();
+    let value = value.trim();
+    (!value.is_empty()).then(|| value.to_string())
+}
+
+fn probe(path: &str) -> Option<ProbeResult> {
+    let file = File::open(path).ok()?;
+    let mss = MediaSourceStream::new(Box::new(file), Default::default());
+    let mut hint = Hint::new();
+    if let Some(ext) = Path::new(path).extension().and_then(|e| e.to_str()) {
+        hint.with_extension(ext);
+    }
+    symphonia::default::get_probe()
+        .format(
+            &hint,
+            mss,
+            &FormatOptions::default(),
+            &MetadataOptions::default(),
+        )
+        .ok()
+}
+
+/// Whether `path` probes as a format with an audio track.
+pub fn is_audio(path: &str) -> bool {
+    probe(path).is_some_and(|p| p.format.default_track().is_some())
+}
+
+/// Reads the title, artist, album and duration of `path`. `None` if the
+/// file can't be probed as audio.
+pub fn read_tags(path: &str) -> Option<TrackTags> {
+    let mut probed = probe(path)?;
+    let mut tags = TrackTags {
+        duration: duration(probed.format.as_ref()),
+        ..TrackTags::default()
+    };
+    // Containers carry their own tags, while ID3v2 ahead of an MP3 stream is
+    // only seen by the probe.
+    if let Some(revision) = probed.format.metadata().current() {
+        tags.merge(revision.tags());
+    }
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        tags.merge(revision.tags());
+    }
+    Some(tags)
+}
+
+/// Lyrics embedded as a tag (ID3 `USLT`, MP4 `©lyr`, Vorbis `LYRICS`).
+pub fn read_lyrics(path: &str) -> Option<String> {
+    let mut probed = probe(path)?;
+    let find = |revision: &MetadataRevision| {
+        revision
+            .tags()
+            .iter()
+            .find(|t| t.std_key == Some(StandardTagKey::Lyrics))
+            .map(|t| t.value.to_string())
+    };
+    if let Some(lyrics) = probed.format.metadata().current().and_then(find) {
+        return Some(lyrics);
+    }
+    probed
+        .metadata
+        .get()
+        .as_ref()
+        .and_then(|m| m.current())
+        .and_then(find)
+}
+
+/// Image data of the cover embedded in `path`, preferring the front cover
+/// when there are several pictures.
+pub fn read_cover(path: &str) -> Option<Vec<u8>> {
+    let mut probed = probe(path)?;
+    let mut visuals = Vec::new();
+    if let Some(revision) = probed.format.metadata().current() {
+        visuals.extend(revision.visuals().iter().cloned());
+    }
+    if let Some(revision) = probed.metadata.get().as_ref().and_then(|m| m.current()) {
+        visuals.extend(revision.visuals().iter().cloned());
+    }
+    visuals
+        .iter()
+        .find(|v| v.usage == Some(StandardVisualKey::FrontCover))
+        .or(visuals.first())
+        .map(|v| v.data.to_vec())
+}
+
+fn duration(format: &dyn FormatReader) -> Option<Duration> {
+    let params = &format.default_track()?.codec_params;
+    let time = params.time_base?.calc_time(params.n_frames?);
+    Some(Duration::from_secs(time.seconds) + Duration::from_secs_f64(time.frac))
+}
+
+/// Reads the album name from the tags embedded in `path`.
+pub fn read_album(path: &str) -> Option<String> {
+    read_tags(path)?.album
+}
+
+#[cfg(test)]
+mod tests {
+    use symphonia::core::meta::Value;
+
+    use super::*;
+
+    fn tag(key: StandardTagKey, value: &str) -> Tag {
+        Tag::new(Some(key), "", Value::from(value))
+    }
+
+    #[test]
+    fn merge_keeps_the_first_non_blank_value() {
+        let mut tags = TrackTags::default();
+        tags.merge(&[
+            tag(StandardTagKey::TrackTitle, "  "),
+            tag(StandardTagKey::TrackTitle, " Song "),
+            tag(StandardTagKey::Album, "First"),
+        ]);
+        tags.merge(&[
+            tag(StandardTagKey::Album, "Second"),
+            tag(StandardTagKey::Artist, "Band"),
+        ]);
+        assert_eq!(tags.title.as_deref(), Some("Song"));
+        assert_eq!(tags.album.as_deref(), Some("First"));
+        assert_eq!(tags.artist.as_deref(), Some("Band"));
+    }
+
+    #[test]
+    fn album_artist_fills_in_a_missing_artist() {
+        let mut tags = TrackTags::default();
+        tags.merge(&[
+            tag(StandardTagKey::Artist, " "),
+            tag(StandardTagKey::AlbumArtist, ""),
+            tag(StandardTagKey::AlbumArtist, " Various Artists\n"),
+        ]);
+        assert_eq!(tags.artist.as_deref(), Some("Various Artists"));
+
+        let mut tags = TrackTags::default();
+        tags.merge(&[tag(StandardTagKey::AlbumArtist, "  ")]);
+        assert_eq!(tags.artist, None);
+        // A later revision can still supply it.
+        tags.merge(&[tag(StandardTagKey::Artist, "Band")]);
+        assert_eq!(tags.artist.as_deref(), Some("Band"));
+    }
+}
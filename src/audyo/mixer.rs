@@ -0,0 +1,404 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::{
+    Sample, Source,
+    cpal::FromSample,
+    source::{SeekError, UniformSourceIterator},
+};
+
+pub const SAMPLE_RATE: u32 = 44_100;
+pub const CHANNELS: u16 = 2;
+/// Frames mixed per lock of the shared state.
+const CHUNK_FRAMES: usize = 512;
+/// Per-frame step towards a new track gain (~45ms time constant), so gain
+/// updates don't click.
+const GAIN_SMOOTHING: f32 = 0.0005;
+/// Fade-in after jumping back to the start of an A-B loop, in frames (~10ms).
+const LOOP_DECLICK: u64 = 441;
+
+type TrackSource = Box<dyn Source<Item = f32> + Send>;
+
+/// Section of a track played over and over, starting `lead_in` before `a`
+/// each time round.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct LoopRegion {
+    pub a: Duration,
+    pub b: Duration,
+    pub lead_in: Duration,
+    /// How many times the section is played; forever with `None`.
+    pub plays: Option<u32>,
+    /// Times the section has been played through so far.
+    pub done: u32,
+}
+
+/// A decoded file, converted to the mixer's output format.
+pub struct Track {
+    path: String,
+    source: TrackSource,
+    gain: f32,
+    target_gain: f32,
+    total_frames: Option<u64>,
+    frames: u64,
+    crossfade: bool,
+    fade_in: Option<Fade>,
+    fade_out: Option<Fade>,
+    loop_region: Option<LoopRegion>,
+}
+
+impl Track {
+    pub fn new<S>(path: String, source: S) -> Self
+    where
+        S: Source + Send + 'static,
+        S::Item: Sample + Send,
+        f32: FromSample<S::Item>,
+    {
+        let total_frames = source.total_duration().map(duration_to_frames);
+        let source: UniformSourceIterator<S, f32> =
+            UniformSourceIterator::new(source, CHANNELS, SAMPLE_RATE);
+        Self {
+            path,
+            source: Box::new(source),
+            gain: 1.0,
+            target_gain: 1.0,
+            total_frames,
+            frames: 0,
+            crossfade: false,
+            fade_in: None,
+            fade_out: None,
+            loop_region: None,
+        }
+    }
+    /// Starts the track at `pos` instead of the beginning. The track plays
+    /// from the start if the source can't seek.
+    pub fn start_at(mut self, pos: Duration) -> Self {
+        if !pos.is_zero() && self.source.try_seek(pos).is_ok() {
+            self.frames = duration_to_frames(pos);
+        }
+        self
+    }
+    /// Linear gain applied to the track, e.g. for loudness normalization.
+    pub fn gain(mut self, gain: f32) -> Self {
+        self.gain = gain;
+        self.target_gain = gain;
+        self
+    }
+    fn remaining_frames(&self) -> Option<u64> {
+        self.total_frames.map(|t| t.saturating_sub(self.frames))
+    }
+    /// Jumps back to the start of the loop region once its end is reached,
+    /// dropping the region after its last repeat.
+    fn wrap_loop(&mut self) {
+        let Some(region) = &mut self.loop_region else {
+            return;
+        };
+        if self.frames < duration_to_frames(region.b) {
+            return;
+        }
+        let start = region.a.saturating_sub(region.lead_in);
+        region.done += 1;
+        if region.plays.is_some_and(|n| region.done >= n) || self.source.try_seek(start).is_err() {
+            self.loop_region = None;
+            return;
+        }
+        self.frames = duration_to_frames(start);
+        self.fade_in = Some(Fade::new(LOOP_DECLICK));
+    }
+    /// Adds the next frame into `out`. Returns `false` once the track has
+    /// ended or faded out completely.
+    fn mix_frame(&mut self, out: &mut [f32; CHANNELS as usize]) -> bool {
+        self.wrap_loop();
+        self.gain += (self.target_gain - self.gain) * GAIN_SMOOTHING;
+        let mut gain = self.gain;
+        if let Some(fade) = &mut self.fade_in {
+            gain *= fade.step().sin();
+            if fade.is_done() {
+                self.fade_in = None;
+            }
+        }
+        if let Some(fade) = &mut self.fade_out {
+            gain *= fade.step().cos();
+            if fade.is_done() {
+                return false;
+            }
+        }
+        for sample in out.iter_mut() {
+            let Some(s) = self.source.next() else {
+                return false;
+            };
+            *sample += s * gain;
+        }
+        self.frames += 1;
+        true
+    }
+}
+
+/// Equal-power fade over a fixed number of frames.
+struct Fade {
+    done: u64,
+    len: u64,
+}
+
+impl Fade {
+    fn new(len: u64) -> Self {
+        Self {
+            done: 0,
+            len: len.max(1),
+        }
+    }
+    /// Advances one frame and returns the fade angle in `0..=π/2`.
+    fn step(&mut self) -> f32 {
+        self.done = (self.done + 1).min(self.len);
+        self.done as f32 / self.len as f32 * std::f32::consts::FRAC_PI_2
+    }
+    fn is_done(&self) -> bool {
+        self.done >= self.len
+    }
+}
+
+#[derive(Default)]
+struct MixerState {
+    current: Option<Track>,
+    next: Option<Track>,
+    outgoing: Option<Track>,
+    crossfade: Duration,
+    advanced: bool,
+    /// Chunks pulled by the output so far, to tell whether it is alive.
+    chunks: u64,
+}
+
+impl MixerState {
+    fn crossfade_frames(&self) -> u64 {
+        duration_to_frames(self.crossfade)
+    }
+    /// Starts fading `current` out and `next` in once the current track is
+    /// within the crossfade window of its end.
+    fn maybe_start_crossfade(&mut self) {
+        let window = self.crossfade_frames();
+        let (Some(current), Some(next)) = (&self.current, &self.next) else {
+            return;
+        };
+        if window == 0
+            || !next.crossfade
+            || current.fade_out.is_some()
+            || current.loop_region.is_some()
+        {
+            return;
+        }
+        let Some(remaining) = current.remaining_frames() else {
+            return;
+        };
+        if remaining <= window {
+            self.promote_next(remaining);
+        }
+    }
+    /// Makes the queued track current. With a non-zero `fade` the previous
+    /// track keeps playing underneath while the two are crossfaded.
+    fn promote_next(&mut self, fade: u64) {
+        let Some(mut next) = self.next.take() else {
+            self.current = None;
+            return;
+        };
+        if let Some(mut current) = self.current.take()
+            && fade > 0
+        {
+            current.fade_out = Some(Fade::new(fade));
+            next.fade_in = Some(Fade::new(fade));
+            self.outgoing = Some(current);
+        }
+        self.current = Some(next);
+        self.advanced = true;
+    }
+    fn mix_frame(&mut self) -> [f32; CHANNELS as usize] {
+        let mut frame = [0.0; CHANNELS as usize];
+        self.maybe_start_crossfade();
+        if let Some(outgoing) = &mut self.outgoing
+            && !outgoing.mix_frame(&mut frame)
+        {
+            self.outgoing = None;
+        }
+        if let Some(current) = &mut self.current
+            && !current.mix_frame(&mut frame)
+        {
+            self.promote_next(0);
+            if let Some(current) = &mut self.current {
+                current.mix_frame(&mut frame);
+            }
+        }
+        frame
+    }
+}
+
+/// Handle to the tracks feeding the sink. Tracks are swapped in and out
+/// without touching the sink, so playback never has to restart.
+#[derive(Clone, Default)]
+pub struct Mixer {
+    state: Arc<Mutex<MixerState>>,
+}
+
+impl Mixer {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// The source to append to the sink. It never ends and plays silence
+    /// while no track is loaded.
+    pub fn source(&self) -> MixerSource {
+        MixerSource {
+            state: self.state.clone(),
+            buffer: Vec::with_capacity(CHUNK_FRAMES * CHANNELS as usize),
+            pos: 0,
+        }
+    }
+    /// Replaces whatever is playing with `track`, crossfading into it when
+    /// `crossfade` is set and a crossfade duration is configured.
+    pub fn play_now(&self, mut track: Track, crossfade: bool) {
+        let mut state = self.state.lock().unwrap();
+        let fade = state.crossfade_frames();
+        state.next = None;
+        state.outgoing = None;
+        state.advanced = false;
+        match state.current.take() {
+            Some(mut current) if crossfade && fade > 0 => {
+                current.fade_out = Some(Fade::new(fade));
+                track.fade_in = Some(Fade::new(fade));
+                state.outgoing = Some(current);
+            }
+            _ => {}
+        }
+        state.current = Some(track);
+    }
+    /// Queues `track` to start as soon as the current one ends, or to be
+    /// crossfaded with its tail when `crossfade` is set.
+    pub fn queue_next(&self, mut track: Track, crossfade: bool) {
+        track.crossfade = crossfade;
+        self.state.lock().unwrap().next = Some(track);
+    }
+    /// Forgets the track queued with `queue_next`, unless it already started.
+    pub fn clear_next(&self) {
+        self.state.lock().unwrap().next = None;
+    }
+    /// Changes the gain of every loaded track playing `path`.
+    pub fn set_gain(&self, path: &str, gain: f32) {
+        let mut state = self.state.lock().unwrap();
+        let MixerState {
+            current,
+            next,
+            outgoing,
+            ..
+        } = &mut *state;
+        for track in [current, next, outgoing].into_iter().flatten() {
+            if track.path == path {
+                track.target_gain = gain;
+            }
+        }
+    }
+    pub fn clear(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.current = None;
+        state.next = None;
+        state.outgoing = None;
+        state.advanced = false;
+    }
+    /// Whether the queued track has started since the last call.
+    pub fn take_advanced(&self) -> bool {
+        std::mem::take(&mut self.state.lock().unwrap().advanced)
+    }
+    pub fn is_idle(&self) -> bool {
+        let state = self.state.lock().unwrap();
+        state.current.is_none() && state.next.is_none()
+    }
+    pub fn position(&self) -> Duration {
+        let state = self.state.lock().unwrap();
+        state
+            .current
+            .as_ref()
+            .map_or(Duration::ZERO, |t| frames_to_duration(t.frames))
+    }
+    pub fn try_seek(&self, pos: Duration) -> Result<(), SeekError> {
+        let mut state = self.state.lock().unwrap();
+        let Some(current) = &mut state.current else {
+            return Ok(());
+        };
+        current.source.try_seek(pos)?;
+        current.frames = duration_to_frames(pos);
+        if let Some(total) = current.total_frames {
+            current.frames = current.frames.min(total);
+        }
+        Ok(())
+    }
+    /// Loops a section of the current track, or stops looping with `None`.
+    pub fn set_loop(&self, region: Option<LoopRegion>) {
+        if let Some(current) = &mut self.state.lock().unwrap().current {
+            current.loop_region = region;
+        }
+    }
+    pub fn loop_region(&self) -> Option<LoopRegion> {
+        let state = self.state.lock().unwrap();
+        state.current.as_ref().and_then(|t| t.loop_region)
+    }
+    pub fn crossfade(&self) -> Duration {
+        self.state.lock().unwrap().crossfade
+    }
+    pub fn chunks_mixed(&self) -> u64 {
+        self.state.lock().unwrap().chunks
+    }
+    pub fn set_crossfade(&self, crossfade: Duration) {
+        self.state.lock().unwrap().crossfade = crossfade;
+    }
+}
+
+pub struct MixerSource {
+    state: Arc<Mutex<MixerState>>,
+    buffer: Vec<f32>,
+    pos: usize,
+}
+
+impl MixerSource {
+    fn refill(&mut self) {
+        self.buffer.clear();
+        self.pos = 0;
+        let mut state = self.state.lock().unwrap();
+        state.chunks += 1;
+        for _ in 0..CHUNK_FRAMES {
+            self.buffer.extend(state.mix_frame());
+        }
+    }
+}
+
+impl Iterator for MixerSource {
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.pos >= self.buffer.len() {
+            self.refill();
+        }
+        let sample = self.buffer[self.pos];
+        self.pos += 1;
+        Some(sample)
+    }
+}
+
+impl Source for MixerSource {
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        CHANNELS
+    }
+    fn sample_rate(&self) -> u32 {
+        SAMPLE_RATE
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+fn duration_to_frames(d: Duration) -> u64 {
+    (d.as_secs_f64() * SAMPLE_RATE as f64) as u64
+}
+
+fn frames_to_duration(frames: u64) -> Duration {
+    Duration::from_secs_f64(frames as f64 / SAMPLE_RATE as f64)
+}
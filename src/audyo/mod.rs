@@ -1 +1,17 @@
+pub mod ab_loop;
+pub mod backend;
+pub mod biquad;
+pub mod cache;
+pub mod equalizer;
+pub mod limiter;
+pub mod loudness;
+pub mod lyrics;
+pub mod metadata;
+pub mod mixer;
+pub mod null_output;
+pub mod output;
 pub mod service;
+pub mod shuffle;
+pub mod sleep;
+pub mod stretch;
+pub mod tap;
@@ -0,0 +1,173 @@
+use std::{
+    path::PathBuf,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    thread::{self, JoinHandle},
+    time::{Duration, Instant},
+};
+
+use hound::{SampleFormat, WavSpec, WavWriter};
+
+use crate::audyo::backend::{AudioBackend, PipelineSource};
+
+/// Frames pulled at a time.
+const CHUNK_FRAMES: usize = 1024;
+const PAUSE_POLL: Duration = Duration::from_millis(10);
+
+#[derive(Default)]
+struct Controls {
+    paused: AtomicBool,
+    stopped: AtomicBool,
+    volume: Mutex<f32>,
+}
+
+/// Backend without a sound card. A thread pulls the audio `clock` times as
+/// fast as real time, or as fast as it can with a clock of 0, and records it
+/// to a WAV file when given one.
+pub struct NullBackend {
+    controls: Arc<Controls>,
+    clock: f32,
+    render: Option<PathBuf>,
+    worker: Option<JoinHandle<()>>,
+    /// Where the source waits for a `Stepper` instead of a worker thread.
+    stepped: Option<Arc<Mutex<Option<PipelineSource>>>>,
+}
+
+impl NullBackend {
+    pub fn new(clock: f32, render: Option<PathBuf>) -> Self {
+        let controls = Controls {
+            volume: Mutex::new(1.0),
+            ..Default::default()
+        };
+        Self {
+            controls: Arc::new(controls),
+            clock,
+            render,
+            worker: None,
+            stepped: None,
+        }
+    }
+    /// Backend that only plays when its `Stepper` says so, for tests that
+    /// need to know exactly how much audio has played.
+    #[cfg(test)]
+    pub fn stepped() -> (Self, Stepper) {
+        let mut backend = Self::new(0.0, None);
+        let source = Arc::new(Mutex::new(None));
+        backend.stepped = Some(source.clone());
+        let stepper = Stepper {
+            source,
+            controls: backend.controls.clone(),
+        };
+        (backend, stepper)
+    }
+}
+
+/// Pulls audio through a `NullBackend::stepped` on the calling thread.
+#[cfg(test)]
+pub struct Stepper {
+    source: Arc<Mutex<Option<PipelineSource>>>,
+    controls: Arc<Controls>,
+}
+
+#[cfg(test)]
+impl Stepper {
+    /// Plays `frames` frames, or nothing while paused or stopped. Returns
+    /// the frames played.
+    pub fn advance(&self, frames: usize) -> usize {
+        if self.controls.paused.load(Ordering::Relaxed)
+            || self.controls.stopped.load(Ordering::Relaxed)
+        {
+            return 0;
+        }
+        let mut source = self.source.lock().unwrap();
+        let Some(source) = source.as_mut() else {
+            return 0;
+        };
+        let channels = source.channels().max(1) as usize;
+        source.by_ref().take(frames * channels).count() / channels
+    }
+}
+
+fn run(mut source: PipelineSource, controls: &Controls, clock: f32, render: Option<PathBuf>) {
+    let channels = source.channels().max(1);
+    let sample_rate = source.sample_rate();
+    let spec = WavSpec {
+        channels,
+        sample_rate,
+        bits_per_sample: 32,
+        sample_format: SampleFormat::Float,
+    };
+    let mut writer = render.and_then(|path| WavWriter::create(path, spec).ok());
+    let chunk_time = (clock > 0.0)
+        .then(|| Duration::from_secs_f64(CHUNK_FRAMES as f64 / sample_rate as f64 / clock as f64));
+    let mut deadline = Instant::now();
+    while !controls.stopped.load(Ordering::Relaxed) {
+        if controls.paused.load(Ordering::Relaxed) {
+            thread::sleep(PAUSE_POLL);
+            deadline = Instant::now();
+            continue;
+        }
+        let volume = *controls.volume.lock().unwrap();
+        for _ in 0..CHUNK_FRAMES * channels as usize {
+            let Some(sample) = source.next() else {
+                return;
+            };
+            if let Some(writer) = &mut writer
+                && writer.write_sample(sample * volume).is_err()
+            {
+                return;
+            }
+        }
+        if let Some(chunk_time) = chunk_time {
+            deadline += chunk_time;
+            if let Some(wait) = deadline.checked_duration_since(Instant::now()) {
+                thread::sleep(wait);
+            }
+        }
+    }
+}
+
+impl AudioBackend for NullBackend {
+    fn start(&mut self, source: PipelineSource) {
+        if let Some(stepped) = &self.stepped {
+            *stepped.lock().unwrap() = Some(source);
+            return;
+        }
+        let controls = self.controls.clone();
+        let (clock, render) = (self.clock, self.render.clone());
+        self.worker = Some(thread::spawn(move || run(source, &controls, clock, render)));
+    }
+    fn play(&self) {
+        self.controls.paused.store(false, Ordering::Relaxed);
+    }
+    fn pause(&self) {
+        self.controls.paused.store(true, Ordering::Relaxed);
+    }
+    fn is_paused(&self) -> bool {
+        self.controls.paused.load(Ordering::Relaxed)
+    }
+    fn volume(&self) -> f32 {
+        *self.controls.volume.lock().unwrap()
+    }
+    fn set_volume(&self, volume: f32) {
+        *self.controls.volume.lock().unwrap() = volume;
+    }
+    fn stop(&self) {
+        self.controls.stopped.store(true, Ordering::Relaxed);
+    }
+    fn device(&self) -> Option<&str> {
+        None
+    }
+}
+
+impl Drop for NullBackend {
+    /// Waits for the worker so a WAV being rendered gets finalized.
+    fn drop(&mut self) {
+        self.stop();
+        if let Some(worker) = self.worker.take() {
+            let _ = worker.join();
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use rodio::{
+    OutputStream, Sink,
+    cpal::{
+        Device,
+        traits::{DeviceTrait, HostTrait},
+    },
+};
+
+use crate::audyo::backend::{AudioBackend, PipelineSource};
+
+/// Names of the output devices the system currently offers.
+pub fn output_devices() -> Vec<String> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .map(|devices| devices.filter_map(|d| d.name().ok()).collect())
+        .unwrap_or_default()
+}
+
+fn find_device(name: &str) -> Option<Device> {
+    rodio::cpal::default_host()
+        .output_devices()
+        .ok()?
+        .find(|d| d.name().is_ok_and(|n| n == name))
+}
+
+/// Plays on a sound card through a rodio sink.
+pub struct RodioBackend {
+    _stream: OutputStream,
+    sink: Sink,
+    device: Option<String>,
+}
+
+impl RodioBackend {
+    /// Opens the device called `name`, or the default device when `name` is
+    /// `None` or can't be opened. `None` when there is no usable device.
+    pub fn open(name: Option<&str>) -> Option<Self> {
+        let host = rodio::cpal::default_host();
+        let candidates = name
+            .and_then(find_device)
+            .into_iter()
+            .chain(host.default_output_device());
+        for device in candidates {
+            let Ok((stream, handle)) = OutputStream::try_from_device(&device) else {
+                continue;
+            };
+            let Ok(sink) = Sink::try_new(&handle) else {
+                continue;
+            };
+            return Some(Self {
+                _stream: stream,
+                sink,
+                device: device.name().ok(),
+            });
+        }
+        None
+    }
+}
+
+impl AudioBackend for RodioBackend {
+    fn start(&mut self, source: PipelineSource) {
+        self.sink.append(source);
+    }
+    fn play(&self) {
+        self.sink.play();
+    }
+    fn pause(&self) {
+        self.sink.pause();
+    }
+    fn is_paused(&self) -> bool {
+        self.sink.is_paused()
+    }
+    fn volume(&self) -> f32 {
+        self.sink.volume()
+    }
+    fn set_volume(&self, volume: f32) {
+        self.sink.set_volume(volume);
+    }
+    fn stop(&self) {
+        self.sink.stop();
+    }
+    fn device(&self) -> Option<&str> {
+        self.device.as_deref()
+    }
+}
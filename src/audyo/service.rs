@@ -1,23 +1,122 @@
-use std::{fs::File, io::BufReader, time::Duration};
+use std::{
+    collections::{HashMap, HashSet},
+    fs::File,
+    io::{self, BufReader},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::{Duration, Instant},
+};
 
-use rand::Rng;
-use rodio::{Decoder, OutputStream, OutputStreamHandle, Sink, Source};
+use rodio::{Decoder, Source, source::SeekError};
+use serde::{Deserialize, Serialize};
 
-use crate::app::LoopMode;
+use crate::{
+    app::LoopMode,
+    audyo::{
+        ab_loop::{AbSection, AbSections},
+        backend::{AudioBackend, BackendKind, open_backend},
+        cache,
+        equalizer::Equalizer,
+        limiter::Limiter,
+        loudness::{Loudness, LoudnessScanner, Normalization, ScanResult},
+        mixer::{LoopRegion, Mixer, Track},
+        output::output_devices,
+        shuffle::Shuffle,
+        sleep::SleepTimer,
+        stretch::TimeStretch,
+        tap::{SampleTap, Tapped},
+    },
+    config::Config,
+    library_index::LibraryIndex,
+};
+
+/// How long before the end of the current track the next one gets queued.
+const PRELOAD_AHEAD: Duration = Duration::from_secs(10);
+const CROSSFADE_STEP: Duration = Duration::from_secs(1);
+const MAX_CROSSFADE: Duration = Duration::from_secs(12);
+const SPEED_STEP: f32 = 0.25;
+const WAVEFORM_PEAKS_PER_SECOND: usize = 20;
+const MIN_SPEED: f32 = 0.5;
+const MAX_SPEED: f32 = 3.0;
+/// Normalization never boosts or cuts a track by more than this.
+const MAX_GAIN_DB: f64 = 12.0;
+const MIN_GAIN_DB: f64 = -24.0;
+/// An output that pulled no audio for this long while playing is considered
+/// gone.
+const OUTPUT_STALL: Duration = Duration::from_secs(2);
+/// Long files left within this of either end start from the beginning.
+const RESUME_MARGIN: Duration = Duration::from_secs(30);
 
 pub struct AudioService {
-    _stream: OutputStream,
-    _stream_handle: OutputStreamHandle,
-    sink: Sink,
+    output: Box<dyn AudioBackend>,
+    /// Settings the output was opened with, to reopen it the same way.
+    output_config: Config,
+    /// Device picked by the user; the default device is used when unset or
+    /// unavailable.
+    preferred_device: Option<String>,
+    /// Mixer chunk count at the last liveness check of the output.
+    output_check: (u64, Instant),
+    mixer: Mixer,
+    tap: SampleTap,
     pub audio_event: AudioEvent,
-    speed: f32,
+    speed: Arc<Mutex<f32>>,
     pub length: usize,
     pub current_audio: Option<String>,
-    pub current_playlist_index: usize,
+    /// Position of the current track in `queue`.
+    pub queue_index: usize,
     pub current_volume: f32,
-    pub playlist: Vec<String>,
-    pub loop_mode: LoopMode,
+    /// Tracks to play, in order; separate from the library.
+    pub queue: Vec<String>,
+    /// Every file in the library, for background loudness scans.
+    library: Vec<String>,
+    loop_mode: LoopMode,
+    /// Mode the current track started in, which `StopAfterCurrent` goes
+    /// back to once it has stopped.
+    resume_mode: LoopMode,
+    /// Times the current track has started in a row.
+    plays: u32,
+    shuffle: Shuffle,
     pub waveform: WaveFormData,
+    preloaded: Option<Preloaded>,
+    scanner: LoudnessScanner,
+    loudness: HashMap<String, ScanResult>,
+    /// Loudness from the library index, a previous scan's or YouTube's,
+    /// used until a file has been scanned in this run.
+    known_loudness: HashMap<String, f64>,
+    /// Album of each library file, from the library index.
+    albums: HashMap<String, String>,
+    /// Scans finished since the last `take_measured_loudness`.
+    measured: Vec<(String, Option<f64>)>,
+    normalization: Normalization,
+    target_lufs: f64,
+    limiter: Arc<AtomicBool>,
+    limiter_threshold_db: f32,
+    equalizer: Equalizer,
+    /// Point A while waiting for B to be marked.
+    ab_start: Option<Duration>,
+    ab_lead_in: Duration,
+    ab_plays: Option<u32>,
+    ab_sections: AbSections,
+    /// Saved section of the current file recalled last.
+    ab_section_index: Option<usize>,
+    sleep_timer: Option<SleepTimer>,
+    /// How long the volume takes to fade out before the sleep timer pauses.
+    sleep_fade: Duration,
+    muted: bool,
+    /// Where long files were left off, in seconds.
+    resume_points: HashMap<String, f64>,
+    resume_min_length: usize,
+    /// Position the next `restart_with` of that path starts from.
+    start_at: Option<(String, Duration)>,
+}
+
+/// A track queued in the mixer behind the one currently playing.
+struct Preloaded {
+    index: usize,
+    path: String,
+    length: usize,
 }
 
 #[derive(Debug, Default, Clone, PartialEq, Eq)]
@@ -28,188 +127,1204 @@ pub enum AudioEvent {
 }
 
 impl AudioService {
-    pub fn new() -> Self {
-        let (_stream, _stream_handle) =
-            OutputStream::try_default().expect("Can not init OutputStream");
-        let sink = Sink::try_new(&_stream_handle).expect("Can not init Sink and PlayError");
-        sink.pause();
-        let cur_vol = sink.volume();
-        Self {
-            _stream,
-            _stream_handle,
-            sink,
+    pub fn new(config: &Config) -> Self {
+        let output = open_backend(config, config.output_device.as_deref());
+        Self::with_backend(config, output)
+    }
+    /// Service playing into `output`, e.g. a `NullBackend` on machines
+    /// without sound hardware.
+    pub fn with_backend(config: &Config, output: Box<dyn AudioBackend>) -> Self {
+        output.pause();
+        let cur_vol = output.volume();
+        let mut service = Self {
+            output,
+            output_config: config.clone(),
+            preferred_device: config.output_device.clone(),
+            output_check: (0, Instant::now()),
+            mixer: Mixer::new(),
+            tap: SampleTap::new(),
             audio_event: AudioEvent::default(),
-            speed: 1.0,
+            speed: Arc::new(Mutex::new(1.0)),
             length: 1,
             current_audio: None,
             current_volume: cur_vol,
-            current_playlist_index: 0,
-            playlist: Vec::new(),
+            queue_index: 0,
+            queue: Vec::new(),
+            library: Vec::new(),
             loop_mode: LoopMode::Single,
-            waveform: WaveFormData {
-                samples: Vec::new(),
-                sample_rate: 0,
-                durations: 0,
-            },
+            resume_mode: LoopMode::Single,
+            plays: 0,
+            shuffle: Shuffle::default(),
+            waveform: WaveFormData::default(),
+            preloaded: None,
+            scanner: LoudnessScanner::new(),
+            loudness: HashMap::new(),
+            known_loudness: HashMap::new(),
+            albums: HashMap::new(),
+            measured: Vec::new(),
+            normalization: Normalization::Off,
+            target_lufs: config.target_lufs,
+            limiter: Arc::new(AtomicBool::new(false)),
+            limiter_threshold_db: config.limiter_threshold_db,
+            ab_start: None,
+            ab_lead_in: Duration::from_secs_f64(config.ab_lead_in_secs.max(0.0)),
+            ab_plays: config.ab_plays,
+            ab_sections: AbSections::load(),
+            ab_section_index: None,
+            sleep_timer: None,
+            sleep_fade: Duration::from_secs(config.sleep_fade_secs),
+            muted: false,
+            resume_points: HashMap::new(),
+            resume_min_length: config.resume_min_minutes as usize * 60,
+            start_at: None,
+            equalizer: Equalizer::new(config.equalizer.clone(), config.eq_overrides.clone()),
+        };
+        service.attach_pipeline();
+        service.set_normalization(config.normalization);
+        service
+    }
+    /// Feeds the mixer into the output's sink through the shared effects.
+    fn attach_pipeline(&mut self) {
+        let limited = Limiter::new(
+            self.mixer.source(),
+            self.limiter_threshold_db,
+            self.limiter.clone(),
+        );
+        self.output.start(Box::new(Tapped::new(
+            TimeStretch::new(limited, self.speed.clone()),
+            self.tap.clone(),
+        )));
+    }
+    /// Name of the device audio is playing on.
+    pub fn output_device(&self) -> Option<&str> {
+        self.output.device()
+    }
+    pub fn output_devices(&self) -> Vec<String> {
+        output_devices()
+    }
+    /// Moves playback to the device called `name`, or the default device
+    /// with `None`. The track carries on from the same position since the
+    /// mixer is only reattached. Returns whether the requested device could
+    /// be opened.
+    pub fn set_output_device(&mut self, name: Option<String>) -> bool {
+        self.preferred_device = name;
+        self.reopen_output();
+        self.preferred_device.is_none() || self.output.device() == self.preferred_device.as_deref()
+    }
+    /// Only sound cards are reopened; the null backend can't go away.
+    fn reopen_output(&mut self) {
+        if self.output_config.backend != BackendKind::Rodio {
+            return;
+        }
+        let paused = self.output.is_paused();
+        let volume = self.output.volume();
+        self.output.stop();
+        self.output = open_backend(&self.output_config, self.preferred_device.as_deref());
+        self.output.set_volume(volume);
+        if paused {
+            self.output.pause();
+        }
+        self.attach_pipeline();
+        self.output_check = (self.mixer.chunks_mixed(), Instant::now());
+    }
+    /// Reopens the output when it stopped pulling audio while playing, which
+    /// is what happens when the device is unplugged. Falls back to the
+    /// default device if the chosen one is gone. Returns the device now in
+    /// use when it changed.
+    pub fn check_output(&mut self) -> Option<Option<String>> {
+        let chunks = self.mixer.chunks_mixed();
+        if self.output.is_paused() || chunks != self.output_check.0 {
+            self.output_check = (chunks, Instant::now());
+            return None;
         }
+        if self.output_check.1.elapsed() < OUTPUT_STALL {
+            return None;
+        }
+        let before = self.output.device().map(str::to_string);
+        self.reopen_output();
+        let after = self.output.device().map(str::to_string);
+        (after != before).then_some(after)
     }
     pub fn play(&mut self) {
-        self.sink.play();
+        self.output.play();
     }
-    fn append_source_to_sink_from_file(&mut self, f: String) {
-        let Ok(file) = File::open(&f) else {
-            return;
-        };
+    /// Opens and decodes `f` into a track for the mixer along with its length
+    /// in seconds, or `None` if the file can't be played.
+    fn append_source_to_sink_from_file(&self, f: &str) -> Option<(Track, usize)> {
+        let file = File::open(f).ok()?;
         let buf_reader = BufReader::new(file);
-        let Ok(source) = Decoder::new(buf_reader) else {
-            return;
-        };
-        self.length = if let Some(d) = source.total_duration() {
+        let source = self.equalizer.apply(f, Decoder::new(buf_reader).ok()?);
+        let length = if let Some(d) = source.total_duration() {
             d.as_secs() as usize
         } else {
             0
         };
-        self.sink.append(source);
+        if self.normalization != Normalization::Off && !self.loudness.contains_key(f) {
+            self.scanner.request(f, true);
+        }
+        let track = Track::new(f.to_string(), source)
+            .gain(self.gain_for(f))
+            .start_at(self.resume_point(f));
+        Some((track, length))
     }
-    pub fn single_mode(&mut self) {
-        if self.playlist.is_empty() {
-            return;
+    /// Switches playback to `f`, crossfading out of the current track when
+    /// a crossfade is configured.
+    fn restart_with(&mut self, f: String) {
+        let crossfade = self
+            .current_audio
+            .as_deref()
+            .is_some_and(|cur| self.should_crossfade(cur, &f));
+        if self.current_audio.is_some() {
+            self.audio_event = AudioEvent::Play;
+            self.output.play();
+        }
+        self.preloaded = None;
+        self.ab_start = None;
+        self.ab_section_index = None;
+        self.remember_position();
+        self.track_started(&f);
+        match self.append_source_to_sink_from_file(&f) {
+            Some((track, length)) => {
+                self.length = length;
+                self.mixer.play_now(track, crossfade);
+            }
+            None => {
+                self.length = 0;
+                self.mixer.clear();
+            }
+        }
+        self.start_at = None;
+        self.current_audio = Some(f);
+    }
+    /// Keeps the mixer fed according to `loop_mode`. The next track is decoded
+    /// and queued behind the current one shortly before it ends so playback
+    /// moves on without a gap. Returns the new playlist index when playback
+    /// advanced to another track.
+    pub fn playlist_mode(&mut self) -> Option<usize> {
+        self.poll_loudness_scans();
+        if self.queue.is_empty() {
+            return None;
         }
-        let f = self.playlist[self.current_playlist_index].clone();
-        if let Some(cur) = &self.current_audio {
-            if f != *cur {
-                self.stop();
-                self.sink =
-                    Sink::try_new(&self._stream_handle).expect("Can not init Sink and PlayError");
-                self.current_audio = Some(f.clone());
-                self.audio_event = AudioEvent::Play;
-                self.append_source_to_sink_from_file(f);
-            } else {
-                if self.sink.len() < 1 {
-                    self.append_source_to_sink_from_file(f);
+        if self.queue_index >= self.queue.len() {
+            self.queue_index = 0;
+        }
+        let f = self.queue[self.queue_index].clone();
+        if self.current_audio.as_ref() != Some(&f) {
+            self.restart_with(f);
+            return None;
+        }
+        if self.preloaded.is_some() && self.mixer.take_advanced() {
+            self.count_sleep_track();
+            return self.on_preloaded_started();
+        }
+        let last_track = self.sleep_timer == Some(SleepTimer::Tracks(0));
+        let stopping = self.stops_after_current();
+        if self.mixer.is_idle() {
+            if last_track {
+                return None;
+            }
+            if stopping {
+                return self.stop_after_track();
+            }
+            self.count_sleep_track();
+            let next = self.next_track_index();
+            self.queue_index = next;
+            let f = self.queue[next].clone();
+            self.preloaded = None;
+            self.forget_position();
+            self.track_started(&f);
+            self.length = match self.append_source_to_sink_from_file(&f) {
+                Some((track, length)) => {
+                    self.mixer.play_now(track, false);
+                    length
                 }
+                None => 0,
+            };
+            self.current_audio = Some(f);
+            return Some(next);
+        }
+        if self.preloaded.is_none()
+            && !last_track
+            && !stopping
+            && self.remaining() <= PRELOAD_AHEAD + self.mixer.crossfade()
+        {
+            self.preload_next();
+        }
+        None
+    }
+    /// Whether `loop_mode` stops playback once the current track ends.
+    fn stops_after_current(&self) -> bool {
+        match self.loop_mode {
+            LoopMode::TrackOnce | LoopMode::StopAfterCurrent => true,
+            LoopMode::ListOnce => self.queue_index + 1 >= self.queue.len(),
+            LoopMode::Single | LoopMode::Playlist | LoopMode::Shuffle | LoopMode::Repeat(_) => {
+                false
             }
+        }
+    }
+    /// Pauses at the end of the track, with the track that plays next
+    /// loaded from its start. Returns the new queue index.
+    fn stop_after_track(&mut self) -> Option<usize> {
+        self.audio_event = AudioEvent::Pause;
+        self.output.pause();
+        self.forget_position();
+        self.queue_index = self.next_track_index();
+        if self.loop_mode == LoopMode::StopAfterCurrent {
+            self.loop_mode = self.resume_mode;
+        }
+        // Reloaded by the next call even when it is the same track.
+        self.current_audio = None;
+        Some(self.queue_index)
+    }
+    fn preload_next(&mut self) {
+        let index = self.next_track_index();
+        let path = self.queue[index].clone();
+        let crossfade = self
+            .current_audio
+            .as_deref()
+            .is_some_and(|cur| self.should_crossfade(cur, &path));
+        if let Some((track, length)) = self.append_source_to_sink_from_file(&path) {
+            self.mixer.queue_next(track, crossfade);
+            self.preloaded = Some(Preloaded {
+                index,
+                path,
+                length,
+            });
+        }
+    }
+    /// The mixer has moved on to the preloaded track. The queue may have
+    /// changed since it was preloaded, so the index is looked up by path if
+    /// it no longer matches.
+    fn on_preloaded_started(&mut self) -> Option<usize> {
+        let preloaded = self.preloaded.take()?;
+        self.forget_position();
+        self.length = preloaded.length;
+        self.track_started(&preloaded.path);
+        let index = if self.queue.get(preloaded.index) == Some(&preloaded.path) {
+            Some(preloaded.index)
         } else {
-            self.current_audio = Some(f.clone());
-            self.append_source_to_sink_from_file(f);
+            self.queue.iter().position(|p| *p == preloaded.path)
+        };
+        self.current_audio = Some(preloaded.path);
+        if let Some(index) = index {
+            self.queue_index = index;
         }
+        index
     }
-    pub fn playlist_mode(&mut self) -> Option<usize> {
-        if self.playlist.is_empty() {
+    /// Where `path` starts playing: the position it was left at if it is a
+    /// long file, otherwise the beginning.
+    fn resume_point(&self, path: &str) -> Duration {
+        match &self.start_at {
+            Some((p, pos)) if p == path => *pos,
+            _ => self
+                .resume_points
+                .get(path)
+                .map_or(Duration::ZERO, |&secs| Duration::from_secs_f64(secs)),
+        }
+    }
+    /// Saves where the current track is if it is long enough to be resumed.
+    /// Positions at the very start or end aren't worth resuming from.
+    pub fn remember_position(&mut self) {
+        let Some(path) = self.current_audio.clone() else {
+            return;
+        };
+        if self.length < self.resume_min_length {
+            return;
+        }
+        let pos = self.mixer.position();
+        if pos < RESUME_MARGIN || self.remaining() < RESUME_MARGIN {
+            self.resume_points.remove(&path);
+        } else {
+            self.resume_points.insert(path, pos.as_secs_f64());
+        }
+    }
+    /// The current track played to the end, so it starts over next time.
+    fn forget_position(&mut self) {
+        if let Some(path) = &self.current_audio {
+            self.resume_points.remove(path);
+        }
+    }
+    pub fn resume_points(&self) -> &HashMap<String, f64> {
+        &self.resume_points
+    }
+    pub fn set_resume_points(&mut self, points: HashMap<String, f64>) {
+        self.resume_points = points;
+    }
+    /// Makes `path` start at `pos` the next time it is loaded, e.g. when
+    /// restoring the last session.
+    pub fn start_at(&mut self, path: String, pos: Duration) {
+        self.start_at = Some((path, pos));
+    }
+    /// Records the move from the current track to `path` for shuffle and
+    /// its history.
+    fn track_started(&mut self, path: &str) {
+        if let Some(previous) = self.current_audio.clone()
+            && previous != path
+        {
+            self.shuffle.push_history(previous);
+        }
+        if self.current_audio.as_deref() == Some(path) {
+            self.plays += 1;
+        } else {
+            self.plays = 1;
+        }
+        if self.loop_mode != LoopMode::StopAfterCurrent {
+            self.resume_mode = self.loop_mode;
+        }
+        self.shuffle.started(path);
+    }
+    /// Consecutive tracks of the same album are meant to run into each other,
+    /// so they are never crossfaded.
+    fn should_crossfade(&self, from: &str, to: &str) -> bool {
+        if self.mixer.crossfade().is_zero() {
+            return false;
+        }
+        match (self.album(from), self.album(to)) {
+            (Some(a), Some(b)) => a != b,
+            _ => true,
+        }
+    }
+    /// Album of `path` as the library index or a loudness scan saw it,
+    /// without opening the file.
+    fn album(&self, path: &str) -> Option<&String> {
+        self.albums
+            .get(path)
+            .or_else(|| self.loudness.get(path)?.album.as_ref())
+    }
+    /// Sets the files scanned in the background, with the loudness and
+    /// albums the library index knows for them.
+    pub fn set_library(&mut self, library: Vec<String>, index: &LibraryIndex) {
+        self.library = library;
+        self.known_loudness = index.known_loudness();
+        self.albums = index.albums();
+        self.request_loudness_scans();
+    }
+    /// The queue changed; whatever was preloaded may no longer come next.
+    pub fn loop_mode(&self) -> LoopMode {
+        self.loop_mode
+    }
+    /// Switches the loop mode. A track already queued behind the current
+    /// one was picked by the old mode, so it is dropped and picked again.
+    pub fn set_loop_mode(&mut self, mode: LoopMode) {
+        self.loop_mode = mode;
+        self.drop_preloaded();
+    }
+    fn drop_preloaded(&mut self) {
+        self.preloaded = None;
+        self.mixer.clear_next();
+    }
+    /// Queues `path` right after the current track.
+    pub fn play_next(&mut self, path: String) {
+        let at = if self.queue.is_empty() {
+            0
+        } else {
+            self.queue_index + 1
+        };
+        self.shuffle.play_next(&path);
+        self.queue.insert(at, path);
+        self.drop_preloaded();
+    }
+    pub fn enqueue(&mut self, path: String) {
+        self.queue.push(path);
+        self.drop_preloaded();
+    }
+    /// Replaces the queue with `paths` and plays the first one.
+    pub fn replace_queue(&mut self, paths: Vec<String>) {
+        self.queue = paths;
+        self.play_queue_from(0);
+    }
+    /// Plays the queue starting at `index`.
+    pub fn play_queue_from(&mut self, index: usize) {
+        if index >= self.queue.len() {
+            return;
+        }
+        self.queue_index = index;
+        self.remember_position();
+        if let Some(previous) = self.current_audio.take() {
+            self.shuffle.push_history(previous);
+        }
+        self.audio_event = AudioEvent::Play;
+        self.output.play();
+        self.drop_preloaded();
+    }
+    /// Skips to the next track: the next one in queue order, or the next
+    /// in the shuffled order when shuffling. Returns the new queue index.
+    pub fn skip_next(&mut self) -> Option<usize> {
+        if self.queue.is_empty() {
             return None;
         }
-        if self.sink.len() < 1 {
-            self.change_track_index();
-            let f = self.playlist[self.current_playlist_index].clone();
-            self.current_audio = Some(f.clone());
-            self.append_source_to_sink_from_file(f);
-            return Some(self.current_playlist_index);
-        } else if self.sink.len() >= 1 {
-            let f = self.playlist[self.current_playlist_index].clone();
-            if let Some(cur) = &self.current_audio {
-                if f != *cur {
-                    self.stop();
-                    self.sink = Sink::try_new(&self._stream_handle)
-                        .expect("Can not init Sink and PlayError");
-                    self.current_audio = Some(f.clone());
-                    self.audio_event = AudioEvent::Play;
-                    self.append_source_to_sink_from_file(f);
+        let index = match self.loop_mode {
+            LoopMode::Shuffle => self.next_track_index(),
+            _ => (self.queue_index + 1) % self.queue.len(),
+        };
+        self.play_queue_from(index);
+        Some(index)
+    }
+    /// Goes back to the previous track. When shuffling that is the track
+    /// played before this one rather than the one above it in the queue.
+    pub fn skip_previous(&mut self) -> Option<usize> {
+        let len = self.queue.len();
+        if len == 0 {
+            return None;
+        }
+        if self.loop_mode == LoopMode::Shuffle {
+            let current = self.current_audio.clone();
+            while let Some(path) = self.shuffle.back(current.as_deref()) {
+                if let Some(index) = self.queue.iter().position(|p| *p == path) {
+                    // Going back isn't a new entry in the history.
+                    self.remember_position();
+                    self.current_audio = None;
+                    self.play_queue_from(index);
+                    return Some(index);
                 }
             }
         }
-        return None;
+        let index = if self.queue_index == 0 {
+            len - 1
+        } else {
+            self.queue_index - 1
+        };
+        self.play_queue_from(index);
+        Some(index)
+    }
+    /// Removes entry `index`. Removing the current track moves on to the one
+    /// after it; an empty queue stops playback.
+    pub fn remove_from_queue(&mut self, index: usize) {
+        if index >= self.queue.len() {
+            return;
+        }
+        self.queue.remove(index);
+        if self.queue.is_empty() {
+            self.clear_queue();
+            return;
+        }
+        if index < self.queue_index {
+            self.queue_index -= 1;
+        } else if index == self.queue_index && index == self.queue.len() {
+            self.queue_index = 0;
+        }
+        self.drop_preloaded();
     }
-    fn change_track_index(&mut self) {
-        if self.loop_mode == LoopMode::Playlist {
-            if self.current_playlist_index == self.playlist.len() - 1 {
-                self.current_playlist_index = 0;
-            } else {
-                self.current_playlist_index += 1;
+    /// Drops files deleted from disk from the queue, except the entry
+    /// playing now, which keeps going from memory. Returns the removed
+    /// indices, highest first.
+    pub fn forget_tracks(&mut self, gone: &HashSet<String>) -> Vec<usize> {
+        let mut removed = Vec::new();
+        for i in (0..self.queue.len()).rev() {
+            let playing =
+                i == self.queue_index && self.current_audio.as_ref() == Some(&self.queue[i]);
+            if gone.contains(&self.queue[i]) && !playing {
+                self.remove_from_queue(i);
+                removed.push(i);
             }
-        } else if self.loop_mode == LoopMode::Shuffle {
-            loop {
-                let new_idx = rand::rng().random_range(0..self.playlist.len());
-                if new_idx != self.current_playlist_index {
-                    self.current_playlist_index = new_idx;
-                    break;
-                }
+        }
+        removed
+    }
+    /// Follows `from` being renamed to `to` on disk, so the current track
+    /// and its queue entries stay put.
+    pub fn rename_track(&mut self, from: &str, to: &str) {
+        for path in self.queue.iter_mut().chain(self.library.iter_mut()) {
+            if path == from {
+                *path = to.to_string();
+            }
+        }
+        if self.current_audio.as_deref() == Some(from) {
+            self.current_audio = Some(to.to_string());
+        }
+        if let Some(preloaded) = &mut self.preloaded
+            && preloaded.path == from
+        {
+            preloaded.path = to.to_string();
+        }
+        if let Some(mut result) = self.loudness.remove(from) {
+            result.path = to.to_string();
+            self.loudness.insert(to.to_string(), result);
+        }
+        if let Some(lufs) = self.known_loudness.remove(from) {
+            self.known_loudness.insert(to.to_string(), lufs);
+        }
+        if let Some(album) = self.albums.remove(from) {
+            self.albums.insert(to.to_string(), album);
+        }
+        if let Some(point) = self.resume_points.remove(from) {
+            self.resume_points.insert(to.to_string(), point);
+        }
+    }
+    /// Moves entry `from` to `to`, keeping track of the current one.
+    pub fn move_in_queue(&mut self, from: usize, to: usize) {
+        if from >= self.queue.len() || to >= self.queue.len() {
+            return;
+        }
+        let path = self.queue.remove(from);
+        self.queue.insert(to, path);
+        if self.queue_index == from {
+            self.queue_index = to;
+        } else if from < self.queue_index && to >= self.queue_index {
+            self.queue_index -= 1;
+        } else if from > self.queue_index && to <= self.queue_index {
+            self.queue_index += 1;
+        }
+        self.drop_preloaded();
+    }
+    /// Empties the queue and stops playback.
+    pub fn clear_queue(&mut self) {
+        self.remember_position();
+        self.queue.clear();
+        self.queue_index = 0;
+        self.current_audio = None;
+        self.length = 0;
+        self.preloaded = None;
+        self.mixer.clear();
+        self.audio_event = AudioEvent::Pause;
+        self.output.pause();
+    }
+    pub fn normalization(&self) -> Normalization {
+        self.normalization
+    }
+    pub fn set_normalization(&mut self, normalization: Normalization) {
+        self.normalization = normalization;
+        self.limiter
+            .store(normalization != Normalization::Off, Ordering::Relaxed);
+        if let Some(cur) = &self.current_audio
+            && !self.loudness.contains_key(cur)
+        {
+            self.scanner.request(cur, true);
+        }
+        self.request_loudness_scans();
+        self.apply_gains();
+    }
+    /// Album gain needs every track of the album, so the whole library is
+    /// scanned in the background while normalization is on.
+    fn request_loudness_scans(&self) {
+        if self.normalization == Normalization::Off {
+            return;
+        }
+        for path in &self.library {
+            if !self.loudness.contains_key(path) {
+                self.scanner.request(path, false);
             }
         }
     }
-    fn stop(&mut self) {
-        self.sink.stop();
+    fn poll_loudness_scans(&mut self) {
+        let mut updated = false;
+        while let Some(result) = self.scanner.try_recv() {
+            let lufs = result.loudness.as_ref().map(|l| l.integrated);
+            self.measured.push((result.path.clone(), lufs));
+            self.loudness.insert(result.path.clone(), result);
+            updated = true;
+        }
+        if updated {
+            self.apply_gains();
+        }
+    }
+    /// Integrated loudness of the files scanned since the last call, for
+    /// the library index.
+    pub fn take_measured_loudness(&mut self) -> Vec<(String, Option<f64>)> {
+        std::mem::take(&mut self.measured)
+    }
+    fn apply_gains(&self) {
+        let loaded = self
+            .current_audio
+            .iter()
+            .chain(self.preloaded.as_ref().map(|p| &p.path));
+        for path in loaded {
+            self.mixer.set_gain(path, self.gain_for(path));
+        }
+    }
+    /// Linear gain bringing `path` to the target loudness. Until the file has
+    /// been scanned, the library index's figure stands in, or unity without
+    /// one.
+    fn gain_for(&self, path: &str) -> f32 {
+        let track = || {
+            let scanned = self.loudness.get(path).map(|r| r.loudness.as_ref());
+            match scanned {
+                Some(loudness) => loudness.map(|l| l.integrated),
+                None => self.known_loudness.get(path).copied(),
+            }
+        };
+        let lufs = match self.normalization {
+            Normalization::Off => None,
+            Normalization::Track => track(),
+            Normalization::Album => self.album_loudness(path).or_else(track),
+        };
+        let Some(lufs) = lufs else {
+            return 1.0;
+        };
+        let gain_db = (self.target_lufs - lufs).clamp(MIN_GAIN_DB, MAX_GAIN_DB);
+        10f64.powf(gain_db / 20.0) as f32
+    }
+    fn album_loudness(&self, path: &str) -> Option<f64> {
+        let album = self.loudness.get(path)?.album.as_ref()?;
+        Loudness::combined(
+            self.loudness
+                .values()
+                .filter(|r| r.album.as_ref() == Some(album))
+                .filter_map(|r| r.loudness.as_ref()),
+        )
+    }
+    pub fn crossfade(&self) -> Duration {
+        self.mixer.crossfade()
+    }
+    pub fn increase_crossfade(&mut self) {
+        let crossfade = (self.mixer.crossfade() + CROSSFADE_STEP).min(MAX_CROSSFADE);
+        self.mixer.set_crossfade(crossfade);
+    }
+    pub fn decrease_crossfade(&mut self) {
+        let crossfade = self.mixer.crossfade().saturating_sub(CROSSFADE_STEP);
+        self.mixer.set_crossfade(crossfade);
+    }
+    fn remaining(&self) -> Duration {
+        if self.length == 0 {
+            return Duration::ZERO;
+        }
+        Duration::from_secs(self.length as u64).saturating_sub(self.mixer.position())
+    }
+    fn next_track_index(&mut self) -> usize {
+        match self.loop_mode {
+            LoopMode::Single | LoopMode::TrackOnce => self.queue_index,
+            LoopMode::Repeat(n) if self.plays < n => self.queue_index,
+            LoopMode::Playlist
+            | LoopMode::ListOnce
+            | LoopMode::StopAfterCurrent
+            | LoopMode::Repeat(_) => (self.queue_index + 1) % self.queue.len(),
+            LoopMode::Shuffle => self
+                .shuffle
+                .next(&self.queue, self.current_audio.as_deref())
+                .and_then(|next| self.queue.iter().position(|p| p == next))
+                .unwrap_or(self.queue_index),
+        }
     }
     pub fn pause(&mut self) {
-        self.sink.pause();
+        self.output.pause();
+    }
+    /// Samples on their way to the output device, for visualisation.
+    pub fn tap(&self) -> &SampleTap {
+        &self.tap
+    }
+    pub fn equalizer(&self) -> &Equalizer {
+        &self.equalizer
+    }
+    /// Playback speed; pitch is preserved by the time-stretch stage.
+    pub fn speed(&self) -> f32 {
+        *self.speed.lock().unwrap()
     }
     pub fn speed_up(&mut self) {
-        self.speed += 0.25;
-        self.sink.set_speed(self.speed);
+        let mut speed = self.speed.lock().unwrap();
+        *speed = (*speed + SPEED_STEP).min(MAX_SPEED);
     }
     pub fn speed_down(&mut self) {
-        self.speed -= 0.25;
-        self.sink.set_speed(self.speed);
+        let mut speed = self.speed.lock().unwrap();
+        *speed = (*speed - SPEED_STEP).max(MIN_SPEED);
     }
-    pub fn seek_forward(&mut self) {
-        let mut current = self.sink.get_pos();
-        if self.length > 5 && (current.as_secs() as usize) >= (self.length - 5) {
-            current = Duration::from_secs(self.length as u64);
+    /// Seeks within the current track, clamped to its length when known.
+    pub fn seek_to(&mut self, pos: Duration) -> Result<(), SeekError> {
+        let pos = if self.length > 0 {
+            pos.min(Duration::from_secs(self.length as u64))
         } else {
-            current += Duration::from_secs(5);
+            pos
+        };
+        self.mixer.try_seek(pos)
+    }
+    pub fn seek_forward(&mut self, step: Duration) -> Result<(), SeekError> {
+        self.seek_to(self.mixer.position() + step)
+    }
+    pub fn seek_backward(&mut self, step: Duration) -> Result<(), SeekError> {
+        self.seek_to(self.mixer.position().saturating_sub(step))
+    }
+    /// Seeks to `ratio` (`0.0..=1.0`) of the current track.
+    pub fn seek_to_ratio(&mut self, ratio: f64) -> Result<(), SeekError> {
+        let pos = Duration::from_secs_f64(self.length as f64 * ratio.clamp(0.0, 1.0));
+        self.seek_to(pos)
+    }
+    /// Marks A at the current position, then B, which starts looping the
+    /// section in between. Marking again stops the loop.
+    pub fn mark_ab(&mut self) {
+        let pos = self.mixer.position();
+        if self.mixer.loop_region().is_some() {
+            self.clear_ab_loop();
+            return;
+        }
+        match self.ab_start.take() {
+            None => self.ab_start = Some(pos),
+            Some(a) if a == pos => self.ab_start = Some(a),
+            Some(a) => self.start_ab_loop(a.min(pos), a.max(pos)),
         }
-        let _ = self.sink.try_seek(current);
     }
-    pub fn seek_backward(&mut self) {
-        let mut current = self.sink.get_pos();
-        if current.as_secs() < 5 {
-            current = Duration::from_secs(0)
-        } else {
-            current -= Duration::from_secs(5);
+    fn start_ab_loop(&mut self, a: Duration, b: Duration) {
+        self.mixer.set_loop(Some(LoopRegion {
+            a,
+            b,
+            lead_in: self.ab_lead_in,
+            plays: self.ab_plays,
+            done: 0,
+        }));
+    }
+    pub fn clear_ab_loop(&mut self) {
+        self.ab_start = None;
+        self.mixer.set_loop(None);
+    }
+    pub fn ab_start(&self) -> Option<Duration> {
+        self.ab_start
+    }
+    pub fn ab_loop(&self) -> Option<LoopRegion> {
+        self.mixer.loop_region()
+    }
+    /// Saves the section being looped for the current file. `false` when
+    /// nothing is looping.
+    pub fn save_ab_section(&mut self) -> io::Result<bool> {
+        let (Some(path), Some(region)) = (&self.current_audio, self.mixer.loop_region()) else {
+            return Ok(false);
+        };
+        self.ab_sections
+            .add(path, AbSection::new(region.a, region.b))?;
+        Ok(true)
+    }
+    /// Starts looping the next section saved for the current file.
+    pub fn next_saved_section(&mut self) -> Result<Option<AbSection>, SeekError> {
+        let Some(path) = &self.current_audio else {
+            return Ok(None);
+        };
+        let sections = self.ab_sections.get(path);
+        if sections.is_empty() {
+            return Ok(None);
+        }
+        let index = self
+            .ab_section_index
+            .map_or(0, |i| (i + 1) % sections.len());
+        let section = sections[index];
+        self.ab_section_index = Some(index);
+        self.ab_start = None;
+        self.start_ab_loop(section.a(), section.b());
+        self.seek_to(section.a().saturating_sub(self.ab_lead_in))?;
+        Ok(Some(section))
+    }
+    pub fn set_sleep_timer(&mut self, timer: Option<SleepTimer>) {
+        self.sleep_timer = timer;
+        // A track queued behind the current one would play past the timer.
+        self.drop_preloaded();
+        if !self.muted {
+            self.output.set_volume(self.current_volume);
+        }
+    }
+    pub fn sleep_timer(&self) -> Option<SleepTimer> {
+        self.sleep_timer
+    }
+    /// Real time left before the sleep timer pauses playback, when known.
+    pub fn sleep_remaining(&self) -> Option<Duration> {
+        match self.sleep_timer? {
+            SleepTimer::At(deadline) => Some(deadline.saturating_duration_since(Instant::now())),
+            SleepTimer::Tracks(0) if self.length > 0 => {
+                Some(self.remaining().div_f32(self.speed()))
+            }
+            SleepTimer::Tracks(_) => None,
+        }
+    }
+    fn count_sleep_track(&mut self) {
+        if let Some(SleepTimer::Tracks(n)) = &mut self.sleep_timer {
+            *n = n.saturating_sub(1);
         }
-        let _ = self.sink.try_seek(current);
+    }
+    /// Fades the volume out as the sleep timer runs down and pauses once it
+    /// expires, leaving `current_volume` in place for the next session.
+    /// Returns `true` when it paused playback.
+    pub fn tick_sleep_timer(&mut self) -> bool {
+        let Some(timer) = self.sleep_timer else {
+            return false;
+        };
+        let ended =
+            timer == SleepTimer::Tracks(0) && self.current_audio.is_some() && self.mixer.is_idle();
+        let remaining = self.sleep_remaining();
+        if !ended && remaining.is_none_or(|r| !r.is_zero()) {
+            if let Some(remaining) = remaining
+                && remaining < self.sleep_fade
+                && !self.muted
+            {
+                let level = remaining.as_secs_f32() / self.sleep_fade.as_secs_f32();
+                self.output.set_volume(self.current_volume * level * level);
+            }
+            return false;
+        }
+        // Paused before the volume comes back, so nothing plays at full
+        // volume after the fade.
+        self.audio_event = AudioEvent::Pause;
+        self.pause();
+        self.set_sleep_timer(None);
+        true
     }
     pub fn mute(&mut self) {
-        self.sink.set_volume(0.0);
+        self.muted = true;
+        self.output.set_volume(0.0);
     }
     pub fn unmute(&mut self) {
-        self.sink.set_volume(self.current_volume);
+        self.muted = false;
+        self.output.set_volume(self.current_volume);
+    }
+    pub fn set_volume(&mut self, volume: f32) {
+        self.current_volume = volume.clamp(0.0, 1.0);
+        if !self.muted {
+            self.output.set_volume(self.current_volume);
+        }
     }
     pub fn increase_vol(&mut self) {
-        self.current_volume = (self.current_volume + 0.1).min(1.0);
-        self.sink.set_volume(self.current_volume);
+        self.set_volume(self.current_volume + 0.1);
     }
     pub fn decrease_vol(&mut self) {
-        self.current_volume = (self.current_volume - 0.1).max(0.0);
-        self.sink.set_volume(self.current_volume);
+        self.set_volume(self.current_volume - 0.1);
     }
     pub fn get_current_position(&self) -> Duration {
-        Duration::from_secs(self.sink.get_pos().as_secs() % (self.length as u64))
+        self.mixer.position()
     }
 }
 
-struct WaveFormData {
-    samples: Vec<f32>,
-    sample_rate: usize,
-    durations: usize,
+/// Amplitude envelope of a track, `sample_rate` peaks per second of audio.
+#[derive(Debug, Default, Clone, Serialize, Deserialize)]
+pub struct WaveFormData {
+    pub samples: Vec<f32>,
+    pub sample_rate: usize,
+    pub durations: usize,
 }
 impl WaveFormData {
-    fn from_file(path: String) -> Self {
-        let file = File::open(path).expect("Can not file this file");
-        let source = Decoder::new(file).expect("Decoder Error");
-        let sample_rate = source.sample_rate() as usize;
+    const CACHE: &'static str = "waveform";
+
+    /// Reads the envelope from the on-disk cache, decoding the file on a miss.
+    /// Decoding a long file takes a while, so call this off the UI thread.
+    pub fn load(path: &str) -> Option<Self> {
+        let key = cache::file_key(path);
+        if let Some(key) = &key
+            && let Some(waveform) = cache::load(Self::CACHE, key)
+        {
+            return Some(waveform);
+        }
+        let waveform = Self::from_file(path)?;
+        if let Some(key) = &key {
+            cache::store(Self::CACHE, key, &waveform);
+        }
+        Some(waveform)
+    }
+    fn from_file(path: &str) -> Option<Self> {
+        let file = File::open(path).ok()?;
+        let source = Decoder::new(BufReader::new(file)).ok()?;
         let channels = source.channels() as usize;
+        let frames_per_peak = (source.sample_rate() as usize / WAVEFORM_PEAKS_PER_SECOND).max(1);
 
-        let samples: Vec<f32> = source
-            .map(|s| (s as f32 / i16::MAX as f32).abs())
-            .collect::<Vec<f32>>()
-            .chunks(channels)
-            .map(|ch| ch.iter().sum::<f32>() / channels as f32)
-            .collect();
-        let durations = samples.len() / sample_rate;
-        Self {
+        let mut samples = Vec::new();
+        let (mut peak, mut frame_sum, mut n) = (0.0f32, 0.0f32, 0);
+        for (i, s) in source.enumerate() {
+            frame_sum += (s as f32 / i16::MAX as f32).abs();
+            if (i + 1) % channels != 0 {
+                continue;
+            }
+            peak = peak.max(frame_sum / channels as f32);
+            frame_sum = 0.0;
+            n += 1;
+            if n == frames_per_peak {
+                samples.push(peak);
+                peak = 0.0;
+                n = 0;
+            }
+        }
+        if n > 0 {
+            samples.push(peak);
+        }
+        let durations = samples.len() / WAVEFORM_PEAKS_PER_SECOND;
+        Some(Self {
             samples,
-            sample_rate,
+            sample_rate: WAVEFORM_PEAKS_PER_SECOND,
             durations,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        fs,
+        path::{Path, PathBuf},
+    };
+
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use super::*;
+    use crate::audyo::{
+        backend::BackendKind,
+        mixer::SAMPLE_RATE,
+        null_output::{NullBackend, Stepper},
+    };
+
+    /// Audio played per tick, about as much as between two UI ticks.
+    const TICK_FRAMES: usize = SAMPLE_RATE as usize / 10;
+
+    /// Tone of `secs` seconds in `dir`.
+    fn track(dir: &Path, name: &str, secs: u32) -> String {
+        let path = dir.join(format!("{}.wav", name));
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(&path, spec).unwrap();
+        for i in 0..8000 * secs {
+            writer.write_sample(((i % 40) as i16 - 20) * 500).unwrap();
+        }
+        writer.finalize().unwrap();
+        path.display().to_string()
+    }
+
+    struct Player {
+        service: AudioService,
+        stepper: Stepper,
+        tracks: Vec<String>,
+        dir: PathBuf,
+    }
+
+    impl Player {
+        /// Service on a stepped null backend with tracks `a`, `b`, ...
+        /// lasting `secs` seconds each.
+        fn new(test: &str, secs: &[u32]) -> Self {
+            let dir = std::env::temp_dir().join(format!("audyo-{}-{}", test, std::process::id()));
+            fs::create_dir_all(&dir).unwrap();
+            let tracks = secs
+                .iter()
+                .zip('a'..)
+                .map(|(&secs, name)| track(&dir, &name.to_string(), secs))
+                .collect();
+            let mut config = Config::default();
+            config.backend = BackendKind::Null;
+            let (backend, stepper) = NullBackend::stepped();
+            let service = AudioService::with_backend(&config, Box::new(backend));
+            Self {
+                service,
+                stepper,
+                tracks,
+                dir,
+            }
         }
+        fn play(&mut self, loop_mode: LoopMode) {
+            self.service.set_loop_mode(loop_mode);
+            self.service.replace_queue(self.tracks.clone());
+            self.service.playlist_mode();
+        }
+        /// Plays up to `ticks` ticks and returns the name of each track that
+        /// started, stopping early once playback pauses.
+        fn run(&mut self, ticks: usize) -> Vec<String> {
+            let mut started = Vec::new();
+            let mut current = self.service.current_audio.clone();
+            for _ in 0..ticks {
+                if self.stepper.advance(TICK_FRAMES) == 0 {
+                    break;
+                }
+                self.service.tick_sleep_timer();
+                self.service.playlist_mode();
+                // A stopped track is only loaded again on the next tick.
+                if self.service.current_audio.is_some() && self.service.current_audio != current {
+                    current = self.service.current_audio.clone();
+                    started.push(self.name(current.as_deref().unwrap()));
+                }
+            }
+            started
+        }
+        fn name(&self, path: &str) -> String {
+            Path::new(path)
+                .file_stem()
+                .unwrap()
+                .to_string_lossy()
+                .into_owned()
+        }
+        fn current(&self) -> Option<String> {
+            self.service.current_audio.as_deref().map(|p| self.name(p))
+        }
+        fn paused(&self) -> bool {
+            self.service.output.is_paused()
+        }
+    }
+
+    impl Drop for Player {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.dir);
+        }
+    }
+
+    #[test]
+    fn playlist_advances_and_wraps() {
+        let mut player = Player::new("playlist", &[1, 1, 1]);
+        player.play(LoopMode::Playlist);
+        assert_eq!(player.current().as_deref(), Some("a"));
+        assert_eq!(player.run(45), ["b", "c", "a", "b"]);
+        assert!(!player.paused());
+    }
+
+    #[test]
+    fn single_repeats_the_track() {
+        let mut player = Player::new("single", &[1, 1]);
+        player.play(LoopMode::Single);
+        assert!(player.run(35).is_empty());
+        assert_eq!(player.current().as_deref(), Some("a"));
+        assert_eq!(player.service.queue_index, 0);
+        assert!(!player.paused());
+    }
+
+    #[test]
+    fn list_once_stops_after_the_last_track() {
+        let mut player = Player::new("list-once", &[1, 1]);
+        player.play(LoopMode::ListOnce);
+        assert_eq!(player.run(100), ["b"]);
+        assert!(player.paused());
+        // Ready to start over from the top.
+        assert_eq!(player.service.queue_index, 0);
+    }
+
+    #[test]
+    fn track_once_stops_on_the_same_track() {
+        let mut player = Player::new("track-once", &[1, 1]);
+        player.play(LoopMode::TrackOnce);
+        assert!(player.run(100).is_empty());
+        assert!(player.paused());
+        assert_eq!(player.service.queue_index, 0);
+    }
+
+    #[test]
+    fn stop_after_current_stops_once_and_goes_back() {
+        let mut player = Player::new("stop-after", &[1, 1]);
+        player.play(LoopMode::Shuffle);
+        player.service.set_loop_mode(LoopMode::StopAfterCurrent);
+        assert!(player.run(100).is_empty());
+        assert!(player.paused());
+        assert_eq!(player.service.queue_index, 1);
+        assert_eq!(player.service.loop_mode(), LoopMode::Shuffle);
+    }
+
+    #[test]
+    fn switching_mode_drops_the_preloaded_track() {
+        let mut player = Player::new("switch-stop", &[1, 1]);
+        player.play(LoopMode::Playlist);
+        player.run(2);
+        assert!(player.service.preloaded.is_some());
+        player.service.set_loop_mode(LoopMode::StopAfterCurrent);
+        assert!(player.run(100).is_empty());
+        assert!(player.paused());
+        assert_eq!(player.service.queue_index, 1);
+
+        let mut player = Player::new("switch-single", &[1, 1]);
+        player.play(LoopMode::Single);
+        player.run(2);
+        assert!(player.service.preloaded.is_some());
+        player.service.set_loop_mode(LoopMode::Playlist);
+        assert_eq!(player.run(12), ["b"]);
+    }
+
+    #[test]
+    fn repeat_plays_each_track_n_times() {
+        let mut player = Player::new("repeat", &[1, 1]);
+        player.play(LoopMode::Repeat(3));
+        // A repeat doesn't show up as a started track, so follow the count.
+        let mut plays = vec![(player.current().unwrap(), player.service.plays)];
+        for _ in 0..80 {
+            player.run(1);
+            let play = (player.current().unwrap(), player.service.plays);
+            if plays.last() != Some(&play) {
+                plays.push(play);
+            }
+        }
+        let plays: Vec<_> = plays
+            .iter()
+            .map(|(name, n)| format!("{}{}", name, n))
+            .collect();
+        assert_eq!(plays[..6], ["a1", "a2", "a3", "b1", "b2", "b3"]);
+    }
+
+    #[test]
+    fn shuffle_plays_every_track_before_repeating() {
+        let mut player = Player::new("shuffle", &[1, 1, 1, 1]);
+        player.play(LoopMode::Shuffle);
+        let mut played = vec![player.current().unwrap()];
+        played.extend(player.run(35));
+        played.truncate(4);
+        played.sort();
+        assert_eq!(played, ["a", "b", "c", "d"]);
+    }
+
+    #[test]
+    fn seek_moves_the_position() {
+        let mut player = Player::new("seek", &[3, 1]);
+        player.play(LoopMode::Playlist);
+        player.run(5);
+        player.service.seek_to(Duration::from_secs(2)).unwrap();
+        assert_eq!(
+            player.service.get_current_position(),
+            Duration::from_secs(2)
+        );
+        player.run(5);
+        let position = player.service.get_current_position();
+        assert!(position > Duration::from_secs(2) && position < Duration::from_secs(3));
+        player.service.seek_to_ratio(0.0).unwrap();
+        assert_eq!(player.service.get_current_position(), Duration::ZERO);
+        // Playback moves on from near the end right away.
+        player.service.seek_to(Duration::from_millis(2900)).unwrap();
+        assert_eq!(player.run(3), ["b"]);
+    }
+
+    #[test]
+    fn volume_steps_keep_the_output_muted() {
+        let mut player = Player::new("mute", &[1]);
+        player.service.set_volume(0.5);
+        player.service.mute();
+        player.service.increase_vol();
+        assert_eq!(player.service.output.volume(), 0.0);
+        player.service.unmute();
+        assert!((player.service.output.volume() - 0.6).abs() < 1e-6);
+        for _ in 0..10 {
+            player.service.decrease_vol();
+        }
+        assert_eq!(player.service.output.volume(), 0.0);
+    }
+
+    #[test]
+    fn sleep_at_end_of_track_stops_despite_preload() {
+        let mut player = Player::new("sleep-end", &[1, 1]);
+        player.play(LoopMode::Playlist);
+        player.run(2);
+        assert!(player.service.preloaded.is_some());
+        player.service.set_sleep_timer(Some(SleepTimer::Tracks(0)));
+        // The next track waits, paused at its start.
+        assert_eq!(player.run(100), ["b"]);
+        assert!(player.paused());
+        assert_eq!(player.service.get_current_position(), Duration::ZERO);
+        assert_eq!(player.service.sleep_timer(), None);
+    }
+
+    #[test]
+    fn sleep_after_tracks_plays_them_first() {
+        let mut player = Player::new("sleep-tracks", &[1, 1, 1]);
+        player.play(LoopMode::Playlist);
+        player.run(2);
+        player.service.set_sleep_timer(Some(SleepTimer::Tracks(1)));
+        assert_eq!(player.run(100), ["b", "c"]);
+        assert!(player.paused());
+        assert_eq!(player.service.get_current_position(), Duration::ZERO);
+    }
+
+    #[test]
+    fn sleep_timer_fades_out_and_restores_the_volume_once_paused() {
+        let mut player = Player::new("sleep-fade", &[3]);
+        player.play(LoopMode::Single);
+        player.service.set_volume(0.8);
+        player.service.sleep_fade = Duration::from_secs(100);
+        let deadline = Instant::now() + Duration::from_secs(50);
+        player
+            .service
+            .set_sleep_timer(Some(SleepTimer::At(deadline)));
+        assert!(!player.service.tick_sleep_timer());
+        // A quarter of the volume halfway through the fade.
+        let volume = player.service.output.volume();
+        assert!((0.19..=0.2).contains(&volume), "{volume}");
+
+        player
+            .service
+            .set_sleep_timer(Some(SleepTimer::At(Instant::now())));
+        assert!(player.service.tick_sleep_timer());
+        assert!(player.paused());
+        assert_eq!(player.service.output.volume(), 0.8);
+        assert_eq!(player.service.current_volume, 0.8);
     }
 }
@@ -0,0 +1,222 @@
+use std::collections::HashSet;
+
+use rand::{Rng, SeedableRng, rngs::StdRng, seq::SliceRandom};
+
+/// How many played tracks are remembered for "previous".
+const HISTORY_LEN: usize = 200;
+
+/// Shuffled play order over the queue. Every track plays once before any of
+/// them repeats, and tracks heard at the end of one round are kept out of
+/// the start of the next.
+#[derive(Debug)]
+pub struct Shuffle {
+    /// Tracks still to play this round; the next one is last.
+    order: Vec<String>,
+    /// Tracks already played this round.
+    played: HashSet<String>,
+    /// Recently played tracks, most recent last.
+    history: Vec<String>,
+    rng: StdRng,
+}
+
+impl Default for Shuffle {
+    fn default() -> Self {
+        Self::with_rng(StdRng::from_os_rng())
+    }
+}
+
+impl Shuffle {
+    fn with_rng(rng: StdRng) -> Self {
+        Self {
+            order: Vec::new(),
+            played: HashSet::new(),
+            history: Vec::new(),
+            rng,
+        }
+    }
+    /// The track to play after `current`, `None` when it is the only one.
+    /// Tracks that left the queue are dropped from the order and new ones
+    /// are slotted in at random, so changing the queue doesn't start a new
+    /// round.
+    pub fn next(&mut self, queue: &[String], current: Option<&str>) -> Option<&str> {
+        self.sync(queue, current);
+        if self.order.is_empty() {
+            self.new_round(queue, current);
+        }
+        self.order.last().map(String::as_str)
+    }
+    fn sync(&mut self, queue: &[String], current: Option<&str>) {
+        let in_queue: HashSet<&str> = queue.iter().map(String::as_str).collect();
+        self.order.retain(|p| in_queue.contains(p.as_str()));
+        self.played.retain(|p| in_queue.contains(p.as_str()));
+        let mut known: HashSet<String> = self.order.iter().chain(&self.played).cloned().collect();
+        known.extend(current.map(str::to_string));
+        for path in queue {
+            if known.insert(path.clone()) {
+                let at = self.rng.random_range(0..=self.order.len());
+                self.order.insert(at, path.clone());
+            }
+        }
+    }
+    fn new_round(&mut self, queue: &[String], current: Option<&str>) {
+        self.played.clear();
+        self.played.extend(current.map(str::to_string));
+        let mut seen = HashSet::new();
+        self.order = queue
+            .iter()
+            .filter(|p| Some(p.as_str()) != current && seen.insert(p.as_str()))
+            .cloned()
+            .collect();
+        self.order.shuffle(&mut self.rng);
+        // The back of the order plays first, so the most recently heard
+        // half of the tracks goes to the front.
+        let recent: HashSet<&String> = self.history.iter().rev().take(queue.len() / 2).collect();
+        self.order.sort_by_key(|p| !recent.contains(p));
+    }
+    /// Marks `path` as playing, whether it was picked by the shuffle or not.
+    pub fn started(&mut self, path: &str) {
+        if let Some(i) = self.order.iter().rposition(|p| p == path) {
+            self.order.remove(i);
+        }
+        self.played.insert(path.to_string());
+    }
+    /// Makes `path` the next track to play.
+    pub fn play_next(&mut self, path: &str) {
+        if let Some(i) = self.order.iter().rposition(|p| p == path) {
+            self.order.remove(i);
+        }
+        self.order.push(path.to_string());
+    }
+    pub fn push_history(&mut self, path: String) {
+        if self.history.last() != Some(&path) {
+            self.history.push(path);
+        }
+        if self.history.len() > HISTORY_LEN {
+            self.history.remove(0);
+        }
+    }
+    /// Steps back in the history. `current` goes back into the order so it
+    /// comes up again next.
+    pub fn back(&mut self, current: Option<&str>) -> Option<String> {
+        let previous = self.history.pop()?;
+        if let Some(current) = current {
+            self.played.remove(current);
+            self.play_next(current);
+        }
+        Some(previous)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn queue(n: usize) -> Vec<String> {
+        (0..n).map(|i| format!("{i}.mp3")).collect()
+    }
+
+    fn shuffle(seed: u64) -> Shuffle {
+        Shuffle::with_rng(StdRng::seed_from_u64(seed))
+    }
+
+    /// Plays `n` tracks the way the service does, returning them in order.
+    fn play(
+        shuffle: &mut Shuffle,
+        queue: &[String],
+        current: &mut Option<String>,
+        n: usize,
+    ) -> Vec<String> {
+        (0..n)
+            .map(|_| {
+                let next = shuffle.next(queue, current.as_deref()).unwrap().to_string();
+                if let Some(previous) = current.replace(next.clone()) {
+                    shuffle.push_history(previous);
+                }
+                shuffle.started(&next);
+                next
+            })
+            .collect()
+    }
+
+    fn sorted(mut tracks: Vec<String>) -> Vec<String> {
+        tracks.sort();
+        tracks
+    }
+
+    #[test]
+    fn plays_every_track_once_per_round() {
+        for seed in 0..20 {
+            let queue = queue(10);
+            let mut shuffle = shuffle(seed);
+            let mut current = None;
+            let first = play(&mut shuffle, &queue, &mut current, 10);
+            assert_eq!(sorted(first.clone()), queue, "seed {seed}");
+            // The track still playing counts towards the next round.
+            let second = play(&mut shuffle, &queue, &mut current, 9);
+            let mut rest = queue.clone();
+            rest.retain(|p| p != &first[9]);
+            assert_eq!(sorted(second), rest, "seed {seed}");
+        }
+    }
+
+    #[test]
+    fn recently_heard_tracks_wait_in_the_next_round() {
+        for seed in 0..20 {
+            let queue = queue(10);
+            let mut shuffle = shuffle(seed);
+            let mut current = None;
+            let first = play(&mut shuffle, &queue, &mut current, 10);
+            let second = play(&mut shuffle, &queue, &mut current, 9);
+            // The five heard before the playing track come last.
+            assert_eq!(
+                sorted(second[4..].to_vec()),
+                sorted(first[4..9].to_vec()),
+                "seed {seed}"
+            );
+        }
+    }
+
+    #[test]
+    fn back_returns_the_history_and_replays_the_current_track() {
+        let queue = queue(5);
+        let mut shuffle = shuffle(7);
+        let mut current = None;
+        let heard = play(&mut shuffle, &queue, &mut current, 3);
+        assert_eq!(shuffle.back(current.as_deref()), Some(heard[1].clone()));
+        assert_eq!(shuffle.back(None), Some(heard[0].clone()));
+        assert_eq!(shuffle.back(None), None);
+        // Stepping back from the third track queued it up again.
+        assert_eq!(
+            shuffle.next(&queue, Some(&heard[0])),
+            Some(heard[2].as_str())
+        );
+    }
+
+    #[test]
+    fn history_is_bounded_and_skips_repeats() {
+        let mut shuffle = shuffle(0);
+        shuffle.push_history("a".into());
+        shuffle.push_history("a".into());
+        assert_eq!(shuffle.history, ["a"]);
+        for i in 0..HISTORY_LEN + 10 {
+            shuffle.push_history(i.to_string());
+        }
+        assert_eq!(shuffle.history.len(), HISTORY_LEN);
+        assert_eq!(shuffle.history.last().map(String::as_str), Some("209"));
+    }
+
+    #[test]
+    fn queue_changes_keep_the_round() {
+        let mut queue = queue(6);
+        let mut shuffle = shuffle(3);
+        let mut current = None;
+        let heard = play(&mut shuffle, &queue, &mut current, 3);
+        let removed = shuffle.order[0].clone();
+        queue.retain(|p| p != &removed);
+        queue.push("new.mp3".into());
+        let rest = play(&mut shuffle, &queue, &mut current, 3);
+        let mut expected = queue.clone();
+        expected.retain(|p| !heard.contains(p));
+        assert_eq!(sorted(rest), sorted(expected));
+    }
+}
@@ -0,0 +1,28 @@
+use std::time::{Duration, Instant};
+
+/// When the sleep timer pauses playback.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum SleepTimer {
+    At(Instant),
+    /// After this many more tracks once the current one ends; 0 stops at
+    /// the end of the current track.
+    Tracks(u32),
+}
+
+impl SleepTimer {
+    /// Parses what was typed into the sleep timer prompt: minutes (`30`),
+    /// `end` for the end of the current track, or `3t` for three more tracks.
+    pub fn parse(s: &str) -> Option<Self> {
+        let s = s.trim();
+        if s == "end" {
+            return Some(Self::Tracks(0));
+        }
+        if let Some(tracks) = s.strip_suffix('t') {
+            return tracks.trim().parse().ok().map(Self::Tracks);
+        }
+        let minutes: f64 = s.parse().ok().filter(|m: &f64| *m > 0.0)?;
+        Some(Self::At(
+            Instant::now() + Duration::from_secs_f64(minutes * 60.0),
+        ))
+    }
+}
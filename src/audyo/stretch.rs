@@ -0,0 +1,267 @@
+use std::{
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::Source;
+
+/// Length of the overlap-added segments, in frames (~46ms at 44.1kHz).
+const WINDOW: usize = 2048;
+/// Output frames produced per segment; segments overlap by half.
+const HOP: usize = WINDOW / 2;
+/// How far a segment may be shifted from its ideal position to line up with
+/// the waveform of the previous one.
+const TOLERANCE: usize = 256;
+/// Only every n-th frame is used when comparing candidate segments.
+const CORRELATION_STRIDE: usize = 4;
+
+/// WSOLA time-stretch: plays `input` faster or slower than real time without
+/// changing its pitch. Input is consumed `speed` times as fast as output is
+/// produced by picking overlapping segments from the input and shifting each
+/// one slightly so its waveform lines up with the previous segment before
+/// they are cross-faded.
+pub struct TimeStretch<S> {
+    input: S,
+    channels: usize,
+    speed: Arc<Mutex<f32>>,
+    window: Vec<f32>,
+    /// Interleaved input frames, starting at absolute frame `buffer_start`.
+    buffer: Vec<f32>,
+    buffer_start: usize,
+    /// Where the next segment would start if no alignment was needed.
+    analysis_pos: f64,
+    /// Start of the last segment placed.
+    prev_pos: Option<usize>,
+    /// Windowed second half of the last segment, waiting for its overlap.
+    tail: Vec<f32>,
+    output: Vec<f32>,
+    out_pos: usize,
+}
+
+impl<S> TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, speed: Arc<Mutex<f32>>) -> Self {
+        let channels = input.channels() as usize;
+        let window = (0..WINDOW)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / WINDOW as f32).cos())
+            .collect();
+        Self {
+            input,
+            channels,
+            speed,
+            window,
+            buffer: Vec::new(),
+            buffer_start: 0,
+            analysis_pos: 0.0,
+            prev_pos: None,
+            tail: vec![0.0; HOP * channels],
+            output: Vec::with_capacity(HOP * channels),
+            out_pos: 0,
+        }
+    }
+
+    fn frame(&self, pos: usize, channel: usize) -> f32 {
+        self.buffer[(pos - self.buffer_start) * self.channels + channel]
+    }
+
+    fn mono(&self, pos: usize) -> f32 {
+        (0..self.channels).map(|c| self.frame(pos, c)).sum::<f32>() / self.channels as f32
+    }
+
+    /// Pulls input until frame `end` (exclusive) is buffered.
+    fn fill(&mut self, end: usize) -> bool {
+        while self.buffer_start + self.buffer.len() / self.channels < end {
+            for _ in 0..self.channels {
+                match self.input.next() {
+                    Some(sample) => self.buffer.push(sample),
+                    None => return false,
+                }
+            }
+        }
+        true
+    }
+
+    /// The segment start in `lo..=hi` whose first half best matches the
+    /// natural continuation of the previous segment.
+    fn best_offset(&self, lo: usize, hi: usize, natural: usize) -> usize {
+        let reference: Vec<f32> = (0..HOP)
+            .step_by(CORRELATION_STRIDE)
+            .map(|i| self.mono(natural + i))
+            .collect();
+        let mut best = (lo, f32::MIN);
+        for candidate in lo..=hi {
+            let (mut corr, mut energy) = (0.0, 0.0);
+            for (k, r) in reference.iter().enumerate() {
+                let m = self.mono(candidate + k * CORRELATION_STRIDE);
+                corr += m * r;
+                energy += m * m;
+            }
+            let score = corr / (energy + 1e-9f32).sqrt();
+            if score > best.1 {
+                best = (candidate, score);
+            }
+        }
+        best.0
+    }
+
+    /// Places the next segment and fills `output` with `HOP` frames.
+    fn step(&mut self) -> bool {
+        let speed = *self.speed.lock().unwrap() as f64;
+        let target = self.analysis_pos.round() as usize;
+        let natural = self.prev_pos.map(|p| p + HOP);
+        let search = natural.filter(|&n| n != target);
+        let (lo, hi) = match search {
+            Some(_) => (
+                target.saturating_sub(TOLERANCE).max(self.buffer_start),
+                target + TOLERANCE,
+            ),
+            None => (target, target),
+        };
+        let end = (hi + WINDOW).max(natural.map_or(0, |n| n + HOP));
+        if !self.fill(end) {
+            return false;
+        }
+        let start = match search {
+            Some(natural) => self.best_offset(lo, hi, natural),
+            None => target,
+        };
+
+        self.output.clear();
+        self.out_pos = 0;
+        for i in 0..HOP {
+            for c in 0..self.channels {
+                let sample =
+                    self.tail[i * self.channels + c] + self.window[i] * self.frame(start + i, c);
+                self.output.push(sample);
+            }
+        }
+        for i in 0..HOP {
+            for c in 0..self.channels {
+                self.tail[i * self.channels + c] =
+                    self.window[HOP + i] * self.frame(start + HOP + i, c);
+            }
+        }
+
+        self.prev_pos = Some(start);
+        self.analysis_pos += HOP as f64 * speed;
+        let keep_from = (start + HOP).min((self.analysis_pos as usize).saturating_sub(TOLERANCE));
+        if keep_from > self.buffer_start {
+            self.buffer
+                .drain(..(keep_from - self.buffer_start) * self.channels);
+            self.buffer_start = keep_from;
+        }
+        true
+    }
+}
+
+impl<S> Iterator for TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        if self.out_pos >= self.output.len() && !self.step() {
+            return None;
+        }
+        let sample = self.output[self.out_pos];
+        self.out_pos += 1;
+        Some(sample)
+    }
+}
+
+impl<S> Source for TimeStretch<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        None
+    }
+    fn channels(&self) -> u16 {
+        self.channels as u16
+    }
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use rodio::buffer::SamplesBuffer;
+
+    use super::*;
+
+    const RATE: u32 = 44_100;
+
+    /// Half a second of a stereo tone, a different one in each channel.
+    fn tone() -> Vec<f32> {
+        (0..RATE as usize / 2)
+            .flat_map(|i| {
+                let t = i as f32 / RATE as f32;
+                let left = (2.0 * std::f32::consts::PI * 440.0 * t).sin();
+                let right = (2.0 * std::f32::consts::PI * 660.0 * t).sin();
+                [0.5 * left, 0.25 * right]
+            })
+            .collect()
+    }
+
+    fn stretch(samples: Vec<f32>, speed: f32) -> Vec<f32> {
+        let source = SamplesBuffer::new(2, RATE, samples);
+        TimeStretch::new(source, Arc::new(Mutex::new(speed))).collect()
+    }
+
+    /// Upward zero crossings of the left channel per second.
+    fn frequency(samples: &[f32]) -> f32 {
+        let left: Vec<f32> = samples.iter().step_by(2).copied().collect();
+        let crossings = left
+            .windows(2)
+            .filter(|w| w[0] < 0.0 && w[1] >= 0.0)
+            .count();
+        crossings as f32 * RATE as f32 / left.len() as f32
+    }
+
+    #[test]
+    fn normal_speed_passes_samples_through() {
+        let input = tone();
+        let output = stretch(input.clone(), 1.0);
+        // Only the first half window fades in; segments then overlap into
+        // the input itself.
+        let faded = HOP * 2;
+        assert!(output.len() > input.len() - WINDOW * 2);
+        for (i, (out, sample)) in output.iter().zip(&input).enumerate().skip(faded) {
+            assert!((out - sample).abs() < 1e-5, "sample {i}: {out} != {sample}");
+        }
+    }
+
+    #[test]
+    fn output_length_scales_with_speed() {
+        let input = tone();
+        let frames = input.len() / 2;
+        for speed in [0.5, 0.75, 1.5, 2.0] {
+            let output = stretch(input.clone(), speed);
+            assert_eq!(output.len() % 2, 0);
+            let expected = frames as f32 / speed;
+            let slack = (WINDOW + TOLERANCE + HOP) as f32 / speed;
+            let got = (output.len() / 2) as f32;
+            assert!(
+                (got - expected).abs() <= slack,
+                "speed {speed}: {got} frames, expected about {expected}"
+            );
+        }
+    }
+
+    #[test]
+    fn pitch_is_kept() {
+        let input = tone();
+        for speed in [0.75, 1.5] {
+            let output = stretch(input.clone(), speed);
+            let hz = frequency(&output[WINDOW * 2..]);
+            assert!((hz - 440.0).abs() < 5.0, "speed {speed}: {hz} Hz");
+        }
+    }
+}
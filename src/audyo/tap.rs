@@ -0,0 +1,114 @@
+use std::{
+    collections::VecDeque,
+    sync::{Arc, Mutex},
+    time::Duration,
+};
+
+use rodio::Source;
+
+/// Mono frames kept for visualisation.
+const CAPACITY: usize = 4096;
+/// Frames collected on the audio thread before publishing them.
+const BATCH: usize = 256;
+
+/// The most recent audio sent to the output, downmixed to mono.
+#[derive(Clone, Default)]
+pub struct SampleTap {
+    frames: Arc<Mutex<VecDeque<f32>>>,
+}
+
+impl SampleTap {
+    pub fn new() -> Self {
+        Self::default()
+    }
+    /// Copies the latest `n` frames, zero padded at the front if fewer have
+    /// been played so far.
+    pub fn latest(&self, n: usize) -> Vec<f32> {
+        let frames = self.frames.lock().unwrap();
+        let skip = frames.len().saturating_sub(n);
+        let mut latest = vec![0.0; n.saturating_sub(frames.len())];
+        latest.extend(frames.iter().skip(skip));
+        latest
+    }
+}
+
+/// Passes `input` through unchanged while publishing it to a `SampleTap`.
+/// The audio thread never waits on the tap: if the UI holds the lock the
+/// batch is kept and published with the next one.
+pub struct Tapped<S> {
+    input: S,
+    tap: SampleTap,
+    channels: usize,
+    frame_sum: f32,
+    channel: usize,
+    pending: Vec<f32>,
+}
+
+impl<S> Tapped<S>
+where
+    S: Source<Item = f32>,
+{
+    pub fn new(input: S, tap: SampleTap) -> Self {
+        let channels = input.channels().max(1) as usize;
+        Self {
+            input,
+            tap,
+            channels,
+            frame_sum: 0.0,
+            channel: 0,
+            pending: Vec::with_capacity(BATCH),
+        }
+    }
+
+    fn publish(&mut self) {
+        let Ok(mut frames) = self.tap.frames.try_lock() else {
+            if self.pending.len() > CAPACITY {
+                self.pending.drain(..self.pending.len() - CAPACITY);
+            }
+            return;
+        };
+        frames.extend(self.pending.drain(..));
+        let excess = frames.len().saturating_sub(CAPACITY);
+        frames.drain(..excess);
+    }
+}
+
+impl<S> Iterator for Tapped<S>
+where
+    S: Source<Item = f32>,
+{
+    type Item = f32;
+
+    fn next(&mut self) -> Option<f32> {
+        let sample = self.input.next()?;
+        self.frame_sum += sample;
+        self.channel += 1;
+        if self.channel == self.channels {
+            self.pending.push(self.frame_sum / self.channels as f32);
+            self.frame_sum = 0.0;
+            self.channel = 0;
+            if self.pending.len() >= BATCH {
+                self.publish();
+            }
+        }
+        Some(sample)
+    }
+}
+
+impl<S> Source for Tapped<S>
+where
+    S: Source<Item = f32>,
+{
+    fn current_frame_len(&self) -> Option<usize> {
+        self.input.current_frame_len()
+    }
+    fn channels(&self) -> u16 {
+        self.input.channels()
+    }
+    fn sample_rate(&self) -> u32 {
+        self.input.sample_rate()
+    }
+    fn total_duration(&self) -> Option<Duration> {
+        self.input.total_duration()
+    }
+}
@@ -0,0 +1,161 @@
+use std::{collections::HashMap, fs, io, path::PathBuf, time::Duration};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audyo::{backend::BackendKind, equalizer::EqPreset, loudness::Normalization},
+    downloader::captions::CaptionFormat,
+    ui::artwork::graphics::Graphics,
+};
+
+/// Root of everything the player keeps on disk.
+pub fn data_dir() -> PathBuf {
+    if let Some(home) = dirs::home_dir() {
+        home.join(".audyo_plaier")
+    } else {
+        PathBuf::from("./audyo_plaier")
+    }
+}
+
+/// User settings read from `~/.audyo_plaier/config.json`. Missing keys fall
+/// back to their defaults.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Config {
+    /// Seconds skipped by `,` and `.`.
+    pub seek_step_secs: u64,
+    /// Seconds skipped by `<` and `>`.
+    pub long_seek_step_secs: u64,
+    /// Show the track's waveform instead of the plain time gauge.
+    pub waveform_seekbar: bool,
+    pub normalization: Normalization,
+    /// Loudness tracks are normalized to, in LUFS.
+    pub target_lufs: f64,
+    /// Level the limiter keeps normalized audio under, in dBFS.
+    pub limiter_threshold_db: f32,
+    /// Equalizer applied to tracks without an override.
+    pub equalizer: EqPreset,
+    /// Presets saved from the equalizer panel.
+    pub eq_presets: Vec<EqPreset>,
+    /// Per-file equalizer settings, keyed by path.
+    pub eq_overrides: HashMap<String, EqPreset>,
+    /// Name of the output device; the system default when unset.
+    pub output_device: Option<String>,
+    pub backend: BackendKind,
+    /// How many times faster than real time the null backend plays; 0 plays
+    /// as fast as possible.
+    pub null_clock: f32,
+    /// WAV file the null backend records its output to.
+    pub render_wav: Option<PathBuf>,
+    /// Seconds played before point A each time an A-B loop comes round.
+    pub ab_lead_in_secs: f64,
+    /// How many times an A-B loop plays before moving on; forever if unset.
+    pub ab_plays: Option<u32>,
+    /// Seconds over which the sleep timer fades the volume out.
+    pub sleep_fade_secs: u64,
+    /// Folders scanned for audio besides the download folder, with their
+    /// subfolders.
+    pub library_dirs: Vec<PathBuf>,
+    /// Files at least this many minutes long pick up where they were left.
+    pub resume_min_minutes: u64,
+    /// Per-file lyrics timing fixes in milliseconds, keyed by path; positive
+    /// shows lines earlier.
+    pub lyrics_offsets: HashMap<String, i64>,
+    /// Fetch a caption track with each download, for the lyrics panel.
+    pub download_captions: bool,
+    /// Caption languages in order of preference; recognized speech in the
+    /// spoken language is the fallback.
+    pub caption_languages: Vec<String>,
+    pub caption_format: CaptionFormat,
+    /// How artwork is drawn; guessed from the terminal when unset.
+    pub artwork_graphics: Option<Graphics>,
+    /// Set when the file on disk couldn't be read or backed up, so `save`
+    /// leaves it alone.
+    #[serde(skip)]
+    read_only: bool,
+}
+
+impl Default for Config {
+    fn default() -> Self {
+        Self {
+            seek_step_secs: 5,
+            long_seek_step_secs: 30,
+            waveform_seekbar: false,
+            normalization: Normalization::Off,
+            target_lufs: -18.0,
+            limiter_threshold_db: -1.0,
+            equalizer: EqPreset::default(),
+            eq_presets: Vec::new(),
+            eq_overrides: HashMap::new(),
+            output_device: None,
+            backend: BackendKind::Rodio,
+            null_clock: 1.0,
+            render_wav: None,
+            ab_lead_in_secs: 0.0,
+            ab_plays: None,
+            sleep_fade_secs: 45,
+            library_dirs: Vec::new(),
+            resume_min_minutes: 20,
+            lyrics_offsets: HashMap::new(),
+            download_captions: false,
+            caption_languages: vec!["en".to_string()],
+            caption_format: CaptionFormat::Lrc,
+            artwork_graphics: None,
+            read_only: false,
+        }
+    }
+}
+
+impl Config {
+    pub fn path() -> PathBuf {
+        data_dir().join("config.json")
+    }
+    /// The saved settings, or the defaults on first start. When the file
+    /// can't be parsed, the defaults come with the error; the file is copied
+    /// to `config.json.bak` first, or kept from being saved over when even
+    /// that fails.
+    pub fn load() -> (Self, Option<String>) {
+        let path = Self::path();
+        let read_only = Self {
+            read_only: true,
+            ..Self::default()
+        };
+        let json = match fs::read_to_string(&path) {
+            Ok(json) => json,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => return (Self::default(), None),
+            Err(e) => {
+                let error = format!("Couldn't read {}: {}", path.display(), e);
+                return (read_only, Some(error));
+            }
+        };
+        match serde_json::from_str(&json) {
+            Ok(config) => (config, None),
+            Err(e) => {
+                let backup = path.with_extension("json.bak");
+                match fs::write(&backup, &json) {
+                    Ok(()) => (
+                        Self::default(),
+                        Some(format!("Bad config, copied to {}: {}", backup.display(), e)),
+                    ),
+                    Err(_) => (
+                        read_only,
+                        Some(format!("Bad config {}, not saving: {}", path.display(), e)),
+                    ),
+                }
+            }
+        }
+    }
+    pub fn save(&self) -> io::Result<()> {
+        if self.read_only {
+            return Err(io::Error::other("config.json couldn't be read"));
+        }
+        fs::create_dir_all(data_dir())?;
+        fs::write(Self::path(), serde_json::to_string_pretty(self)?)
+    }
+    pub fn seek_step(&self) -> Duration {
+        Duration::from_secs(self.seek_step_secs)
+    }
+    pub fn long_seek_step(&self) -> Duration {
+        Duration::from_secs(self.long_seek_step_secs)
+    }
+}
@@ -0,0 +1,272 @@
+use std::{
+    fmt::Write,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+
+use reqwest::Url;
+use serde::{Deserialize, Serialize};
+
+/// A caption track offered for a video.
+#[derive(Debug, Clone)]
+pub struct CaptionTrack {
+    pub url: String,
+    /// BCP 47 code like `en` or `pt-BR`.
+    pub language: String,
+    /// Speech recognition rather than captions someone wrote.
+    pub auto_generated: bool,
+}
+
+impl CaptionTrack {
+    /// The track's URL asking for YouTube's JSON format.
+    pub fn json_url(&self) -> Option<String> {
+        let mut url = Url::parse(&self.url).ok()?;
+        let query: Vec<(String, String)> = url
+            .query_pairs()
+            .filter(|(key, _)| key != "fmt")
+            .map(|(key, value)| (key.into_owned(), value.into_owned()))
+            .collect();
+        url.query_pairs_mut()
+            .clear()
+            .extend_pairs(query)
+            .append_pair("fmt", "json3");
+        Some(url.into())
+    }
+}
+
+/// File written next to a download for its captions.
+#[derive(Debug, Clone, Copy, PartialEq, Default, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum CaptionFormat {
+    /// Read by the lyrics panel.
+    #[default]
+    Lrc,
+    Srt,
+}
+
+impl CaptionFormat {
+    pub fn extension(&self) -> &'static str {
+        match self {
+            Self::Lrc => "lrc",
+            Self::Srt => "srt",
+        }
+    }
+    pub fn path_for(&self, audio: &Path) -> PathBuf {
+        audio.with_extension(self.extension())
+    }
+    pub fn render(&self, captions: &[Caption]) -> String {
+        match self {
+            Self::Lrc => to_lrc(captions),
+            Self::Srt => to_srt(captions),
+        }
+    }
+}
+
+/// Picks the track to download: written captions in the first preferred
+/// language that has any, then recognized speech in one, then recognized
+/// speech in whatever language is spoken. Languages match by prefix, so
+/// `en` takes `en-GB`.
+pub fn choose<'a>(tracks: &'a [CaptionTrack], languages: &[String]) -> Option<&'a CaptionTrack> {
+    let matches = |track: &CaptionTrack, language: &str| {
+        track.language == language
+            || track
+                .language
+                .strip_prefix(language)
+                .is_some_and(|rest| rest.starts_with('-'))
+    };
+    for auto_generated in [false, true] {
+        for language in languages {
+            if let Some(track) = tracks
+                .iter()
+                .find(|t| t.auto_generated == auto_generated && matches(t, language))
+            {
+                return Some(track);
+            }
+        }
+    }
+    tracks.iter().find(|t| t.auto_generated)
+}
+
+/// One caption on screen.
+#[derive(Debug, Clone, PartialEq)]
+pub struct Caption {
+    pub start: Duration,
+    pub end: Duration,
+    pub text: String,
+}
+
+#[derive(Deserialize)]
+struct Json3 {
+    #[serde(default)]
+    events: Vec<Event>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "camelCase")]
+struct Event {
+    t_start_ms: Option<u64>,
+    d_duration_ms: Option<u64>,
+    #[serde(default)]
+    segs: Vec<Segment>,
+}
+
+#[derive(Deserialize)]
+struct Segment {
+    #[serde(default)]
+    utf8: String,
+}
+
+/// Parses YouTube's `json3` caption format. Events without text, like the
+/// line breaks of recognized speech, are left out, and each caption ends
+/// by the time the next one starts.
+pub fn parse_json3(json: &str) -> serde_json::Result<Vec<Caption>> {
+    let parsed: Json3 = serde_json::from_str(json)?;
+    let mut captions: Vec<Caption> = parsed
+        .events
+        .into_iter()
+        .filter_map(|event| {
+            let text: String = event.segs.iter().map(|s| s.utf8.as_str()).collect();
+            let text = text.split_whitespace().collect::<Vec<_>>().join(" ");
+            let start = event.t_start_ms?;
+            (!text.is_empty()).then(|| Caption {
+                start: Duration::from_millis(start),
+                end: Duration::from_millis(start.saturating_add(event.d_duration_ms.unwrap_or(0))),
+                text,
+            })
+        })
+        .collect();
+    captions.sort_by_key(|c| c.start);
+    for i in 1..captions.len() {
+        let next = captions[i].start;
+        let caption = &mut captions[i - 1];
+        caption.end = caption.end.min(next).max(caption.start);
+    }
+    Ok(captions)
+}
+
+fn to_lrc(captions: &[Caption]) -> String {
+    let mut out = String::new();
+    for caption in captions {
+        let centis = caption.start.as_millis() / 10;
+        let _ = writeln!(
+            out,
+            "[{:02}:{:02}.{:02}]{}",
+            centis / 6000,
+            centis / 100 % 60,
+            centis % 100,
+            caption.text
+        );
+    }
+    out
+}
+
+fn to_srt(captions: &[Caption]) -> String {
+    let time = |d: Duration| {
+        let ms = d.as_millis();
+        format!(
+            "{:02}:{:02}:{:02},{:03}",
+            ms / 3_600_000,
+            ms / 60_000 % 60,
+            ms / 1000 % 60,
+            ms % 1000
+        )
+    };
+    let mut out = String::new();
+    for (i, caption) in captions.iter().enumerate() {
+        let _ = writeln!(
+            out,
+            "{}\n{} --> {}\n{}\n",
+            i + 1,
+            time(caption.start),
+            time(caption.end),
+            caption.text
+        );
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn track(language: &str, auto_generated: bool) -> CaptionTrack {
+        CaptionTrack {
+            url: format!("https://example.com/{}", language),
+            language: language.to_string(),
+            auto_generated,
+        }
+    }
+
+    fn languages(codes: &[&str]) -> Vec<String> {
+        codes.iter().map(|c| c.to_string()).collect()
+    }
+
+    fn caption(start: u64, end: u64, text: &str) -> Caption {
+        Caption {
+            start: Duration::from_millis(start),
+            end: Duration::from_millis(end),
+            text: text.to_string(),
+        }
+    }
+
+    #[test]
+    fn written_captions_in_any_language_come_first() {
+        let tracks = [track("en", true), track("de", false)];
+        let chosen = choose(&tracks, &languages(&["en", "de"])).unwrap();
+        assert_eq!(chosen.language, "de");
+    }
+
+    #[test]
+    fn recognized_speech_follows_the_language_order() {
+        let tracks = [track("fr", true), track("de", true), track("en", true)];
+        let chosen = choose(&tracks, &languages(&["en", "de"])).unwrap();
+        assert_eq!(chosen.language, "en");
+        // Whatever is spoken when no preferred language has a track.
+        let chosen = choose(&tracks, &languages(&["ja"])).unwrap();
+        assert_eq!(chosen.language, "fr");
+        // Written captions in other languages are translations.
+        assert!(choose(&[track("fr", false)], &languages(&["en"])).is_none());
+    }
+
+    #[test]
+    fn languages_match_by_prefix() {
+        let tracks = [track("eng", false), track("en-GB", false)];
+        let chosen = choose(&tracks, &languages(&["en"])).unwrap();
+        assert_eq!(chosen.language, "en-GB");
+    }
+
+    #[test]
+    fn parses_json3() {
+        let json = r#"{"events": [
+            {"tStartMs": 4000, "dDurationMs": 2000, "segs": [{"utf8": "second"}]},
+            {"tStartMs": 0, "dDurationMs": 5000, "segs": [{"utf8": "first "}, {"utf8": " line\n"}]},
+            {"tStartMs": 1000, "segs": [{"utf8": "\n"}]},
+            {"dDurationMs": 10, "segs": [{"utf8": "no start"}]},
+            {"tStartMs": 18446744073709551615, "dDurationMs": 10, "segs": [{"utf8": "late"}]}
+        ]}"#;
+        let captions = parse_json3(json).unwrap();
+        assert_eq!(
+            captions,
+            [
+                caption(0, 4000, "first line"),
+                caption(4000, 6000, "second"),
+                caption(u64::MAX, u64::MAX, "late"),
+            ]
+        );
+        assert!(parse_json3("{}").unwrap().is_empty());
+        assert!(parse_json3("[").is_err());
+    }
+
+    #[test]
+    fn renders_lrc_and_srt() {
+        let captions = [
+            caption(0, 1500, "one"),
+            caption(3_723_456, 3_725_000, "two"),
+        ];
+        assert_eq!(to_lrc(&captions), "[00:00.00]one\n[62:03.45]two\n");
+        assert_eq!(
+            to_srt(&captions),
+            "1\n00:00:00,000 --> 00:00:01,500\none\n\n2\n01:02:03,456 --> 01:02:05,000\ntwo\n\n"
+        );
+    }
+}
@@ -8,6 +8,8 @@ use serde_json::{Value, json};
 use std::sync::Arc;
 use thiserror::Error;
 
+use crate::downloader::captions::CaptionTrack;
+
 #[derive(Clone, Debug)]
 struct ClientConfig {
     client_name: String,
@@ -20,11 +22,8 @@ struct ClientConfig {
 
 #[async_trait]
 trait ClientStrategy: Send + Sync {
-    fn config(&self) -> &ClientConfig;
     fn build_payload(&self, video_id: &str) -> Value;
     fn build_headers(&self, base_url: &'static str) -> HeaderMap;
-    fn client_name(&self) -> &str;
-    fn client_number(&self) -> u32;
 }
 
 pub struct AndroidClient {
@@ -60,9 +59,6 @@ impl Default for AndroidClient {
 }
 
 impl ClientStrategy for AndroidClient {
-    fn config(&self) -> &ClientConfig {
-        &self.client_config
-    }
     fn build_payload(&self, video_id: &str) -> Value {
         json!({
             "videoId": video_id,
@@ -114,12 +110,6 @@ impl ClientStrategy for AndroidClient {
         );
         headers
     }
-    fn client_name(&self) -> &str {
-        "ANDROID"
-    }
-    fn client_number(&self) -> u32 {
-        3
-    }
 }
 
 pub struct YoutubeClient {
@@ -138,15 +128,12 @@ impl YoutubeClient {
             .expect("Failed to build HTTP client");
         YoutubeClient {
             http: client,
-            strategy: strategy,
+            strategy,
         }
     }
     pub fn default_android() -> Self {
         Self::new(Arc::new(AndroidClient::new()))
     }
-    fn set_strategy(&mut self, strategy: Arc<dyn ClientStrategy>) {
-        self.strategy = strategy
-    }
     pub async fn get_video_info(&self, video_id: &str) -> Result<VideoInfo> {
         let headers = self.strategy.build_headers(Self::YOUTUBE_URL);
         let payload = self.strategy.build_payload(video_id);
@@ -160,6 +147,9 @@ impl YoutubeClient {
             .send()
             .await?;
 
+        if response.status() == reqwest::StatusCode::TOO_MANY_REQUESTS {
+            return Err(YtdlError::RateLimited);
+        }
         response.error_for_status_ref()?;
         let player_response = response.json::<PlayerResponse>().await?;
 
@@ -171,12 +161,49 @@ impl YoutubeClient {
         player_response: PlayerResponse,
         video_id: &str,
     ) -> Result<VideoInfo> {
+        // Age-restricted, private or region-locked videos say why.
+        if let Some(status) = player_response.playability_status
+            && status.status != "OK"
+        {
+            return Err(YtdlError::PlayabilityError {
+                reason: status.reason.unwrap_or_default(),
+                status: status.status,
+            });
+        }
         let details = player_response
             .video_details
             .ok_or_else(|| YtdlError::VideoNotFound(video_id.to_string()))?;
         let streaming = player_response
             .streaming_data
             .ok_or(YtdlError::NoSuitableFormat)?;
+        let audio_config = player_response
+            .player_config
+            .and_then(|c| c.audio_config)
+            .unwrap_or_default();
+        let publish_date = player_response
+            .microformat
+            .and_then(|m| m.player_microformat_renderer)
+            .and_then(|m| m.publish_date.or(m.upload_date))
+            // Sometimes a full timestamp; only the day is kept.
+            .map(|d| d.chars().take(10).collect());
+        let thumbnail_url = details.thumbnail.and_then(|t| {
+            t.thumbnails
+                .into_iter()
+                .max_by_key(|t| t.width.unwrap_or(0) * t.height.unwrap_or(0))
+                .map(|t| t.url)
+        });
+        let captions = player_response
+            .captions
+            .and_then(|c| c.player_captions_tracklist_renderer)
+            .map(|r| r.caption_tracks)
+            .unwrap_or_default()
+            .into_iter()
+            .map(|t| CaptionTrack {
+                url: t.base_url,
+                language: t.language_code,
+                auto_generated: t.kind.as_deref() == Some("asr"),
+            })
+            .collect();
         let mut formats = Vec::new();
         if let Some(regular) = streaming.formats {
             formats.extend(regular);
@@ -188,8 +215,12 @@ impl YoutubeClient {
             video_id: details.video_id,
             title: details.title,
             author: details.author,
-            length_seconds: details.length_seconds.parse::<u32>().unwrap_or(0),
-            formats: formats,
+            formats,
+            loudness_db: audio_config.loudness_db,
+            perceptual_loudness_db: audio_config.perceptual_loudness_db,
+            thumbnail_url,
+            publish_date,
+            captions,
         })
     }
     pub fn extract_video_id(url: &str) -> Option<String> {
@@ -202,12 +233,11 @@ impl YoutubeClient {
         ];
 
         for (pattern, group) in patterns {
-            if let Ok(re) = regex_lite::Regex::new(pattern) {
-                if let Some(caps) = re.captures(url) {
-                    if let Some(m) = caps.get(group) {
-                        return Some(m.as_str().to_string());
-                    }
-                }
+            if let Ok(re) = regex_lite::Regex::new(pattern)
+                && let Some(caps) = re.captures(url)
+                && let Some(m) = caps.get(group)
+            {
+                return Some(m.as_str().to_string());
             }
         }
         None
@@ -219,15 +249,82 @@ pub struct VideoInfo {
     pub video_id: String,
     pub title: String,
     pub author: String,
-    pub length_seconds: u32,
     pub formats: Vec<FormatResponse>,
+    /// How much louder than YouTube's playback reference the video is.
+    pub loudness_db: Option<f64>,
+    pub perceptual_loudness_db: Option<f64>,
+    /// Largest thumbnail offered.
+    pub thumbnail_url: Option<String>,
+    /// `YYYY-MM-DD`, when the response includes it.
+    pub publish_date: Option<String>,
+    #[serde(skip)]
+    pub captions: Vec<CaptionTrack>,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 struct PlayerResponse {
+    playability_status: Option<PlayabilityStatus>,
     video_details: Option<VideoDetails>,
     streaming_data: Option<StreamingData>,
+    player_config: Option<PlayerConfig>,
+    microformat: Option<Microformat>,
+    captions: Option<Captions>,
+}
+
+#[derive(Deserialize, Debug)]
+struct PlayabilityStatus {
+    /// `OK` when the video can be played.
+    status: String,
+    reason: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Captions {
+    player_captions_tracklist_renderer: Option<CaptionsTracklist>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CaptionsTracklist {
+    #[serde(default)]
+    caption_tracks: Vec<CaptionTrackResponse>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct CaptionTrackResponse {
+    base_url: String,
+    language_code: String,
+    /// `asr` for automatic speech recognition.
+    kind: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct Microformat {
+    player_microformat_renderer: Option<PlayerMicroformat>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlayerMicroformat {
+    publish_date: Option<String>,
+    upload_date: Option<String>,
+}
+
+#[derive(Deserialize, Debug)]
+#[serde(rename_all = "camelCase")]
+struct PlayerConfig {
+    audio_config: Option<AudioConfig>,
+}
+
+#[derive(Deserialize, Debug, Default)]
+#[serde(rename_all = "camelCase")]
+struct AudioConfig {
+    loudness_db: Option<f64>,
+    perceptual_loudness_db: Option<f64>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -235,8 +332,20 @@ struct PlayerResponse {
 struct VideoDetails {
     video_id: String,
     author: String,
-    length_seconds: String,
     title: String,
+    thumbnail: Option<Thumbnails>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Thumbnails {
+    thumbnails: Vec<Thumbnail>,
+}
+
+#[derive(Deserialize, Debug)]
+struct Thumbnail {
+    url: String,
+    width: Option<u32>,
+    height: Option<u32>,
 }
 
 #[derive(Deserialize, Debug)]
@@ -244,22 +353,15 @@ struct VideoDetails {
 struct StreamingData {
     formats: Option<Vec<FormatResponse>>,
     adaptive_formats: Option<Vec<FormatResponse>>,
-    expires_in_seconds: String,
 }
 
 #[derive(Deserialize, Debug)]
 #[serde(rename_all = "camelCase")]
 pub struct FormatResponse {
-    itag: Option<u32>,
     pub url: Option<String>,
-    approx_duration_ms: Option<String>,
-    audio_channels: Option<u8>,
-    audio_quality: Option<String>,
-    audio_sample_rate: Option<String>,
     average_bitrate: Option<u32>,
     bitrate: Option<u32>,
     pub mime_type: Option<String>,
-    quality: Option<String>,
 }
 
 impl FormatResponse {
@@ -297,9 +399,6 @@ pub enum YtdlError {
     #[error("Video not found: {0}")]
     VideoNotFound(String),
 
-    #[error("Format not available: itag={0}")]
-    FormatNotAvailable(u32),
-
     #[error("No suitable format found")]
     NoSuitableFormat,
 
@@ -312,14 +411,47 @@ pub enum YtdlError {
     #[error("Rate limited by YouTube")]
     RateLimited,
 
-    #[error("Invalid video ID: {0}")]
-    InvalidVideoId(String),
-
-    #[error("Download failed: {0}")]
-    DownloadFailed(String),
-
     #[error("Parse error: {0}")]
     ParseError(String),
 }
 
 pub type Result<T> = std::result::Result<T, YtdlError>;
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn parse(json: Value) -> Result<VideoInfo> {
+        let response = serde_json::from_value(json).unwrap();
+        YoutubeClient::default_android().parse_player_payload(response, "abc")
+    }
+
+    #[test]
+    fn unplayable_videos_report_the_reason() {
+        let result = parse(json!({
+            "playabilityStatus": {"status": "LOGIN_REQUIRED", "reason": "Sign in to confirm your age"},
+        }));
+        assert!(matches!(
+            result,
+            Err(YtdlError::PlayabilityError { status, reason })
+                if status == "LOGIN_REQUIRED" && reason == "Sign in to confirm your age"
+        ));
+    }
+
+    #[test]
+    fn playable_videos_list_all_formats() {
+        let info = parse(json!({
+            "playabilityStatus": {"status": "OK"},
+            "videoDetails": {"videoId": "abc", "title": "T", "author": "A"},
+            "streamingData": {
+                "formats": [{"url": "u1", "mimeType": "video/mp4; codecs=\"mp4a.40.2\""}],
+                "adaptiveFormats": [{"signatureCipher": "s=..", "mimeType": "audio/webm"}],
+            },
+        }))
+        .unwrap();
+        assert_eq!(info.title, "T");
+        assert_eq!(info.formats.len(), 2);
+        assert!(info.formats[0].is_progressive_audio());
+        assert!(info.formats[1].url.is_none());
+    }
+}
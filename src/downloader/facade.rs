@@ -1,42 +1,107 @@
-use std::path::PathBuf;
+use std::{
+    fs,
+    path::{Path, PathBuf},
+    time::{SystemTime, UNIX_EPOCH},
+};
 
+use crate::config::data_dir;
 use crate::downloader::{
-    client::{Result, YoutubeClient, YtdlError},
+    captions::{self, CaptionFormat, CaptionTrack},
+    client::{Result, VideoInfo, YoutubeClient, YtdlError},
+    info::DownloadInfo,
     media_downloader::{Downloader, generate_filename},
+    mp4_tags::Mp4Tags,
 };
 
 pub struct YoutubeFacade {
     client: YoutubeClient,
     downloader: Downloader,
     pub output_dir: PathBuf,
+    /// Caption languages in order of preference and the file they are
+    /// written to; no captions are fetched when unset.
+    captions: Option<(Vec<String>, CaptionFormat)>,
 }
 
 impl YoutubeFacade {
     pub fn new() -> Self {
         let ytb_client = YoutubeClient::default_android();
         let downloader = Downloader::new();
-        let output_dir = if let Some(home) = dirs::home_dir() {
-            home.join(".audyo_plaier")
-        } else {
-            PathBuf::from("./audyo_plaier")
-        }
-        .join("audio");
+        let output_dir = data_dir().join("audio");
 
         Self {
             client: ytb_client,
-            downloader: downloader,
-            output_dir: output_dir,
+            downloader,
+            output_dir,
+            captions: None,
         }
     }
+    pub fn captions(mut self, languages: Vec<String>, format: CaptionFormat) -> Self {
+        self.captions = Some((languages, format));
+        self
+    }
     pub async fn download_audio(&self, video_id: &str) -> Result<()> {
         let video_info = self.client.get_video_info(video_id).await?;
         let filename = generate_filename(&video_info.title, "m4a");
         let output_path = &self.output_dir.join(filename);
-        let format =
-            select_best_format(video_info.formats).ok_or(YtdlError::NoSuitableFormat)?;
+        let info = DownloadInfo::from(&video_info);
+        let tags = self.tags_for(&video_info).await;
+        let captions = self.captions.as_ref().and_then(|(languages, format)| {
+            captions::choose(&video_info.captions, languages).map(|t| (t.clone(), *format))
+        });
+        // Formats without a URL carry a `signatureCipher` instead.
+        let error = if video_info.formats.iter().any(|f| f.url.is_none()) {
+            YtdlError::SignatureRequired
+        } else {
+            YtdlError::NoSuitableFormat
+        };
+        let format = select_best_format(video_info.formats).ok_or(error)?;
         self.downloader.download(&format, output_path).await?;
+        // Only a head start for normalization, the audio is what matters.
+        let _ = info.store(output_path);
+        // Likewise the tags; an untagged file still plays.
+        let _ = tags.write(output_path);
+        if let Some(cover) = tags.cover.as_ref().filter(|c| c.starts_with(&[0xff, 0xd8])) {
+            let _ = fs::write(DownloadInfo::thumbnail_path(output_path), cover);
+        }
+        if let Some((track, format)) = captions {
+            let _ = self.save_captions(&track, format, output_path).await;
+        }
         Ok(())
     }
+    /// Fetches `track` and writes it next to `audio` in `format`.
+    async fn save_captions(
+        &self,
+        track: &CaptionTrack,
+        format: CaptionFormat,
+        audio: &Path,
+    ) -> Result<()> {
+        let url = track
+            .json_url()
+            .ok_or_else(|| YtdlError::ParseError(format!("bad caption URL: {}", track.url)))?;
+        let json = self.downloader.fetch(&url).await?;
+        let captions = captions::parse_json3(&String::from_utf8_lossy(&json))?;
+        fs::write(format.path_for(audio), format.render(&captions))?;
+        Ok(())
+    }
+    /// Tags for a download of `video`, with its thumbnail as the cover.
+    async fn tags_for(&self, video: &VideoInfo) -> Mp4Tags {
+        let cover = match &video.thumbnail_url {
+            Some(url) => self.downloader.fetch(url).await.ok(),
+            None => None,
+        };
+        Mp4Tags {
+            title: Some(video.title.clone()),
+            artist: Some(video.author.clone()),
+            comment: Some(format!(
+                "https://www.youtube.com/watch?v={}",
+                video.video_id
+            )),
+            // Some clients don't say when the video was published; the day
+            // of the download is the next best thing.
+            date: video.publish_date.clone().or_else(|| Some(today())),
+            cover,
+        }
+    }
     pub fn extract_video_id_from_url(&self, url: &str) -> Option<String> {
         YoutubeClient::extract_video_id(url)
     }
@@ -65,3 +130,21 @@ fn select_best_format(
         .max_by_key(|f| f.bitrate())
         .or_else(|| audio_only.into_iter().max_by_key(|f| f.bitrate()))
 }
+
+/// Today's date in UTC as `YYYY-MM-DD`.
+fn today() -> String {
+    let secs = SystemTime::now()
+        .duration_since(UNIX_EPOCH)
+        .map_or(0, |d| d.as_secs());
+    // Howard Hinnant's civil-from-days algorithm.
+    let z = (secs / 86_400) as i64 + 719_468;
+    let era = z.div_euclid(146_097);
+    let doe = z.rem_euclid(146_097);
+    let yoe = (doe - doe / 1_460 + doe / 36_524 - doe / 146_096) / 365;
+    let doy = doe - (365 * yoe + yoe / 4 - yoe / 100);
+    let mp = (5 * doy + 2) / 153;
+    let day = doy - (153 * mp + 2) / 5 + 1;
+    let month = if mp < 10 { mp + 3 } else { mp - 9 };
+    let year = yoe + era * 400 + i64::from(month <= 2);
+    format!("{:04}-{:02}-{:02}", year, month, day)
+}
@@ -0,0 +1,70 @@
+use std::{
+    fs,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::downloader::client::VideoInfo;
+
+/// YouTube normalizes playback to roughly this loudness; `loudnessDb` is how
+/// far a video sits above it.
+const YOUTUBE_REFERENCE_LUFS: f64 = -14.0;
+const EXTENSION: &str = "info.json";
+const THUMBNAIL_EXTENSION: &str = "jpg";
+/// Thumbnail, lyrics and captions files next to the audio.
+const SIDECAR_EXTENSIONS: [&str; 3] = [THUMBNAIL_EXTENSION, "lrc", "srt"];
+
+/// What YouTube reported about a download, kept next to the audio file as
+/// `<name>.info.json`.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct DownloadInfo {
+    pub video_id: String,
+    pub title: String,
+    pub author: String,
+    pub loudness_db: Option<f64>,
+    pub perceptual_loudness_db: Option<f64>,
+}
+
+impl DownloadInfo {
+    pub fn path_for(audio: &Path) -> PathBuf {
+        audio.with_extension(EXTENSION)
+    }
+    /// The video thumbnail saved next to `audio`.
+    pub fn thumbnail_path(audio: &Path) -> PathBuf {
+        audio.with_extension(THUMBNAIL_EXTENSION)
+    }
+    /// Whether `path` is one of the files kept next to a download rather
+    /// than audio.
+    pub fn is_sidecar(path: &Path) -> bool {
+        path.to_string_lossy().ends_with(&format!(".{EXTENSION}"))
+            || path
+                .extension()
+                .is_some_and(|e| SIDECAR_EXTENSIONS.iter().any(|s| e == *s))
+    }
+    pub fn load(audio: &Path) -> Option<Self> {
+        let json = fs::read_to_string(Self::path_for(audio)).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+    pub fn store(&self, audio: &Path) -> std::io::Result<()> {
+        fs::write(Self::path_for(audio), serde_json::to_string(self)?)
+    }
+    /// Integrated loudness estimated from YouTube's numbers, in LUFS.
+    pub fn loudness_lufs(&self) -> Option<f64> {
+        self.loudness_db
+            .or(self.perceptual_loudness_db)
+            .map(|db| YOUTUBE_REFERENCE_LUFS + db)
+    }
+}
+
+impl From<&VideoInfo> for DownloadInfo {
+    fn from(info: &VideoInfo) -> Self {
+        Self {
+            video_id: info.video_id.clone(),
+            title: info.title.clone(),
+            author: info.author.clone(),
+            loudness_db: info.loudness_db,
+            perceptual_loudness_db: info.perceptual_loudness_db,
+        }
+    }
+}
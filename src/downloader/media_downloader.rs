@@ -11,45 +11,17 @@ use tokio::io::AsyncWriteExt;
 
 #[derive(Debug, Clone)]
 struct DownloaderConfig {
-    chunked_size: usize,
     user_agent: String,
-    max_retries: u32,
 }
 
 impl Default for DownloaderConfig {
     fn default() -> Self {
         DownloaderConfig {
-            chunked_size: 1024,
             user_agent: "Mozilla/5.0 (Windows NT 10.0; Win64; x64) AppleWebKit/537.36".to_string(),
-            max_retries: 3,
         }
     }
 }
 
-struct DownloadBuilder {
-    download_config: DownloaderConfig,
-}
-
-impl DownloadBuilder {
-    fn new() -> Self {
-        DownloadBuilder {
-            download_config: DownloaderConfig::default(),
-        }
-    }
-    fn chunk_size(mut self, chunk_size: usize) -> Self {
-        self.download_config.chunked_size = chunk_size;
-        self
-    }
-    fn max_retries(mut self, max_retries: u32) -> Self {
-        self.download_config.max_retries = max_retries;
-        self
-    }
-    fn user_agent(mut self, user_agent: impl Into<String>) -> Self {
-        self.download_config.user_agent = user_agent.into();
-        self
-    }
-}
-
 pub struct Downloader {
     client: Client,
     download_config: DownloaderConfig,
@@ -76,9 +48,20 @@ impl Downloader {
         let url = format_response
             .url
             .as_ref()
-            .ok_or(YtdlError::FormatNotAvailable(18))?;
+            .ok_or(YtdlError::SignatureRequired)?;
         self.download_url(url, output_path).await
     }
+    /// Fetches a small resource such as a thumbnail into memory.
+    pub async fn fetch(&self, url: &str) -> Result<Vec<u8>> {
+        let response = self
+            .client
+            .get(url)
+            .header(USER_AGENT, &self.download_config.user_agent)
+            .send()
+            .await?;
+        response.error_for_status_ref()?;
+        Ok(response.bytes().await?.to_vec())
+    }
     async fn download_url(&self, url: &str, output_path: &Path) -> Result<PathBuf> {
         if let Some(parent) = output_path.parent() {
             tokio::fs::create_dir_all(parent).await?;
@@ -103,10 +86,7 @@ impl Downloader {
         Ok(output_path.to_path_buf())
     }
 
-    async fn write_response_to_file(
-        response: reqwest::Response,
-        output_path: &Path,
-    ) -> Result<()> {
+    async fn write_response_to_file(response: reqwest::Response, output_path: &Path) -> Result<()> {
         let mut file = File::create(output_path).await?;
         let mut stream = response.bytes_stream();
 
@@ -1,4 +1,7 @@
+pub mod captions;
 pub mod client;
 mod constant;
 pub mod facade;
+pub mod info;
 pub mod media_downloader;
+pub mod mp4_tags;
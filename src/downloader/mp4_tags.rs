@@ -0,0 +1,465 @@
+use std::{
+    fs::{self, File},
+    io::{self, Read, Seek, SeekFrom, Write},
+    ops::Range,
+    path::Path,
+};
+
+/// `data` atom type of UTF-8 text.
+const TYPE_UTF8: u32 = 1;
+const TYPE_JPEG: u32 = 13;
+const TYPE_PNG: u32 = 14;
+/// Boxes on the way from `moov` to the chunk offset tables.
+const CONTAINERS: [&[u8; 4]; 4] = [b"trak", b"mdia", b"minf", b"stbl"];
+
+/// iTunes-style metadata written into `moov/udta/meta/ilst` of an MP4 file.
+#[derive(Debug, Clone, Default)]
+pub struct Mp4Tags {
+    pub title: Option<String>,
+    pub artist: Option<String>,
+    pub comment: Option<String>,
+    /// Release date, `YYYY-MM-DD`.
+    pub date: Option<String>,
+    /// JPEG or PNG image; anything else is left out.
+    pub cover: Option<Vec<u8>>,
+}
+
+impl Mp4Tags {
+    /// Replaces the metadata of the MP4 file at `path`. The file is rewritten
+    /// next to the original and moved over it, so a failure leaves it as it
+    /// was.
+    pub fn write(&self, path: &Path) -> io::Result<()> {
+        let mut file = File::open(path)?;
+        let len = file.metadata()?.len();
+        let mut boxes = Vec::new();
+        let mut offset = 0;
+        while offset < len {
+            let (kind, size, _) = read_header(&mut file, offset, len)?;
+            boxes.push((kind, offset..offset + size));
+            offset += size;
+        }
+        if boxes.iter().any(|(kind, _)| kind == b"moof") {
+            // Fragments address their data in ways a bigger `moov` would break.
+            return Err(io::Error::new(
+                io::ErrorKind::Unsupported,
+                "fragmented MP4 files aren't tagged",
+            ));
+        }
+        let moov_range = boxes
+            .iter()
+            .find(|(kind, _)| kind == b"moov")
+            .map(|(_, range)| range.clone())
+            .ok_or_else(|| invalid("no moov box"))?;
+
+        let mut moov = vec![0; (moov_range.end - moov_range.start) as usize];
+        file.seek(SeekFrom::Start(moov_range.start))?;
+        file.read_exact(&mut moov)?;
+        let mut new_moov = self.rebuild_moov(&moov)?;
+        // Everything stored after `moov` moves by however much it grew.
+        let delta = new_moov.len() as i64 - moov.len() as i64;
+        shift_chunk_offsets(&mut new_moov, moov_range.end, delta)?;
+
+        let tmp = path.with_extension("tagging");
+        let result = (|| {
+            let mut out = File::create(&tmp)?;
+            for (kind, range) in &boxes {
+                if kind == b"moov" {
+                    out.write_all(&new_moov)?;
+                } else {
+                    file.seek(SeekFrom::Start(range.start))?;
+                    io::copy(&mut (&mut file).take(range.end - range.start), &mut out)?;
+                }
+            }
+            out.sync_all()?;
+            fs::rename(&tmp, path)
+        })();
+        if result.is_err() {
+            let _ = fs::remove_file(&tmp);
+        }
+        result
+    }
+    /// `moov` with its `udta/meta` replaced by these tags. Other `udta`
+    /// children are kept.
+    fn rebuild_moov(&self, moov: &[u8]) -> io::Result<Vec<u8>> {
+        let header = header_len(moov)?;
+        let mut body = Vec::new();
+        let mut udta = Vec::new();
+        for (kind, range, child_header) in children(moov, header..moov.len())? {
+            if &kind == b"udta" {
+                let udta_body = range.start + child_header..range.end;
+                for (kind, range, _) in children(moov, udta_body)? {
+                    if &kind != b"meta" {
+                        udta.extend_from_slice(&moov[range]);
+                    }
+                }
+            } else {
+                body.extend_from_slice(&moov[range]);
+            }
+        }
+        udta.extend(self.meta_box()?);
+        body.extend(make_box(b"udta", &udta)?);
+        make_box(b"moov", &body)
+    }
+    fn meta_box(&self) -> io::Result<Vec<u8>> {
+        let mut hdlr = vec![0; 8];
+        hdlr.extend_from_slice(b"mdirappl");
+        hdlr.extend_from_slice(&[0; 9]);
+
+        let mut ilst = Vec::new();
+        let text = [
+            (b"\xa9nam", &self.title),
+            (b"\xa9ART", &self.artist),
+            (b"\xa9cmt", &self.comment),
+            (b"\xa9day", &self.date),
+        ];
+        for (kind, value) in text {
+            if let Some(value) = value {
+                ilst.extend(item(kind, TYPE_UTF8, value.as_bytes())?);
+            }
+        }
+        if let Some(cover) = &self.cover
+            && let Some(kind) = image_type(cover)
+        {
+            ilst.extend(item(b"covr", kind, cover)?);
+        }
+
+        // `meta` is a full box: version and flags come first.
+        let mut meta = vec![0; 4];
+        meta.extend(make_box(b"hdlr", &hdlr)?);
+        meta.extend(make_box(b"ilst", &ilst)?);
+        make_box(b"meta", &meta)
+    }
+}
+
+fn invalid(message: &str) -> io::Error {
+    io::Error::new(io::ErrorKind::InvalidData, message.to_string())
+}
+
+/// Kind, total size and header length of the box at `offset`.
+fn read_header(file: &mut File, offset: u64, len: u64) -> io::Result<([u8; 4], u64, u64)> {
+    let mut buf = [0; 8];
+    file.seek(SeekFrom::Start(offset))?;
+    file.read_exact(&mut buf)?;
+    let kind = [buf[4], buf[5], buf[6], buf[7]];
+    let (size, header) = match u32::from_be_bytes([buf[0], buf[1], buf[2], buf[3]]) {
+        0 => (len - offset, 8),
+        1 => {
+            file.read_exact(&mut buf)?;
+            (u64::from_be_bytes(buf), 16)
+        }
+        size => (size as u64, 8),
+    };
+    if size < header || offset + size > len {
+        return Err(invalid("truncated box"));
+    }
+    Ok((kind, size, header))
+}
+
+fn header_len(data: &[u8]) -> io::Result<usize> {
+    match data
+        .get(..4)
+        .map(|s| u32::from_be_bytes(s.try_into().unwrap()))
+    {
+        Some(1) => Ok(16),
+        Some(_) => Ok(8),
+        None => Err(invalid("truncated box")),
+    }
+}
+
+/// A box inside a buffer: its kind, where it lies and how long its header is.
+type Child = ([u8; 4], Range<usize>, usize);
+
+/// Every box in `data[range]`.
+fn children(data: &[u8], range: Range<usize>) -> io::Result<Vec<Child>> {
+    let mut boxes = Vec::new();
+    let mut offset = range.start;
+    while offset + 8 <= range.end {
+        let field = |at: usize| u32::from_be_bytes(data[at..at + 4].try_into().unwrap());
+        let kind = data[offset + 4..offset + 8].try_into().unwrap();
+        let (size, header) = match field(offset) {
+            0 => (range.end - offset, 8),
+            1 if offset + 16 <= range.end => {
+                let size = u64::from_be_bytes(data[offset + 8..offset + 16].try_into().unwrap());
+                (size as usize, 16)
+            }
+            size => (size as usize, 8),
+        };
+        if size < header || offset + size > range.end {
+            return Err(invalid("truncated box"));
+        }
+        boxes.push((kind, offset..offset + size, header));
+        offset += size;
+    }
+    Ok(boxes)
+}
+
+/// Size field of a box with a `len`-byte body and a 32-bit header.
+fn box_size(len: usize) -> io::Result<u32> {
+    len.checked_add(8)
+        .and_then(|size| u32::try_from(size).ok())
+        .ok_or_else(|| invalid("box too large"))
+}
+
+fn make_box(kind: &[u8; 4], body: &[u8]) -> io::Result<Vec<u8>> {
+    let mut out = Vec::with_capacity(body.len() + 8);
+    out.extend_from_slice(&box_size(body.len())?.to_be_bytes());
+    out.extend_from_slice(kind);
+    out.extend_from_slice(body);
+    Ok(out)
+}
+
+/// An `ilst` entry holding one `data` atom.
+fn item(kind: &[u8; 4], data_type: u32, value: &[u8]) -> io::Result<Vec<u8>> {
+    let mut data = data_type.to_be_bytes().to_vec();
+    data.extend_from_slice(&[0; 4]);
+    data.extend_from_slice(value);
+    make_box(kind, &make_box(b"data", &data)?)
+}
+
+fn image_type(data: &[u8]) -> Option<u32> {
+    if data.starts_with(&[0xff, 0xd8, 0xff]) {
+        Some(TYPE_JPEG)
+    } else if data.starts_with(b"\x89PNG") {
+        Some(TYPE_PNG)
+    } else {
+        None
+    }
+}
+
+/// Moves every chunk offset at or past `from` in the `stco` and `co64`
+/// tables of `moov` by `delta` bytes.
+fn shift_chunk_offsets(moov: &mut [u8], from: u64, delta: i64) -> io::Result<()> {
+    if delta == 0 {
+        return Ok(());
+    }
+    let header = header_len(moov)?;
+    let mut pending = Vec::new();
+    pending.push(header..moov.len());
+    while let Some(range) = pending.pop() {
+        for (kind, child, child_header) in children(moov, range)? {
+            let body = child.start + child_header..child.end;
+            if CONTAINERS.contains(&&kind) {
+                pending.push(body);
+            } else if &kind == b"stco" || &kind == b"co64" {
+                let width = if &kind == b"stco" { 4 } else { 8 };
+                shift_table(&mut moov[body], width, from, delta)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+fn shift_table(table: &mut [u8], width: usize, from: u64, delta: i64) -> io::Result<()> {
+    let count = table
+        .get(4..8)
+        .map(|s| u32::from_be_bytes(s.try_into().unwrap()) as usize)
+        .ok_or_else(|| invalid("truncated chunk offset table"))?;
+    let entries = table
+        .get_mut(8..8 + count * width)
+        .ok_or_else(|| invalid("truncated chunk offset table"))?;
+    for entry in entries.chunks_exact_mut(width) {
+        let offset = if width == 4 {
+            u32::from_be_bytes(entry.try_into().unwrap()) as u64
+        } else {
+            u64::from_be_bytes(entry.try_into().unwrap())
+        };
+        if offset < from {
+            continue;
+        }
+        let shifted = offset
+            .checked_add_signed(delta)
+            .ok_or_else(|| invalid("bad offset"))?;
+        if width == 4 {
+            let shifted =
+                u32::try_from(shifted).map_err(|_| invalid("chunk offset doesn't fit in stco"))?;
+            entry.copy_from_slice(&shifted.to_be_bytes());
+        } else {
+            entry.copy_from_slice(&shifted.to_be_bytes());
+        }
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::path::PathBuf;
+
+    use super::*;
+
+    fn mp4_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        make_box(kind, body).unwrap()
+    }
+
+    /// The same box with a 64-bit size field.
+    fn large_box(kind: &[u8; 4], body: &[u8]) -> Vec<u8> {
+        let mut out = 1u32.to_be_bytes().to_vec();
+        out.extend_from_slice(kind);
+        out.extend_from_slice(&(body.len() as u64 + 16).to_be_bytes());
+        out.extend_from_slice(body);
+        out
+    }
+
+    /// Track whose sample table holds one chunk offset table.
+    fn trak(kind: &[u8; 4], offsets: &[u64]) -> Vec<u8> {
+        let mut table = vec![0; 4];
+        table.extend_from_slice(&(offsets.len() as u32).to_be_bytes());
+        for &offset in offsets {
+            if kind == b"stco" {
+                table.extend_from_slice(&(offset as u32).to_be_bytes());
+            } else {
+                table.extend_from_slice(&offset.to_be_bytes());
+            }
+        }
+        let mut inner = mp4_box(kind, &table);
+        for container in [b"stbl", b"minf", b"mdia", b"trak"] {
+            inner = mp4_box(container, &inner);
+        }
+        inner
+    }
+
+    const PAYLOAD: &[u8] = b"AAAABBBB";
+
+    /// ftyp, moov and mdat, with chunks at both halves of the payload.
+    fn file(large_moov: bool) -> Vec<u8> {
+        let build = |a: u64, b: u64| {
+            let mut udta = mp4_box(b"xtra", b"kept");
+            udta.extend(mp4_box(b"meta", b"old tags"));
+            let mut moov = trak(b"stco", &[4, a, b]);
+            moov.extend(trak(b"co64", &[a]));
+            moov.extend(mp4_box(b"udta", &udta));
+            let mut data = mp4_box(b"ftyp", b"M4A isom");
+            if large_moov {
+                data.extend(large_box(b"moov", &moov));
+            } else {
+                data.extend(mp4_box(b"moov", &moov));
+            }
+            data.extend(mp4_box(b"mdat", PAYLOAD));
+            data
+        };
+        let payload = (build(0, 0).len() - PAYLOAD.len()) as u64;
+        build(payload, payload + 4)
+    }
+
+    /// Body of the box reached through `path` from the top of `data`.
+    fn find(data: &[u8], path: &[&[u8; 4]]) -> Vec<Range<usize>> {
+        let mut ranges: Vec<Range<usize>> = std::iter::once(0..data.len()).collect();
+        for kind in path {
+            ranges = ranges
+                .into_iter()
+                .flat_map(|range| children(data, range).unwrap())
+                .filter(|(k, ..)| k == *kind)
+                .map(|(_, range, header)| range.start + header..range.end)
+                .collect();
+        }
+        ranges
+    }
+
+    fn offsets(data: &[u8], kind: &[u8; 4]) -> Vec<u64> {
+        let path = [b"moov", b"trak", b"mdia", b"minf", b"stbl", kind];
+        let table = &data[find(data, &path)[0].clone()];
+        let width = if kind == b"stco" { 4 } else { 8 };
+        table[8..]
+            .chunks(width)
+            .map(|e| {
+                let mut buf = [0; 8];
+                buf[8 - width..].copy_from_slice(e);
+                u64::from_be_bytes(buf)
+            })
+            .collect()
+    }
+
+    struct Temp(PathBuf);
+
+    impl Temp {
+        fn new(name: &str, data: &[u8]) -> Self {
+            let path =
+                std::env::temp_dir().join(format!("audyo-{}-{}.m4a", name, std::process::id()));
+            fs::write(&path, data).unwrap();
+            Self(path)
+        }
+    }
+
+    impl Drop for Temp {
+        fn drop(&mut self) {
+            let _ = fs::remove_file(&self.0);
+        }
+    }
+
+    fn tags() -> Mp4Tags {
+        Mp4Tags {
+            title: Some("Song".to_string()),
+            ..Mp4Tags::default()
+        }
+    }
+
+    fn round_trip(name: &str, large_moov: bool) {
+        let before = file(large_moov);
+        let temp = Temp::new(name, &before);
+        tags().write(&temp.0).unwrap();
+        let after = fs::read(&temp.0).unwrap();
+
+        let kinds: Vec<_> = children(&after, 0..after.len())
+            .unwrap()
+            .into_iter()
+            .map(|(kind, ..)| kind)
+            .collect();
+        assert_eq!(kinds, [*b"ftyp", *b"moov", *b"mdat"]);
+        assert_eq!(&after[find(&after, &[b"mdat"])[0].clone()], PAYLOAD);
+
+        let delta = after.len() as u64 - before.len() as u64;
+        let old = offsets(&before, b"stco");
+        // The offset before `moov` stays; the ones into `mdat` move along.
+        assert_eq!(
+            offsets(&after, b"stco"),
+            [4, old[1] + delta, old[2] + delta]
+        );
+        assert_eq!(offsets(&after, b"co64"), [old[1] + delta]);
+        for (offset, chunk) in offsets(&after, b"stco")[1..].iter().zip([b"AAAA", b"BBBB"]) {
+            assert_eq!(&after[*offset as usize..*offset as usize + 4], chunk);
+        }
+
+        let udta = find(&after, &[b"moov", b"udta"]);
+        let kinds: Vec<_> = children(&after, udta[0].clone())
+            .unwrap()
+            .into_iter()
+            .map(|(kind, ..)| kind)
+            .collect();
+        assert_eq!(kinds, [*b"xtra", *b"meta"]);
+        assert!(after.windows(4).any(|w| w == b"Song"));
+        assert!(!after.windows(8).any(|w| w == b"old tags"));
+    }
+
+    #[test]
+    fn rewrites_tags_and_moves_chunk_offsets() {
+        round_trip("tags", false);
+    }
+
+    #[test]
+    fn rewrites_a_moov_with_a_64_bit_size() {
+        round_trip("tags-large", true);
+    }
+
+    #[test]
+    fn leaves_fragmented_files_alone() {
+        let mut data = file(false);
+        data.extend(mp4_box(b"moof", b""));
+        let temp = Temp::new("fragmented", &data);
+        let error = tags().write(&temp.0).unwrap_err();
+        assert_eq!(error.kind(), io::ErrorKind::Unsupported);
+        assert_eq!(fs::read(&temp.0).unwrap(), data);
+    }
+
+    #[test]
+    fn rejects_truncated_boxes() {
+        let data = file(false);
+        let temp = Temp::new("truncated", &data[..data.len() - 1]);
+        assert!(tags().write(&temp.0).is_err());
+    }
+
+    #[test]
+    fn box_sizes_must_fit_32_bits() {
+        assert_eq!(box_size(u32::MAX as usize - 8).unwrap(), u32::MAX);
+        assert!(box_size(u32::MAX as usize - 7).is_err());
+        assert!(box_size(usize::MAX).is_err());
+    }
+}
@@ -1,15 +1,20 @@
 use std::{thread, time::Duration};
 
-use crossterm::event::{self, Event as CEvent, KeyCode};
+use crossterm::event::{self, Event as CEvent, KeyCode, MouseButton, MouseEvent, MouseEventKind};
+use ratatui::layout::Position;
 use tokio::runtime::Runtime;
 
 use crate::{
     Focus,
     app::{App, SignalMessage, Volume},
-    audyo::service::AudioEvent,
+    audyo::{equalizer::BANDS, service::AudioEvent},
     downloader::{client::Result, facade::YoutubeFacade},
+    parse_timestamp,
 };
 
+/// How far `+` and `-` shift lyrics.
+const LYRICS_OFFSET_STEP_MS: i64 = 100;
+
 impl App<'_> {
     pub async fn handle_event(&mut self) -> Result<()> {
         self.poll_msg();
@@ -19,6 +24,9 @@ impl App<'_> {
         {
             self.volume = self.volume.normal();
         }
+        if self.message.is_some() && self.last_message.elapsed() > Duration::from_secs(3) {
+            self.message = None;
+        }
         if !event::poll(self.tick_rate)? {
             return Ok(());
         }
@@ -26,20 +34,147 @@ impl App<'_> {
         let event = event::read()?;
         match event {
             CEvent::Key(key_event) => match key_event.code {
-                KeyCode::Char('q') => self.should_quit = true,
-                KeyCode::Tab => {
-                    self.focus = if self.focus == Focus::Buttons {
-                        Focus::FolderList
+                KeyCode::Char(c)
+                    if matches!(
+                        self.focus,
+                        Focus::JumpTo | Focus::PresetName | Focus::SleepTimer
+                    ) =>
+                {
+                    self.text.insert(c)
+                }
+                KeyCode::Backspace
+                    if matches!(
+                        self.focus,
+                        Focus::JumpTo | Focus::PresetName | Focus::SleepTimer
+                    ) =>
+                {
+                    self.text.delete_back()
+                }
+                // The download popup takes a paste; of the letter keys only
+                // the ones it always had stay live.
+                KeyCode::Char(c)
+                    if self.focus == Focus::Popup && !matches!(c, 'q' | 's' | 'r' | '/') => {}
+                KeyCode::Esc if self.focus == Focus::SleepTimer => {
+                    self.focus = Focus::FolderList;
+                    self.text.clear();
+                }
+                KeyCode::Enter if self.focus == Focus::SleepTimer => {
+                    self.focus = Focus::FolderList;
+                    let input = self.text.value().to_string();
+                    self.set_sleep_timer(&input);
+                    self.text.clear();
+                }
+                KeyCode::Esc if self.focus == Focus::JumpTo => {
+                    self.focus = Focus::FolderList;
+                    self.text.clear();
+                }
+                KeyCode::Esc if self.focus == Focus::PresetName => {
+                    self.focus = Focus::Equalizer;
+                    self.text.clear();
+                }
+                KeyCode::Enter if self.focus == Focus::PresetName => {
+                    self.focus = Focus::Equalizer;
+                    let name = self.text.value().to_string();
+                    self.save_eq_preset_as(&name);
+                    self.text.clear();
+                }
+                KeyCode::Char('j') | KeyCode::Down if self.focus == Focus::Devices => {
+                    self.next_device()
+                }
+                KeyCode::Char('k') | KeyCode::Up if self.focus == Focus::Devices => {
+                    self.prev_device()
+                }
+                KeyCode::Enter if self.focus == Focus::Devices => self.choose_device(),
+                KeyCode::Esc if self.focus == Focus::Devices => self.focus = Focus::FolderList,
+                KeyCode::Char('D') => self.open_device_picker(),
+                KeyCode::Enter if self.focus == Focus::FolderList => self.play_from_here(),
+                KeyCode::Char('N') if self.focus == Focus::FolderList => self.queue_play_next(),
+                KeyCode::Char('+') if self.focus == Focus::FolderList => self.queue_add(),
+                KeyCode::Char('o') if self.focus == Focus::FolderList => self.cycle_sort(),
+                KeyCode::Char('O') if self.focus == Focus::FolderList => self.reverse_sort(),
+                KeyCode::Char('j') | KeyCode::Down if self.focus == Focus::Queue => {
+                    self.next_queue_entry()
+                }
+                KeyCode::Char('k') | KeyCode::Up if self.focus == Focus::Queue => {
+                    self.prev_queue_entry()
+                }
+                KeyCode::Enter if self.focus == Focus::Queue => self.play_queue_entry(),
+                KeyCode::Char('d') | KeyCode::Delete if self.focus == Focus::Queue => {
+                    self.remove_queue_entry()
+                }
+                KeyCode::Char('K') if self.focus == Focus::Queue => self.move_queue_entry(-1),
+                KeyCode::Char('J') if self.focus == Focus::Queue => self.move_queue_entry(1),
+                KeyCode::Char('C') if self.focus == Focus::Queue => self.clear_queue(),
+                KeyCode::Char('j') | KeyCode::Down if self.focus == Focus::Lyrics => {
+                    self.move_lyrics_line(1)
+                }
+                KeyCode::Char('k') | KeyCode::Up if self.focus == Focus::Lyrics => {
+                    self.move_lyrics_line(-1)
+                }
+                KeyCode::Enter if self.focus == Focus::Lyrics => self.play_lyrics_line(),
+                KeyCode::Char('+') | KeyCode::Char('=') if self.focus == Focus::Lyrics => {
+                    self.shift_lyrics(LYRICS_OFFSET_STEP_MS)
+                }
+                KeyCode::Char('-') if self.focus == Focus::Lyrics => {
+                    self.shift_lyrics(-LYRICS_OFFSET_STEP_MS)
+                }
+                KeyCode::Esc if self.focus == Focus::Lyrics => {
+                    self.lyrics_line = None;
+                    self.focus = Focus::FolderList;
+                }
+                KeyCode::Char('y') => self.toggle_lyrics(),
+                KeyCode::Char('h') | KeyCode::Left if self.focus == Focus::Equalizer => {
+                    self.eq_band = self.eq_band.saturating_sub(1);
+                }
+                KeyCode::Char('l') | KeyCode::Right if self.focus == Focus::Equalizer => {
+                    self.eq_band = (self.eq_band + 1).min(BANDS - 1);
+                }
+                KeyCode::Char('k') | KeyCode::Up if self.focus == Focus::Equalizer => {
+                    self.adjust_eq_band(1.0)
+                }
+                KeyCode::Char('j') | KeyCode::Down if self.focus == Focus::Equalizer => {
+                    self.adjust_eq_band(-1.0)
+                }
+                KeyCode::Char('p') if self.focus == Focus::Equalizer => self.next_eq_preset(),
+                KeyCode::Char('o') if self.focus == Focus::Equalizer => self.toggle_eq_override(),
+                KeyCode::Char('S') if self.focus == Focus::Equalizer => {
+                    self.focus = Focus::PresetName
+                }
+                KeyCode::Char('e') => {
+                    self.show_equalizer = !self.show_equalizer;
+                    self.focus = if self.show_equalizer {
+                        Focus::Equalizer
                     } else {
-                        Focus::Buttons
+                        Focus::FolderList
+                    };
+                }
+                KeyCode::Esc if self.focus == Focus::Equalizer => {
+                    self.show_equalizer = false;
+                    self.focus = Focus::FolderList;
+                }
+                KeyCode::Enter if self.focus == Focus::JumpTo => {
+                    self.focus = Focus::FolderList;
+                    match parse_timestamp(self.text.value()) {
+                        Some(pos) => {
+                            let result = self.audio_service.seek_to(pos);
+                            self.report_seek(result);
+                        }
+                        None => self.notify(format!("Invalid time: {}", self.text.value())),
                     }
+                    self.text.clear();
+                }
+                KeyCode::Char('q') => self.should_quit = true,
+                KeyCode::Tab => {
+                    self.focus = match self.focus {
+                        Focus::FolderList => Focus::Queue,
+                        Focus::Queue if self.show_lyrics => Focus::Lyrics,
+                        Focus::Queue | Focus::Lyrics => Focus::Buttons,
+                        _ => Focus::FolderList,
+                    };
+                    self.lyrics_line = None;
                 }
                 KeyCode::Char('/') | KeyCode::F(1) => {
-                    if !self.show_help {
-                        self.show_help = true
-                    } else {
-                        self.show_help = false
-                    }
+                    self.show_help = !self.show_help;
                 }
                 KeyCode::Char('s') => {
                     if self.focus == Focus::Popup {
@@ -62,6 +197,41 @@ impl App<'_> {
                 KeyCode::Char('r') => {
                     self.load_folder();
                 }
+                KeyCode::Char('g') => self.focus = Focus::JumpTo,
+                KeyCode::Char('z') => self.focus = Focus::SleepTimer,
+                KeyCode::Char('w') => self.show_waveform = !self.show_waveform,
+                KeyCode::Char('v') => self.main_view = self.main_view.next(),
+                KeyCode::Char('n') => self.toggle_normalization(),
+                KeyCode::Char('a') => self.mark_ab(),
+                KeyCode::Char('A') => self.save_ab_section(),
+                KeyCode::Char('L') => self.next_saved_section(),
+                KeyCode::Char(',') => {
+                    let result = self.audio_service.seek_backward(self.config.seek_step());
+                    self.report_seek(result);
+                }
+                KeyCode::Char('.') => {
+                    let result = self.audio_service.seek_forward(self.config.seek_step());
+                    self.report_seek(result);
+                }
+                KeyCode::Char('<') => {
+                    let result = self
+                        .audio_service
+                        .seek_backward(self.config.long_seek_step());
+                    self.report_seek(result);
+                }
+                KeyCode::Char('>') => {
+                    let result = self
+                        .audio_service
+                        .seek_forward(self.config.long_seek_step());
+                    self.report_seek(result);
+                }
+                KeyCode::Char(c @ '0'..='9') => {
+                    let ratio = c.to_digit(10).unwrap_or(0) as f64 / 10.0;
+                    let result = self.audio_service.seek_to_ratio(ratio);
+                    self.report_seek(result);
+                }
+                KeyCode::Char('[') => self.audio_service.speed_down(),
+                KeyCode::Char(']') => self.audio_service.speed_up(),
 
                 KeyCode::Char('j') | KeyCode::Down => {
                     if self.focus == Focus::FolderList {
@@ -72,6 +242,7 @@ impl App<'_> {
                             1 => {
                                 self.toggle_decrease_vol();
                             }
+                            6 => self.audio_service.decrease_crossfade(),
                             _ => {}
                         }
                     }
@@ -85,71 +256,68 @@ impl App<'_> {
                             1 => {
                                 self.toggle_increase_vol();
                             }
+                            6 => self.audio_service.increase_crossfade(),
                             _ => {}
                         }
                     }
                 }
 
-                KeyCode::Char('h') | KeyCode::Left => {
-                    if self.focus == Focus::Buttons {
-                        self.prev_button();
-                    }
+                KeyCode::Char('h') | KeyCode::Left if self.focus == Focus::Buttons => {
+                    self.prev_button();
                 }
-                KeyCode::Char('l') | KeyCode::Right => {
-                    if self.focus == Focus::Buttons {
-                        self.next_button();
-                    }
+                KeyCode::Char('l') | KeyCode::Right if self.focus == Focus::Buttons => {
+                    self.next_button();
                 }
-                KeyCode::Char(' ') => {
-                    if self.focus == Focus::Buttons {
-                        if let Some(i) = self.folder_state.selected() {
-                            match self.button_index {
-                                3 => {
-                                    match self.audio_service.audio_event {
-                                        AudioEvent::Pause => {
-                                            self.audio_service.audio_event = AudioEvent::Play;
-                                            self.audio_service.play();
-                                        }
-                                        AudioEvent::Play => {
-                                            self.audio_service.audio_event = AudioEvent::Pause;
-                                            self.audio_service.pause();
-                                        }
+                KeyCode::Char(' ') if self.focus == Focus::Buttons => {
+                    let len = self.audio_service.queue.len();
+                    match self.button_index {
+                        2..=4 if len == 0 => self.notify("Queue is empty"),
+                        3 => {
+                            if self.audio_service.current_audio.is_none()
+                                && let Some(i) = self.queue_state.selected()
+                            {
+                                self.audio_service.play_queue_from(i);
+                            } else {
+                                match self.audio_service.audio_event {
+                                    AudioEvent::Pause => {
+                                        self.audio_service.audio_event = AudioEvent::Play;
+                                        self.audio_service.play();
+                                    }
+                                    AudioEvent::Play => {
+                                        self.audio_service.audio_event = AudioEvent::Pause;
+                                        self.audio_service.pause();
                                     }
-                                    self.audio_service.current_playlist_index = i;
-                                }
-                                4 => {
-                                    let next_audio = if i == self.audio_folder.files.len() - 1 {
-                                        0
-                                    } else {
-                                        i + 1
-                                    };
-                                    self.audio_service.current_playlist_index = next_audio;
-                                    self.audio_service.audio_event = AudioEvent::Play;
-                                    self.audio_service.play();
-                                    self.folder_state.select(Some(next_audio));
-                                }
-                                2 => {
-                                    let prev_audio = if i == 0 {
-                                        self.audio_folder.files.len() - 1
-                                    } else {
-                                        i - 1
-                                    };
-                                    self.audio_service.current_playlist_index = prev_audio;
-                                    self.audio_service.audio_event = AudioEvent::Play;
-                                    self.audio_service.play();
-                                    self.folder_state.select(Some(prev_audio));
                                 }
-                                0 => self.toggle_mute(),
-                                5 => self.toggle_mode(),
-                                _ => {}
                             }
                         }
+                        4 => {
+                            let next_audio = self.audio_service.skip_next();
+                            self.queue_state.select(next_audio);
+                        }
+                        2 => {
+                            let prev_audio = self.audio_service.skip_previous();
+                            self.queue_state.select(prev_audio);
+                        }
+                        0 => self.toggle_mute(),
+                        5 => self.toggle_mode(),
+                        _ => {}
                     }
                 }
                 _ => {}
             },
             CEvent::Paste(pasted) if self.focus == Focus::Popup => {
-                self.text.content.push_str(&pasted);
+                self.text.insert_str(&pasted);
+            }
+            CEvent::Mouse(MouseEvent {
+                kind: MouseEventKind::Down(MouseButton::Left),
+                column,
+                row,
+                ..
+            }) if self.progress_area.contains(Position::new(column, row)) => {
+                let ratio =
+                    (column - self.progress_area.x) as f64 / self.progress_area.width as f64;
+                let result = self.audio_service.seek_to_ratio(ratio);
+                self.report_seek(result);
             }
 
             _ => {}
@@ -162,15 +330,21 @@ impl App<'_> {
 impl App<'_> {
     fn spawn_task_download(&mut self, video_id: String) {
         let tx = self.tx.clone();
+        let captions = self.config.download_captions.then(|| {
+            (
+                self.config.caption_languages.clone(),
+                self.config.caption_format,
+            )
+        });
         thread::spawn(move || {
             let rt = Runtime::new().unwrap();
             rt.block_on(async move {
-                let ytb_facade = YoutubeFacade::new();
-                match ytb_facade.download_audio(&video_id).await {
-                    Ok(()) => {
-                        let _ = tx.send(SignalMessage::Downloaded);
-                    }
-                    Err(_) => {}
+                let mut ytb_facade = YoutubeFacade::new();
+                if let Some((languages, format)) = captions {
+                    ytb_facade = ytb_facade.captions(languages, format);
+                }
+                if let Ok(()) = ytb_facade.download_audio(&video_id).await {
+                    let _ = tx.send(SignalMessage::Downloaded);
                 }
             });
         });
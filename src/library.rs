@@ -0,0 +1,144 @@
+use std::{
+    collections::HashSet,
+    fs,
+    path::{Path, PathBuf},
+};
+
+use crate::{audyo::metadata::is_audio, downloader::info::DownloadInfo};
+
+/// Formats symphonia decodes, taken without probing.
+const AUDIO_EXTENSIONS: [&str; 13] = [
+    "mp3", "m4a", "mp4", "aac", "flac", "ogg", "oga", "wav", "aif", "aiff", "caf", "mka", "webm",
+];
+/// Files still being written, which could probe fine while incomplete.
+const PARTIAL_EXTENSIONS: [&str; 4] = ["part", "tagging", "tmp", "crdownload"];
+/// Files commonly found next to music that are never audio, so they aren't
+/// probed on every scan.
+const OTHER_EXTENSIONS: [&str; 20] = [
+    "txt", "md", "pdf", "nfo", "log", "cue", "m3u", "m3u8", "pls", "json", "xml", "ini", "db",
+    "jpg", "jpeg", "png", "gif", "webp", "bmp", "lrc",
+];
+
+/// Playable files found under the library folders.
+#[derive(Debug, Default)]
+pub struct Scan {
+    pub files: Vec<String>,
+    /// Folders that couldn't be read, with the reason.
+    pub errors: Vec<String>,
+}
+
+/// Walks `roots` and their subfolders for playable files. Hidden entries are
+/// skipped, and every folder is read once however many symlinks lead to it.
+pub fn scan(roots: &[PathBuf]) -> Scan {
+    let mut scan = Scan::default();
+    let mut visited = HashSet::new();
+    let mut pending: Vec<PathBuf> = roots.iter().rev().cloned().collect();
+    while let Some(dir) = pending.pop() {
+        let entries = fs::canonicalize(&dir).and_then(|real| {
+            // `None` when already read through another root or a symlink.
+            visited.insert(real).then(|| fs::read_dir(&dir)).transpose()
+        });
+        let entries = match entries {
+            Ok(Some(entries)) => entries,
+            Ok(None) => continue,
+            Err(e) => {
+                scan.errors.push(format!("{}: {}", dir.display(), e));
+                continue;
+            }
+        };
+        let mut subdirs = Vec::new();
+        for entry in entries {
+            let entry = match entry {
+                Ok(entry) => entry,
+                Err(e) => {
+                    scan.errors.push(format!("{}: {}", dir.display(), e));
+                    continue;
+                }
+            };
+            let path = entry.path();
+            if entry.file_name().to_string_lossy().starts_with('.') {
+                continue;
+            }
+            // Follows symlinks; broken ones are skipped.
+            match fs::metadata(&path) {
+                Ok(meta) if meta.is_dir() => subdirs.push(path),
+                Ok(meta) if meta.is_file() && is_playable(&path) => {
+                    scan.files.push(path.display().to_string())
+                }
+                _ => {}
+            }
+        }
+        // Depth first, in name order.
+        subdirs.sort();
+        pending.extend(subdirs.into_iter().rev());
+    }
+    scan
+}
+
+/// Whether `path` looks like audio: by extension when it is a known one, by
+/// probing the file otherwise.
+pub fn is_playable(path: &Path) -> bool {
+    if DownloadInfo::is_sidecar(path) {
+        return false;
+    }
+    let extension = path
+        .extension()
+        .map(|e| e.to_string_lossy().to_lowercase())
+        .unwrap_or_default();
+    if AUDIO_EXTENSIONS.contains(&extension.as_str()) {
+        true
+    } else if PARTIAL_EXTENSIONS.contains(&extension.as_str())
+        || OTHER_EXTENSIONS.contains(&extension.as_str())
+    {
+        false
+    } else {
+        is_audio(&path.display().to_string())
+    }
+}
+
+/// Summary of `errors` short enough for the status line.
+pub fn describe_errors(errors: &[String]) -> Option<String> {
+    match errors {
+        [] => None,
+        [error] => Some(format!("Couldn't read {}", error)),
+        [first, rest @ ..] => Some(format!("Couldn't read {} (and {} more)", first, rest.len())),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use hound::{SampleFormat, WavSpec, WavWriter};
+
+    use super::*;
+
+    fn wav(path: &Path) {
+        let spec = WavSpec {
+            channels: 1,
+            sample_rate: 8000,
+            bits_per_sample: 16,
+            sample_format: SampleFormat::Int,
+        };
+        let mut writer = WavWriter::create(path, spec).unwrap();
+        for _ in 0..800 {
+            writer.write_sample(0i16).unwrap();
+        }
+        writer.finalize().unwrap();
+    }
+
+    #[test]
+    fn known_extensions_skip_the_probe() {
+        let dir = std::env::temp_dir().join(format!("audyo-playable-{}", std::process::id()));
+        fs::create_dir_all(&dir).unwrap();
+        // Audio either way, so only a probe tells them apart.
+        for name in ["tone", "tone.txt", "tone.JPG", "tone.json", "tone.m4a.part"] {
+            wav(&dir.join(name));
+        }
+        assert!(is_playable(&dir.join("tone")));
+        for name in ["tone.txt", "tone.JPG", "tone.json", "tone.m4a.part"] {
+            assert!(!is_playable(&dir.join(name)), "{name}");
+        }
+        // Not even opened.
+        assert!(is_playable(&dir.join("missing.mp3")));
+        fs::remove_dir_all(dir).unwrap();
+    }
+}
@@ -0,0 +1,297 @@
+use std::{
+    collections::{HashMap, HashSet},
+    fs, io,
+    path::{Path, PathBuf},
+    time::UNIX_EPOCH,
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{
+    audyo::metadata::{TrackTags, read_tags},
+    config::data_dir,
+    downloader::info::DownloadInfo,
+    library::{self, Scan},
+};
+
+/// Bumped whenever `Entry` changes meaning; an index of another version is
+/// thrown away and rebuilt by the next scan.
+const VERSION: u32 = 1;
+
+/// Size and modification time of a file, which tell whether it changed
+/// since it was probed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default, Serialize, Deserialize)]
+pub struct Stamp {
+    pub size: u64,
+    /// Nanoseconds since the Unix epoch.
+    pub mtime_ns: u64,
+}
+
+impl Stamp {
+    pub fn of(path: &str) -> Option<Self> {
+        let meta = fs::metadata(path).ok()?;
+        let mtime = meta.modified().ok()?.duration_since(UNIX_EPOCH).ok()?;
+        Some(Self {
+            size: meta.len(),
+            mtime_ns: mtime.as_nanos() as u64,
+        })
+    }
+}
+
+/// What is known about one file of the library.
+#[derive(Debug, Clone, Default, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Entry {
+    pub stamp: Stamp,
+    pub tags: TrackTags,
+    /// Integrated loudness measured by the last local scan, in LUFS.
+    pub loudness: Option<f64>,
+    /// Loudness YouTube reported for a download, in LUFS.
+    pub reported_loudness: Option<f64>,
+    /// Video a download came from.
+    pub video_id: Option<String>,
+    pub play_count: u32,
+}
+
+impl Entry {
+    /// Reads `path` and the info saved next to it. `None` when the file is
+    /// gone.
+    pub fn probe(path: &str) -> Option<Self> {
+        let stamp = Stamp::of(path)?;
+        let info = DownloadInfo::load(Path::new(path));
+        Some(Self {
+            stamp,
+            tags: read_tags(path).unwrap_or_default(),
+            loudness: None,
+            reported_loudness: info.as_ref().and_then(DownloadInfo::loudness_lufs),
+            video_id: info.map(|i| i.video_id),
+            play_count: 0,
+        })
+    }
+}
+
+/// Every file of the library with its tags, loudness and play count, kept
+/// in `~/.audyo_plaier/library.json` so startup doesn't probe them again.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct LibraryIndex {
+    version: u32,
+    entries: HashMap<String, Entry>,
+    /// Whether there are changes the file doesn't have yet.
+    #[serde(skip)]
+    dirty: bool,
+}
+
+impl Default for LibraryIndex {
+    fn default() -> Self {
+        Self {
+            version: VERSION,
+            entries: HashMap::new(),
+            dirty: false,
+        }
+    }
+}
+
+impl LibraryIndex {
+    fn path() -> PathBuf {
+        data_dir().join("library.json")
+    }
+    /// The saved index; empty on first start and when it was written by
+    /// another version.
+    pub fn load() -> Self {
+        fs::read_to_string(Self::path())
+            .map(|json| Self::parse(&json))
+            .unwrap_or_default()
+    }
+    fn parse(json: &str) -> Self {
+        serde_json::from_str::<Self>(json)
+            .ok()
+            .filter(|index| index.version == VERSION)
+            .unwrap_or_default()
+    }
+    /// Writes the index if it changed, through a temporary file so a crash
+    /// never leaves half of it behind.
+    pub fn save(&mut self) -> io::Result<()> {
+        if !self.dirty {
+            return Ok(());
+        }
+        fs::create_dir_all(data_dir())?;
+        let path = Self::path();
+        let temp = path.with_extension("json.tmp");
+        fs::write(&temp, serde_json::to_string(self)?)?;
+        fs::rename(temp, path)?;
+        self.dirty = false;
+        Ok(())
+    }
+    pub fn get(&self, path: &str) -> Option<&Entry> {
+        self.entries.get(path)
+    }
+    /// Indexed files under `roots`, as they were last seen.
+    pub fn files_under(&self, roots: &[PathBuf]) -> Vec<String> {
+        self.entries
+            .keys()
+            .filter(|path| roots.iter().any(|root| Path::new(path).starts_with(root)))
+            .cloned()
+            .collect()
+    }
+    pub fn stamps(&self) -> HashMap<String, Stamp> {
+        self.entries
+            .iter()
+            .map(|(path, entry)| (path.clone(), entry.stamp))
+            .collect()
+    }
+    /// Loudness of each file to go by until it is scanned: the last local
+    /// scan, or else YouTube's figure.
+    pub fn known_loudness(&self) -> HashMap<String, f64> {
+        self.entries
+            .iter()
+            .filter_map(|(path, entry)| {
+                let lufs = entry.loudness.or(entry.reported_loudness)?;
+                Some((path.clone(), lufs))
+            })
+            .collect()
+    }
+    /// Album tag of each file that has one.
+    pub fn albums(&self) -> HashMap<String, String> {
+        self.entries
+            .iter()
+            .filter_map(|(path, entry)| Some((path.clone(), entry.tags.album.clone()?)))
+            .collect()
+    }
+    /// Stores a fresh probe of `path`, keeping its play count and video.
+    pub fn insert(&mut self, path: String, mut entry: Entry) {
+        if let Some(old) = self.entries.get(&path) {
+            entry.play_count = old.play_count;
+            entry.video_id = entry.video_id.or_else(|| old.video_id.clone());
+        }
+        self.entries.insert(path, entry);
+        self.dirty = true;
+    }
+    pub fn remove(&mut self, path: &str) {
+        self.dirty |= self.entries.remove(path).is_some();
+    }
+    pub fn rename(&mut self, from: &str, to: &str) {
+        if let Some(entry) = self.entries.remove(from) {
+            self.entries.insert(to.to_string(), entry);
+            self.dirty = true;
+        }
+    }
+    /// Drops files a scan of `roots` didn't find, and files outside them.
+    /// Roots that are gone altogether, like an unplugged drive, keep their
+    /// entries until they come back.
+    pub fn prune(&mut self, found: &[String], roots: &[PathBuf]) {
+        let found: HashSet<&String> = found.iter().collect();
+        let missing: Vec<&PathBuf> = roots.iter().filter(|root| !root.exists()).collect();
+        let before = self.entries.len();
+        self.entries.retain(|path, _| {
+            found.contains(path) || missing.iter().any(|root| Path::new(path).starts_with(root))
+        });
+        self.dirty |= self.entries.len() != before;
+    }
+    /// Counts another play of `path`.
+    pub fn played(&mut self, path: &str) {
+        if let Some(entry) = self.entries.get_mut(path) {
+            entry.play_count += 1;
+            self.dirty = true;
+        }
+    }
+    pub fn set_loudness(&mut self, path: &str, lufs: Option<f64>) {
+        if let Some(entry) = self.entries.get_mut(path)
+            && entry.loudness != lufs
+        {
+            entry.loudness = lufs;
+            self.dirty = true;
+        }
+    }
+}
+
+/// Files found by a background scan, with fresh probes of the ones that
+/// are new or changed.
+pub struct Refresh {
+    pub scan: Scan,
+    pub probed: Vec<(String, Entry)>,
+}
+
+/// Scans `roots`, probing only files whose size or modification time
+/// differs from `stamps`.
+pub fn refresh(roots: &[PathBuf], stamps: &HashMap<String, Stamp>) -> Refresh {
+    let scan = library::scan(roots);
+    let probed = scan
+        .files
+        .iter()
+        .filter(|file| Stamp::of(file).is_none_or(|stamp| stamps.get(*file) != Some(&stamp)))
+        .filter_map(|file| Some((file.clone(), Entry::probe(file)?)))
+        .collect();
+    Refresh { scan, probed }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn entry(size: u64) -> Entry {
+        Entry {
+            stamp: Stamp { size, mtime_ns: 1 },
+            ..Entry::default()
+        }
+    }
+
+    #[test]
+    fn other_versions_are_thrown_away() {
+        let mut index = LibraryIndex::default();
+        index.insert("a.mp3".to_string(), entry(1));
+        let json = serde_json::to_string(&index).unwrap();
+        assert!(LibraryIndex::parse(&json).get("a.mp3").is_some());
+        let old = json.replace(&format!("\"version\":{}", VERSION), "\"version\":0");
+        assert_ne!(old, json);
+        assert!(LibraryIndex::parse(&old).get("a.mp3").is_none());
+        assert!(LibraryIndex::parse("{").get("a.mp3").is_none());
+    }
+
+    #[test]
+    fn insert_keeps_play_count_and_video() {
+        let mut index = LibraryIndex::default();
+        index.insert(
+            "a.mp3".to_string(),
+            Entry {
+                video_id: Some("abc".to_string()),
+                ..entry(1)
+            },
+        );
+        index.played("a.mp3");
+        index.played("a.mp3");
+        index.insert("a.mp3".to_string(), entry(2));
+        let a = index.get("a.mp3").unwrap();
+        assert_eq!(a.stamp.size, 2);
+        assert_eq!(a.play_count, 2);
+        assert_eq!(a.video_id.as_deref(), Some("abc"));
+    }
+
+    #[test]
+    fn prune_keeps_found_files_and_missing_roots() {
+        let present = std::env::temp_dir().join(format!("audyo-prune-{}", std::process::id()));
+        fs::create_dir_all(&present).unwrap();
+        let missing = present.join("unplugged");
+        let path = |root: &Path, name: &str| root.join(name).display().to_string();
+        let mut index = LibraryIndex::default();
+        for file in [
+            path(&present, "found.mp3"),
+            path(&present, "deleted.mp3"),
+            path(&missing, "away.mp3"),
+            path(Path::new("/elsewhere"), "outside.mp3"),
+        ] {
+            index.insert(file, entry(1));
+        }
+        index.dirty = false;
+        index.prune(
+            &[path(&present, "found.mp3")],
+            &[present.clone(), missing.clone()],
+        );
+        let mut kept = index.files_under(&[PathBuf::from("/")]);
+        kept.sort();
+        let mut expected = [path(&present, "found.mp3"), path(&missing, "away.mp3")];
+        expected.sort();
+        assert_eq!(kept, expected);
+        assert!(index.dirty);
+        fs::remove_dir_all(present).unwrap();
+    }
+}
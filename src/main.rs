@@ -1,19 +1,22 @@
-use std::{error::Error, io, time::Duration};
+use std::{
+    error::Error,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
 
 use crossterm::{
-    event::EnableBracketedPaste,
+    event::{DisableMouseCapture, EnableBracketedPaste, EnableMouseCapture},
     execute,
     terminal::{EnterAlternateScreen, LeaveAlternateScreen, disable_raw_mode, enable_raw_mode},
 };
-use glob::glob;
 use ratatui::{
     Terminal,
     backend::CrosstermBackend,
     layout::{Constraint, Layout, Rect},
     style::{Color, Modifier, Style, palette::tailwind},
-    symbols::bar::NINE_LEVELS,
     text::Span,
-    widgets::{Block, Borders, List, ListItem, Paragraph, Sparkline},
+    widgets::{Block, Borders, List, ListItem, Paragraph, Row, Table},
 };
 use ratatui::{
     text::Line,
@@ -24,64 +27,206 @@ mod audyo;
 use audyo::service::AudioService;
 
 mod app;
-use app::App;
+use app::{App, MainView};
+
+mod config;
+mod library;
+mod library_index;
+mod session;
+mod watcher;
+use library_index::{Entry, LibraryIndex, Refresh};
 
-use crate::audyo::service::AudioEvent;
+use crate::audyo::{metadata::TrackTags, service::AudioEvent, sleep::SleepTimer};
 
 mod downloader;
 mod events;
 mod ui;
+use ui::{equalizer::EqualizerPanel, lyrics::LyricsPanel, waveform::Waveform};
 
 const CUSTOM_LABEL_COLOR: Color = tailwind::WHITE;
 const GAUGE3_COLOR: Color = tailwind::GRAY.c800;
 
+/// Column the library is sorted by.
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum SortColumn {
+    File,
+    Title,
+    Artist,
+    Album,
+    Duration,
+}
+
+impl SortColumn {
+    fn next(&self) -> Self {
+        match self {
+            Self::File => Self::Title,
+            Self::Title => Self::Artist,
+            Self::Artist => Self::Album,
+            Self::Album => Self::Duration,
+            Self::Duration => Self::File,
+        }
+    }
+    fn text(&self) -> &'static str {
+        match self {
+            Self::File => "file",
+            Self::Title => "title",
+            Self::Artist => "artist",
+            Self::Album => "album",
+            Self::Duration => "duration",
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 struct AudioFolder {
-    path: String,
+    /// Folders scanned for audio, with their subfolders.
+    roots: Vec<PathBuf>,
     files: Vec<String>,
+    /// Folders the last scan couldn't read.
+    errors: Vec<String>,
+    /// Tags and the like of every file, kept between runs.
+    index: LibraryIndex,
+    sort: SortColumn,
+    descending: bool,
 }
 
 impl AudioFolder {
     fn new() -> Self {
         Self {
-            path: String::new(),
+            roots: Vec::new(),
             files: Vec::new(),
+            errors: Vec::new(),
+            index: LibraryIndex::default(),
+            sort: SortColumn::File,
+            descending: false,
         }
     }
-    fn path(mut self, path: String) -> Self {
-        self.path = path;
+    fn tags(&self, path: &str) -> Option<&TrackTags> {
+        self.index.get(path).map(|entry| &entry.tags)
+    }
+    /// The track's "artist – title", or its file name without tags.
+    fn display_name(&self, path: &str) -> String {
+        self.tags(path)
+            .and_then(TrackTags::display_title)
+            .unwrap_or_else(|| file_name(path))
+    }
+    fn sort(&mut self) {
+        let index = &self.index;
+        let tags = |path: &String| index.get(path).map(|entry| &entry.tags);
+        // Tracks missing the tag go last.
+        let text = |path: &String, f: fn(&TrackTags) -> Option<&String>| {
+            let value = tags(path).and_then(f).map(|s| s.to_lowercase());
+            (value.is_none(), value)
+        };
+        match self.sort {
+            SortColumn::File => self.files.sort(),
+            SortColumn::Title => self.files.sort_by_cached_key(|p| {
+                let title = tags(p).and_then(|t| t.title.as_ref());
+                title
+                    .map_or_else(|| file_name(p), |t| t.clone())
+                    .to_lowercase()
+            }),
+            SortColumn::Artist => self
+                .files
+                .sort_by_cached_key(|p| text(p, |t| t.artist.as_ref())),
+            SortColumn::Album => self
+                .files
+                .sort_by_cached_key(|p| text(p, |t| t.album.as_ref())),
+            SortColumn::Duration => self.files.sort_by_cached_key(|p| {
+                let duration = tags(p).and_then(|t| t.duration);
+                (duration.is_none(), duration)
+            }),
+        }
+        if self.descending {
+            self.files.reverse();
+        }
+    }
+    fn roots(mut self, roots: Vec<PathBuf>) -> Self {
+        self.roots = roots;
+        self
+    }
+    fn index(mut self, index: LibraryIndex) -> Self {
+        self.index = index;
         self
     }
-    fn load_mp3_file(&mut self) {
-        let path = match glob(&self.path) {
-            Ok(path) => path,
-            Err(_) => {
-                eprintln!("Invalid file path {}", &self.path);
-                return;
+    /// Adds `path`, or refreshes its entry when it is already listed.
+    fn add(&mut self, path: String, entry: Entry) {
+        if !self.files.contains(&path) {
+            self.files.push(path.clone());
+        }
+        self.index.insert(path, entry);
+    }
+    /// Drops `path` and, for a folder, everything under it. Returns the
+    /// files that were listed.
+    fn remove(&mut self, path: &str) -> Vec<String> {
+        let (gone, kept) = self
+            .files
+            .drain(..)
+            .partition(|f| Path::new(f).starts_with(path));
+        self.files = kept;
+        for file in &gone {
+            self.index.remove(file);
+        }
+        gone
+    }
+    /// Follows a file or folder moving from `from` to `to`. Returns the old
+    /// and new path of every listed file that moved.
+    fn rename(&mut self, from: &str, to: &str) -> Vec<(String, String)> {
+        let mut moved = Vec::new();
+        for file in &self.files {
+            if let Ok(rest) = Path::new(file).strip_prefix(from) {
+                let new = watcher::join(Path::new(to), rest).display().to_string();
+                moved.push((file.clone(), new));
             }
-        };
-        let mut files: Vec<_> = Vec::new();
-        for entry in path {
-            match entry {
-                Ok(file) => {
-                    let f = file.display().to_string();
-                    files.push(f);
-                }
-                Err(e) => {
-                    eprintln!("Glob error {}", e);
-                    return;
-                }
-            };
         }
-        self.files = files;
+        for (old, new) in &moved {
+            // A file moved over another one replaces it.
+            self.files.retain(|f| f != new);
+            if let Some(f) = self.files.iter_mut().find(|f| *f == old) {
+                *f = new.clone();
+            }
+            self.index.rename(old, new);
+        }
+        moved
+    }
+    /// Lists the library as the index last saw it, without touching the
+    /// files.
+    fn load_index(&mut self) {
+        self.files = self.index.files_under(&self.roots);
+        self.sort();
+    }
+    /// Takes in what a background scan found. Folders that couldn't be
+    /// read end up in `errors`.
+    fn apply_refresh(&mut self, refresh: Refresh) {
+        for (path, entry) in refresh.probed {
+            self.index.insert(path, entry);
+        }
+        self.index.prune(&refresh.scan.files, &self.roots);
+        self.files = refresh.scan.files;
+        self.errors = refresh.scan.errors;
+        self.sort();
     }
 }
 
+fn file_name(path: &str) -> String {
+    std::path::Path::new(path)
+        .file_name()
+        .map(|n| n.to_string_lossy().into_owned())
+        .unwrap_or_else(|| path.to_string())
+}
+
 #[derive(PartialEq, Debug)]
 enum Focus {
     FolderList,
+    Queue,
     Buttons,
     Popup,
+    JumpTo,
+    Equalizer,
+    PresetName,
+    Devices,
+    SleepTimer,
+    Lyrics,
 }
 
 impl<'a> App<'a> {
@@ -107,6 +252,27 @@ impl<'a> App<'a> {
         self.folder_state.select(Some(i));
     }
 
+    /// Sorts the library by the next column, keeping the selected track.
+    fn cycle_sort(&mut self) {
+        self.audio_folder.sort = self.audio_folder.sort.next();
+        self.resort_library();
+    }
+    fn reverse_sort(&mut self) {
+        self.audio_folder.descending = !self.audio_folder.descending;
+        self.resort_library();
+    }
+    fn resort_library(&mut self) {
+        let selected = self
+            .folder_state
+            .selected()
+            .and_then(|i| self.audio_folder.files.get(i).cloned());
+        self.audio_folder.sort();
+        if let Some(path) = selected {
+            let i = self.audio_folder.files.iter().position(|f| *f == path);
+            self.folder_state.select(i);
+        }
+    }
+
     fn next_button(&mut self) {
         self.button_index = (self.button_index + 1) % self.buttons.len();
     }
@@ -140,66 +306,249 @@ impl App<'_> {
         if self.focus == Focus::Popup {
             self.render_search_popup(frame);
         }
+        if self.focus == Focus::JumpTo {
+            self.render_jump_popup(frame);
+        }
+        if self.focus == Focus::PresetName {
+            self.render_preset_name_popup(frame);
+        }
+        if self.focus == Focus::Devices {
+            self.render_device_popup(frame);
+        }
+        if self.focus == Focus::SleepTimer {
+            self.render_sleep_popup(frame);
+        }
         if self.show_help {
             self.render_help_popup(frame);
         }
     }
     fn render_donut(&mut self, frame: &mut ratatui::Frame, area: Rect) {
-        let track = if let Some(extract_track_name) = &self.audio_service.current_audio {
-            let split_str = extract_track_name.split("/").collect::<Vec<_>>();
-            split_str[split_str.len() - 1]
-        } else {
-            ""
+        let track = match &self.audio_service.current_audio {
+            Some(path) => match self.audio_folder.tags(path).and_then(|t| t.album.as_ref()) {
+                Some(album) => format!("{} ({})", self.audio_folder.display_name(path), album),
+                None => self.audio_folder.display_name(path),
+            },
+            None => String::new(),
         };
         let status = match self.audio_service.audio_event {
             AudioEvent::Pause => "◼ Paused",
             AudioEvent::Play => "▶ Now playing",
         };
+        let speed = self.audio_service.speed();
+        let mut title = if speed == 1.0 {
+            format!(" {}: {}", status, track)
+        } else {
+            format!(" {}: {} [{}x]", status, track, speed)
+        };
+        match (
+            self.audio_service.sleep_timer(),
+            self.audio_service.sleep_remaining(),
+        ) {
+            (_, Some(remaining)) => title += &format!(" ☾ {} ", formart_duration(remaining)),
+            (Some(SleepTimer::Tracks(1)), None) => title += " ☾ 1 more track ",
+            (Some(SleepTimer::Tracks(n)), None) => title += &format!(" ☾ {} more tracks ", n),
+            _ => {}
+        }
+        let block = Block::default().borders(Borders::ALL).title(title);
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        if self.show_equalizer {
+            self.render_equalizer(frame, inner);
+            return;
+        }
+        let inner = if self.show_lyrics {
+            let halves =
+                Layout::horizontal([Constraint::Percentage(50), Constraint::Percentage(50)])
+                    .split(inner);
+            self.render_lyrics(frame, halves[1]);
+            halves[0]
+        } else {
+            inner
+        };
+        match self.main_view {
+            MainView::Artwork if self.artwork.has_image() => {
+                // Protocol images would be drawn over popups.
+                let overlay = self.show_help
+                    || matches!(
+                        self.focus,
+                        Focus::Popup
+                            | Focus::JumpTo
+                            | Focus::PresetName
+                            | Focus::Devices
+                            | Focus::SleepTimer
+                    );
+                self.artwork.render(inner, frame.buffer_mut(), overlay);
+            }
+            MainView::Donut | MainView::Artwork => frame.render_widget(&self.donut, inner),
+            MainView::Spectrum => frame.render_widget(&self.spectrum, inner),
+        }
+    }
+    fn render_lyrics(&mut self, frame: &mut ratatui::Frame, area: Rect) {
+        let offset = self.lyrics_offset();
+        let mut block = Block::default()
+            .borders(Borders::LEFT)
+            .title(" Lyrics ")
+            .padding(Padding::horizontal(1));
+        if offset != 0 {
+            block = block.title(format!(" {:+.1}s ", offset as f64 / 1000.0));
+        }
+        if self.focus == Focus::Lyrics {
+            block = block
+                .border_style(Style::default().fg(Color::Cyan))
+                .title_bottom(" Enter jump  +/- offset ");
+        }
+        let inner = block.inner(area);
+        frame.render_widget(block, area);
+        let current = self.current_lyric();
+        let selected = self.lyrics_line.filter(|_| self.focus == Focus::Lyrics);
+        match &self.lyrics {
+            Some(lyrics) => frame.render_widget(LyricsPanel::new(lyrics, current, selected), inner),
+            None => frame.render_widget(
+                Paragraph::new("No lyrics").style(Style::default().fg(Color::DarkGray)),
+                inner,
+            ),
+        }
+    }
+    fn render_equalizer(&mut self, frame: &mut ratatui::Frame, area: Rect) {
+        let preset = self.eq_preset();
+        let scope = if self.eq_has_override() {
+            "this track"
+        } else {
+            "global"
+        };
+        // Shown once it cuts, as it explains why boosting sounds quieter.
+        let preamp = match preset.preamp_db() {
+            db if db < 0.0 => format!(", preamp {:+.0} dB", db),
+            _ => String::new(),
+        };
         let block = Block::default()
-            .borders(Borders::ALL)
-            .title(format!(" {}: {}", status, track));
+            .borders(Borders::TOP)
+            .title(format!(
+                " Equalizer: {} ({}{}) ",
+                preset.name, scope, preamp
+            ))
+            .title_bottom(" ←/→ band  ↑/↓ gain  p preset  o track  S save  e close ");
         let inner = block.inner(area);
         frame.render_widget(block, area);
-        frame.render_widget(&self.donut, inner);
+        let focused = self.focus == Focus::Equalizer;
+        frame.render_widget(EqualizerPanel::new(&preset, self.eq_band, focused), inner);
     }
     fn render_list_files(&mut self, frame: &mut ratatui::Frame, area: Rect) {
-        let folder_items: Vec<_> = self
-            .audio_folder
+        let vertical =
+            Layout::vertical([Constraint::Percentage(60), Constraint::Percentage(40)]).split(area);
+        self.render_library(frame, vertical[0]);
+        self.render_queue(frame, vertical[1]);
+        self.render_help_box(frame, area);
+    }
+    fn render_library(&mut self, frame: &mut ratatui::Frame, area: Rect) {
+        let folder = &self.audio_folder;
+        let rows: Vec<_> = folder
             .files
             .iter()
             .map(|f| {
-                let name = std::path::Path::new(f)
-                    .file_name()
-                    .map(|n| n.to_string_lossy().into_owned())
-                    .unwrap_or_else(|| f.clone());
-                ListItem::new(name)
+                let tags = folder.tags(f);
+                let album = tags.and_then(|t| t.album.clone()).unwrap_or_default();
+                let duration = tags
+                    .and_then(|t| t.duration)
+                    .map_or_else(|| "--:--".to_string(), formart_duration);
+                Row::new(vec![folder.display_name(f), album, duration])
             })
             .collect();
 
+        let arrow = if folder.descending { "▼" } else { "▲" };
+        let header = |name: &str, columns: &[SortColumn]| {
+            if columns.contains(&folder.sort) {
+                format!("{} {}{}", name, folder.sort.text(), arrow)
+            } else {
+                name.to_string()
+            }
+        };
+        let header = Row::new(vec![
+            header(
+                "Track",
+                &[SortColumn::File, SortColumn::Title, SortColumn::Artist],
+            ),
+            header("Album", &[SortColumn::Album]),
+            header("Time", &[SortColumn::Duration]),
+        ])
+        .style(Style::default().fg(Color::Yellow));
+
         let block = Block::default()
             .borders(Borders::ALL)
-            .title("Queue")
+            .title("Library")
             .title_alignment(ratatui::layout::Alignment::Center);
         let hs = Style::default().fg(Color::Black).bg(Color::Green);
 
-        let folder_list = List::new(folder_items)
+        let table = Table::new(
+            rows,
+            [
+                Constraint::Percentage(55),
+                Constraint::Percentage(30),
+                Constraint::Length(6),
+            ],
+        )
+        .header(header)
+        .block(block)
+        .row_highlight_style(hs)
+        .highlight_symbol(" >");
+        frame.render_stateful_widget(table, area, &mut self.folder_state);
+    }
+    fn render_queue(&mut self, frame: &mut ratatui::Frame, area: Rect) {
+        let current = self
+            .audio_service
+            .current_audio
+            .as_ref()
+            .map(|_| self.audio_service.queue_index);
+        let queue_items: Vec<_> = self
+            .audio_service
+            .queue
+            .iter()
+            .enumerate()
+            .map(|(i, f)| {
+                let name = self.audio_folder.display_name(f);
+                if current == Some(i) {
+                    ListItem::new(format!("▶ {}", name)).style(Style::default().fg(Color::Green))
+                } else {
+                    ListItem::new(format!("  {}", name))
+                }
+            })
+            .collect();
+
+        let hs = if self.focus == Focus::Queue {
+            Style::default().fg(Color::Black).bg(Color::Green)
+        } else {
+            Style::default().add_modifier(Modifier::BOLD)
+        };
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title(format!("Queue ({})", self.audio_service.queue.len()))
+            .title_alignment(ratatui::layout::Alignment::Center);
+        let queue_list = List::new(queue_items)
             .block(block)
             .highlight_style(hs)
             .highlight_symbol(" >");
-        frame.render_stateful_widget(folder_list, area, &mut self.folder_state);
-        self.render_help_box(frame, area);
+        frame.render_stateful_widget(queue_list, area, &mut self.queue_state);
     }
 
     fn render_button(&mut self, frame: &mut ratatui::Frame, area: Rect) {
+        let crossfade = match self.audio_service.crossfade().as_secs() {
+            0 => "⇄ off".to_string(),
+            secs => format!("⇄ {}s", secs),
+        };
         self.buttons = vec![
-            self.mute_sound.text(),
-            self.volume.text(),
-            "⏮️",
-            "⏯️",
-            "⏭️",
-            self.loop_mode.text(),
+            self.mute_sound.text().into(),
+            self.volume.text().into(),
+            "⏮️".into(),
+            "⏯️".into(),
+            "⏭️".into(),
+            self.loop_mode.text().into(),
+            crossfade.into(),
         ];
-        let button_chunks = Layout::horizontal([Constraint::Percentage(20); 6]).split(area);
+        let button_chunks = Layout::horizontal(vec![
+            Constraint::Ratio(1, self.buttons.len() as u32);
+            self.buttons.len()
+        ])
+        .split(area);
 
         for (i, button) in self.buttons.iter().enumerate() {
             let is_selected = self.focus == Focus::Buttons && self.button_index == i;
@@ -222,7 +571,7 @@ impl App<'_> {
             ])
             .split(inner);
 
-            let p = Paragraph::new(*button)
+            let p = Paragraph::new(button.as_ref())
                 .style(style)
                 .alignment(ratatui::layout::Alignment::Center);
 
@@ -249,12 +598,74 @@ impl App<'_> {
             format!("{}/{}", elapsed_time, total),
             Style::new().fg(CUSTOM_LABEL_COLOR),
         );
-        let gauge = Gauge::default()
-            .block(Block::default().title("Time").borders(Borders::ALL))
-            .gauge_style(GAUGE3_COLOR)
-            .ratio(ratio)
-            .label(span);
-        frame.render_widget(gauge, area);
+        let show_waveform = self.show_waveform && !self.audio_service.waveform.samples.is_empty();
+        let mut title = vec![Span::raw("Time ")];
+        if show_waveform {
+            title.push(span.clone());
+            title.push(Span::raw(" "));
+        }
+        if let Some(region) = self.audio_service.ab_loop() {
+            let plays = match region.plays {
+                Some(plays) => format!(" {}/{}", region.done + 1, plays),
+                None => String::new(),
+            };
+            title.push(Span::styled(
+                format!(
+                    "A-B {}-{}{} ",
+                    formart_duration(region.a),
+                    formart_duration(region.b),
+                    plays
+                ),
+                Style::new().fg(Color::Yellow),
+            ));
+        }
+        if let Some(message) = &self.message {
+            title.push(Span::styled(
+                format!("⚠ {}", message),
+                Style::new().fg(Color::Red),
+            ));
+        }
+        let block = Block::default()
+            .title(Line::from(title))
+            .borders(Borders::ALL);
+        self.progress_area = block.inner(area);
+        if show_waveform {
+            let waveform = Waveform::new(&self.audio_service.waveform, ratio);
+            frame.render_widget(waveform, self.progress_area);
+            frame.render_widget(block, area);
+        } else {
+            let gauge = Gauge::default()
+                .block(block)
+                .gauge_style(GAUGE3_COLOR)
+                .ratio(ratio)
+                .label(span);
+            frame.render_widget(gauge, area);
+        }
+        self.render_ab_markers(frame);
+    }
+    /// Draws the A and B points of the A-B loop over the seekbar.
+    fn render_ab_markers(&self, frame: &mut ratatui::Frame) {
+        let area = self.progress_area;
+        let length = self.audio_service.length as f64;
+        if length == 0.0 || area.is_empty() {
+            return;
+        }
+        let (a, b) = match self.audio_service.ab_loop() {
+            Some(region) => (Some(region.a), Some(region.b)),
+            None => (self.audio_service.ab_start(), None),
+        };
+        let buf = frame.buffer_mut();
+        for (pos, label) in [(a, "A"), (b, "B")] {
+            let Some(pos) = pos else {
+                continue;
+            };
+            let ratio = (pos.as_secs_f64() / length).min(1.0);
+            let x = area.x + ((area.width - 1) as f64 * ratio).round() as u16;
+            for y in area.y..area.bottom() {
+                buf[(x, y)].set_symbol("│").set_fg(Color::Yellow);
+            }
+            buf[(x, area.y)].set_symbol(label).set_fg(Color::Yellow);
+        }
     }
 
     fn render_search_popup(&mut self, frame: &mut ratatui::Frame) {
@@ -272,8 +683,80 @@ impl App<'_> {
         frame.render_widget(Clear, area);
         frame.render_widget(paragraph, area);
     }
+    fn render_jump_popup(&mut self, frame: &mut ratatui::Frame) {
+        let area = length_box_center(frame.area(), 30, 3);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Jump to (mm:ss)")
+            .style(Style::default().fg(Color::Yellow));
+
+        let paragraph = Paragraph::new(self.text.value())
+            .style(Style::default().fg(Color::White))
+            .block(block);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(paragraph, area);
+    }
+    fn render_preset_name_popup(&mut self, frame: &mut ratatui::Frame) {
+        let area = length_box_center(frame.area(), 30, 3);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Save preset as")
+            .style(Style::default().fg(Color::Yellow));
+
+        let paragraph = Paragraph::new(self.text.value())
+            .style(Style::default().fg(Color::White))
+            .block(block);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(paragraph, area);
+    }
+    fn render_sleep_popup(&mut self, frame: &mut ratatui::Frame) {
+        let area = length_box_center(frame.area(), 40, 3);
+
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Sleep in (minutes, end, Nt, off)")
+            .style(Style::default().fg(Color::Yellow));
+
+        let paragraph = Paragraph::new(self.text.value())
+            .style(Style::default().fg(Color::White))
+            .block(block);
+
+        frame.render_widget(Clear, area);
+        frame.render_widget(paragraph, area);
+    }
+    fn render_device_popup(&mut self, frame: &mut ratatui::Frame) {
+        let area = _popup(frame.area(), 50, 40);
+        let current = self.audio_service.output_device();
+        let items: Vec<_> = self
+            .devices
+            .iter()
+            .map(|d| {
+                if Some(d.as_str()) == current {
+                    ListItem::new(format!("{} ♪", d))
+                } else {
+                    ListItem::new(d.as_str())
+                }
+            })
+            .collect();
+        let block = Block::default()
+            .borders(Borders::ALL)
+            .title("Output device")
+            .style(Style::default().fg(Color::Yellow));
+        let list = List::new(items)
+            .block(block)
+            .style(Style::default().fg(Color::White))
+            .highlight_style(Style::default().fg(Color::Black).bg(Color::Green))
+            .highlight_symbol(" >");
+
+        frame.render_widget(Clear, area);
+        frame.render_stateful_widget(list, area, &mut self.device_state);
+    }
     fn render_help_popup(&mut self, frame: &mut ratatui::Frame) {
-        let area = _popup(frame.area(), 25, 50);
+        let area = _popup(frame.area(), 25, 70);
         let help_lines = vec![
             Line::from(vec![Span::styled(
                 "  NAVIGATION",
@@ -291,6 +774,52 @@ impl App<'_> {
                 Span::styled("    k/↑    ", Style::default().fg(Color::Cyan)),
                 Span::raw("Previous track"),
             ]),
+            Line::from(vec![
+                Span::styled("    o/O    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Sort library by next column / reverse"),
+            ]),
+            Line::from(""),
+            Line::from(vec![Span::styled(
+                "  QUEUE",
+                Style::default().fg(Color::Yellow),
+            )]),
+            Line::from(vec![
+                Span::styled("    Enter  ", Style::default().fg(Color::Cyan)),
+                Span::raw("Library: play from here / Queue: play"),
+            ]),
+            Line::from(vec![
+                Span::styled("    N/+    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Play next / add to end of queue"),
+            ]),
+            Line::from(vec![
+                Span::styled("    d      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Remove from queue"),
+            ]),
+            Line::from(vec![
+                Span::styled("    K/J    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Move up / down in queue"),
+            ]),
+            Line::from(vec![
+                Span::styled("    C      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Clear queue"),
+            ]),
+            Line::from(""),
+            Line::from(vec![Span::styled(
+                "  LYRICS",
+                Style::default().fg(Color::Yellow),
+            )]),
+            Line::from(vec![
+                Span::styled("    y      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Show / hide lyrics"),
+            ]),
+            Line::from(vec![
+                Span::styled("    Enter  ", Style::default().fg(Color::Cyan)),
+                Span::raw("Jump to the selected line"),
+            ]),
+            Line::from(vec![
+                Span::styled("    +/-    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Show lyrics earlier / later"),
+            ]),
             Line::from(""),
             Line::from(vec![Span::styled(
                 "  PLAYBACK",
@@ -303,7 +832,67 @@ impl App<'_> {
             Line::from(vec![
                 Span::styled("    ↑/↓    ", Style::default().fg(Color::Cyan)),
                 Span::raw("Up down volume of second button"),
-            ]), 
+            ]),
+            Line::from(vec![
+                Span::styled("    ↑/↓    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Adjust crossfade of last button"),
+            ]),
+            Line::from(vec![
+                Span::styled("    [/]    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Slow down / speed up"),
+            ]),
+            Line::from(vec![
+                Span::styled("    ,/.    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Seek backward / forward"),
+            ]),
+            Line::from(vec![
+                Span::styled("    </>    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Seek backward / forward further"),
+            ]),
+            Line::from(vec![
+                Span::styled("    0-9    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Jump to 0%-90% of the track"),
+            ]),
+            Line::from(vec![
+                Span::styled("    g      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Jump to mm:ss"),
+            ]),
+            Line::from(vec![
+                Span::styled("    a      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Mark A, mark B and loop, stop looping"),
+            ]),
+            Line::from(vec![
+                Span::styled("    A/L    ", Style::default().fg(Color::Cyan)),
+                Span::raw("Save A-B loop / loop next saved one"),
+            ]),
+            Line::from(vec![
+                Span::styled("    z      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Sleep timer"),
+            ]),
+            Line::from(vec![
+                Span::styled("    w      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Toggle waveform seekbar"),
+            ]),
+            Line::from(vec![
+                Span::styled("    v      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Switch donut / spectrum / artwork"),
+            ]),
+            Line::from(vec![
+                Span::styled("    n      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Loudness normalization off/track/album"),
+            ]),
+            Line::from(vec![
+                Span::styled("    e      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Equalizer: ←/→ band, ↑/↓ gain"),
+            ]),
+            Line::from(vec![
+                Span::styled("    p/o/S  ", Style::default().fg(Color::Cyan)),
+                Span::raw("Next preset / per-track / save preset"),
+            ]),
+            Line::from(vec![
+                Span::styled("    D      ", Style::default().fg(Color::Cyan)),
+                Span::raw("Choose output device"),
+            ]),
             Line::from(""),
             Line::from(vec![Span::styled(
                 "  DOWNLOAD",
@@ -372,6 +961,15 @@ fn _popup(area: Rect, per_x: u16, per_y: u16) -> Rect {
     let [area] = horizontal.areas(area);
     area
 }
+fn length_box_center(area: Rect, len_x: u16, len_y: u16) -> Rect {
+    let vertical =
+        Layout::vertical([Constraint::Length(len_y)]).flex(ratatui::layout::Flex::Center);
+    let horizontal =
+        Layout::horizontal([Constraint::Length(len_x)]).flex(ratatui::layout::Flex::Center);
+    let [area] = vertical.areas(area);
+    let [area] = horizontal.areas(area);
+    area
+}
 fn length_box(area: Rect, len_x: u16, len_y: u16) -> Rect {
     let vertical = Layout::vertical([Constraint::Length(len_y)]).flex(ratatui::layout::Flex::End);
     let horizontal =
@@ -388,27 +986,77 @@ fn formart_duration(d: Duration) -> String {
     format!("{:02}:{:02}", minutes, seconds)
 }
 
+/// Parses `ss`, `mm:ss` or `hh:mm:ss`.
+fn parse_timestamp(s: &str) -> Option<Duration> {
+    let mut secs = 0u64;
+    for part in s.trim().split(':') {
+        secs = secs
+            .checked_mul(60)?
+            .checked_add(part.trim().parse::<u64>().ok()?)?;
+    }
+    Some(Duration::from_secs(secs))
+}
+
 #[tokio::main]
 async fn main() -> Result<(), Box<dyn Error>> {
     enable_raw_mode()?;
     let mut stdout = io::stdout();
-    execute!(stdout, EnterAlternateScreen, EnableBracketedPaste)?;
+    execute!(
+        stdout,
+        EnterAlternateScreen,
+        EnableBracketedPaste,
+        EnableMouseCapture
+    )?;
     let backend: CrosstermBackend<io::Stdout> = CrosstermBackend::new(stdout);
     let mut terminal = Terminal::new(backend)?;
     let mut app = App::new();
     app.load_folder();
+    app.restore_session();
     while !app.should_quit {
         app.audio_tick();
         terminal.draw(|f| {
             app.render_main_page(f);
         })?;
+        if app.artwork.flush(terminal.backend_mut())? {
+            terminal.clear()?;
+        }
 
         app.handle_event().await?;
     }
 
+    // Nothing was rendered since the last flush, so this takes the image down.
+    app.artwork.flush(terminal.backend_mut())?;
     disable_raw_mode()?;
-    execute!(terminal.backend_mut(), LeaveAlternateScreen)?;
+    execute!(
+        terminal.backend_mut(),
+        LeaveAlternateScreen,
+        DisableMouseCapture
+    )?;
     terminal.show_cursor()?;
+    app.save_session()?;
+    app.audio_folder.index.save()?;
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn parses_timestamps() {
+        assert_eq!(parse_timestamp("42"), Some(Duration::from_secs(42)));
+        assert_eq!(parse_timestamp(" 1:05 "), Some(Duration::from_secs(65)));
+        assert_eq!(parse_timestamp("1:02:03"), Some(Duration::from_secs(3723)));
+        for bad in ["", "1:", "a:10", "-5", "1.5"] {
+            assert_eq!(parse_timestamp(bad), None, "{bad}");
+        }
+    }
+
+    #[test]
+    fn rejects_timestamps_that_overflow() {
+        assert_eq!(parse_timestamp("99999999999999999999"), None);
+        assert_eq!(parse_timestamp("18446744073709551615:0"), None);
+        assert_eq!(parse_timestamp("307445734561825860:15:0"), None);
+    }
+}
@@ -0,0 +1,125 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+};
+
+use serde::{Deserialize, Serialize};
+
+use crate::{app::LoopMode, config::data_dir};
+
+/// Player state saved on quit and restored on the next start, kept in
+/// `~/.audyo_plaier/session.json`.
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+#[serde(default)]
+pub struct Session {
+    /// Track that was loaded, and how far into it playback was in seconds.
+    pub current: Option<String>,
+    pub position_secs: f64,
+    pub playing: bool,
+    pub volume: f32,
+    pub muted: bool,
+    pub loop_mode: LoopMode,
+    pub queue: Vec<String>,
+    pub queue_index: usize,
+    /// Selected rows of the library and queue lists.
+    pub library_row: Option<usize>,
+    pub queue_row: Option<usize>,
+    /// Where each long file was left off, in seconds.
+    pub resume_points: HashMap<String, f64>,
+}
+
+impl Default for Session {
+    fn default() -> Self {
+        Self {
+            current: None,
+            position_secs: 0.0,
+            playing: false,
+            volume: 1.0,
+            muted: false,
+            loop_mode: LoopMode::Single,
+            queue: Vec::new(),
+            queue_index: 0,
+            library_row: Some(0),
+            queue_row: None,
+            resume_points: HashMap::new(),
+        }
+    }
+}
+
+impl Session {
+    fn path() -> PathBuf {
+        data_dir().join("session.json")
+    }
+    /// The saved session, or `None` on first start.
+    pub fn load() -> Option<Self> {
+        Self::load_from(&Self::path())
+    }
+    pub fn save(&self) -> io::Result<()> {
+        self.save_to(&Self::path())
+    }
+    fn load_from(path: &Path) -> Option<Self> {
+        let json = fs::read_to_string(path).ok()?;
+        serde_json::from_str(&json).ok()
+    }
+    fn save_to(&self, path: &Path) -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, serde_json::to_string_pretty(self)?)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn temp_path(name: &str) -> PathBuf {
+        std::env::temp_dir()
+            .join(format!("audyo-session-{}-{}", name, std::process::id()))
+            .join("session.json")
+    }
+
+    #[test]
+    fn saved_sessions_load_back() {
+        let path = temp_path("round-trip");
+        let session = Session {
+            current: Some("/music/b.mp3".into()),
+            position_secs: 83.25,
+            playing: true,
+            volume: 0.4,
+            muted: true,
+            loop_mode: LoopMode::Shuffle,
+            queue: vec!["/music/a.mp3".into(), "/music/b.mp3".into()],
+            queue_index: 1,
+            library_row: Some(7),
+            queue_row: None,
+            resume_points: HashMap::from([("/podcasts/ep1.mp3".into(), 1234.5)]),
+        };
+        session.save_to(&path).unwrap();
+        let loaded = Session::load_from(&path);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        assert_eq!(loaded, Some(session));
+    }
+
+    #[test]
+    fn missing_fields_fall_back_to_defaults() {
+        let path = temp_path("partial");
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, r#"{"current": "/music/a.mp3", "volume": 0.5}"#).unwrap();
+        let loaded = Session::load_from(&path);
+        fs::write(&path, "not json").unwrap();
+        let broken = Session::load_from(&path);
+        let _ = fs::remove_dir_all(path.parent().unwrap());
+        assert_eq!(
+            loaded,
+            Some(Session {
+                current: Some("/music/a.mp3".into()),
+                volume: 0.5,
+                ..Session::default()
+            })
+        );
+        assert_eq!(broken, None);
+        assert_eq!(Session::load_from(&temp_path("missing")), None);
+    }
+}
@@ -0,0 +1,157 @@
+//! Terminal graphics protocols that show real pixels instead of half-blocks.
+
+use std::{
+    env,
+    io::{self, Write},
+};
+
+use base64::{Engine, engine::general_purpose::STANDARD};
+use serde::{Deserialize, Serialize};
+
+use super::Image;
+
+/// Base64 bytes per kitty escape sequence.
+const KITTY_CHUNK: usize = 4096;
+
+/// How artwork is drawn.
+#[derive(Debug, Clone, Copy, PartialEq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum Graphics {
+    /// Two truecolor pixels per cell with `▀`; works everywhere.
+    HalfBlocks,
+    Kitty,
+    Sixel,
+}
+
+impl Graphics {
+    /// Guesses what the terminal supports from its environment. Multiplexers
+    /// get half-blocks since they don't pass images through reliably.
+    pub fn detect() -> Self {
+        let var = |name: &str| env::var(name).unwrap_or_default();
+        let (term, program) = (var("TERM"), var("TERM_PROGRAM"));
+        if env::var_os("TMUX").is_some() || term.starts_with("screen") {
+            Self::HalfBlocks
+        } else if env::var_os("KITTY_WINDOW_ID").is_some()
+            || term == "xterm-kitty"
+            || matches!(program.as_str(), "WezTerm" | "ghostty")
+        {
+            Self::Kitty
+        } else if term.contains("sixel")
+            || term.starts_with("foot")
+            || term.starts_with("mlterm")
+            || term.starts_with("yaft")
+        {
+            Self::Sixel
+        } else {
+            Self::HalfBlocks
+        }
+    }
+}
+
+/// Moves the cursor to the top-left cell of where the image goes.
+fn move_to(out: &mut impl Write, column: u16, row: u16) -> io::Result<()> {
+    write!(out, "\x1b[{};{}H", row + 1, column + 1)
+}
+
+/// Shows `image` as kitty image `id` at the given cell, replacing what was
+/// shown under that id before.
+pub fn kitty(
+    out: &mut impl Write,
+    image: &Image,
+    id: u32,
+    column: u16,
+    row: u16,
+) -> io::Result<()> {
+    let rgb: Vec<u8> = image.pixels.iter().flatten().copied().collect();
+    let payload = STANDARD.encode(rgb);
+    kitty_delete(out, id)?;
+    move_to(out, column, row)?;
+    let chunks: Vec<&[u8]> = payload.as_bytes().chunks(KITTY_CHUNK).collect();
+    for (i, chunk) in chunks.iter().enumerate() {
+        let more = u8::from(i + 1 < chunks.len());
+        if i == 0 {
+            write!(
+                out,
+                "\x1b_Ga=T,f=24,s={},v={},i={},C=1,q=2,m={};",
+                image.width, image.height, id, more
+            )?;
+        } else {
+            write!(out, "\x1b_Gm={};", more)?;
+        }
+        out.write_all(chunk)?;
+        out.write_all(b"\x1b\\")?;
+    }
+    out.flush()
+}
+
+pub fn kitty_delete(out: &mut impl Write, id: u32) -> io::Result<()> {
+    write!(out, "\x1b_Ga=d,d=I,i={},q=2\x1b\\", id)
+}
+
+/// Shows `image` at the given cell as sixels, using a 6×6×6 colour cube.
+pub fn sixel(out: &mut impl Write, image: &Image, column: u16, row: u16) -> io::Result<()> {
+    let level = |v: u8| (v as usize * 5 + 127) / 255;
+    let indices: Vec<usize> = image
+        .pixels
+        .iter()
+        .map(|p| level(p[0]) * 36 + level(p[1]) * 6 + level(p[2]))
+        .collect();
+
+    let mut data = Vec::new();
+    write!(data, "\x1bPq\"1;1;{};{}", image.width, image.height)?;
+    let mut used = [false; 216];
+    for &i in &indices {
+        used[i] = true;
+    }
+    for (i, _) in used.iter().enumerate().filter(|(_, used)| **used) {
+        let (r, g, b) = (i / 36, i / 6 % 6, i % 6);
+        write!(data, "#{};2;{};{};{}", i, r * 20, g * 20, b * 20)?;
+    }
+    for top in (0..image.height).step_by(6) {
+        let rows = (image.height - top).min(6);
+        let mut colors = [false; 216];
+        for y in top..top + rows {
+            for &i in &indices[y * image.width..(y + 1) * image.width] {
+                colors[i] = true;
+            }
+        }
+        for (color, _) in colors.iter().enumerate().filter(|(_, used)| **used) {
+            write!(data, "#{}", color)?;
+            let mut run = (0u8, 0usize);
+            for x in 0..image.width {
+                let mut bits = 0u8;
+                for dy in 0..rows {
+                    if indices[(top + dy) * image.width + x] == color {
+                        bits |= 1 << dy;
+                    }
+                }
+                let char = 63 + bits;
+                if char == run.0 {
+                    run.1 += 1;
+                } else {
+                    write_run(&mut data, run)?;
+                    run = (char, 1);
+                }
+            }
+            write_run(&mut data, run)?;
+            // Back to the start of the band for the next colour.
+            data.push(b'$');
+        }
+        data.push(b'-');
+    }
+    data.extend_from_slice(b"\x1b\\");
+    move_to(out, column, row)?;
+    out.write_all(&data)?;
+    out.flush()
+}
+
+fn write_run(out: &mut Vec<u8>, (char, count): (u8, usize)) -> io::Result<()> {
+    match count {
+        0 => Ok(()),
+        1..=3 => {
+            out.extend(std::iter::repeat_n(char, count));
+            Ok(())
+        }
+        _ => write!(out, "!{}{}", count, char as char),
+    }
+}
@@ -0,0 +1,557 @@
+//! Baseline JPEG decoder, enough for YouTube thumbnails and typical cover
+//! art. Progressive and arithmetic-coded files aren't supported.
+
+use std::f32::consts::PI;
+
+use super::{Image, MAX_SIDE};
+
+/// Largest magnitude categories of DC differences and AC coefficients in
+/// an 8-bit baseline image.
+const MAX_DC_SIZE: u8 = 11;
+const MAX_AC_SIZE: u8 = 10;
+
+/// Position in a block of each coefficient in zig-zag order.
+const ZIGZAG: [usize; 64] = [
+    0, 1, 8, 16, 9, 2, 3, 10, 17, 24, 32, 25, 18, 11, 4, 5, 12, 19, 26, 33, 40, 48, 41, 34, 27, 20,
+    13, 6, 7, 14, 21, 28, 35, 42, 49, 56, 57, 50, 43, 36, 29, 22, 15, 23, 30, 37, 44, 51, 58, 59,
+    52, 45, 38, 31, 39, 46, 53, 60, 61, 54, 47, 55, 62, 63,
+];
+
+#[derive(Clone, Default)]
+struct Huffman {
+    max_code: [i32; 17],
+    min_code: [i32; 17],
+    offset: [usize; 17],
+    values: Vec<u8>,
+}
+
+impl Huffman {
+    fn new(counts: &[u8], values: &[u8]) -> Self {
+        let mut table = Self {
+            values: values.to_vec(),
+            ..Self::default()
+        };
+        let mut code = 0;
+        let mut k = 0;
+        for len in 1..=16 {
+            let count = counts[len - 1] as i32;
+            table.offset[len] = k;
+            table.min_code[len] = code;
+            code += count;
+            k += count as usize;
+            table.max_code[len] = if count > 0 { code - 1 } else { -1 };
+            code <<= 1;
+        }
+        table
+    }
+    fn decode(&self, bits: &mut Bits) -> Option<u8> {
+        let mut code = 0;
+        for len in 1..=16 {
+            code = (code << 1) | bits.bit() as i32;
+            if code <= self.max_code[len] {
+                let index = self.offset[len] + (code - self.min_code[len]) as usize;
+                return self.values.get(index).copied();
+            }
+        }
+        None
+    }
+}
+
+/// Reads the entropy-coded segment, undoing 0xFF00 byte stuffing. Hitting a
+/// marker yields zero bits until the caller moves past it.
+struct Bits<'a> {
+    data: &'a [u8],
+    pos: usize,
+    acc: u32,
+    left: u32,
+}
+
+impl Bits<'_> {
+    fn bit(&mut self) -> u32 {
+        if self.left == 0 {
+            let mut byte = 0;
+            if let Some(&b) = self.data.get(self.pos) {
+                if b != 0xff {
+                    byte = b;
+                    self.pos += 1;
+                } else if self.data.get(self.pos + 1) == Some(&0) {
+                    byte = 0xff;
+                    self.pos += 2;
+                }
+            }
+            self.acc = byte as u32;
+            self.left = 8;
+        }
+        self.left -= 1;
+        (self.acc >> self.left) & 1
+    }
+    fn receive(&mut self, n: u8) -> i32 {
+        let mut v = 0;
+        for _ in 0..n {
+            v = (v << 1) | self.bit() as i32;
+        }
+        v
+    }
+    /// Decodes an `n`-bit magnitude category into a signed value. `n` is at
+    /// most 16.
+    fn receive_extend(&mut self, n: u8) -> i32 {
+        if n == 0 {
+            return 0;
+        }
+        let v = self.receive(n);
+        if v < 1 << (n - 1) {
+            v - (1 << n) + 1
+        } else {
+            v
+        }
+    }
+    /// Skips the RSTn marker after a restart interval.
+    fn restart(&mut self) {
+        self.left = 0;
+        while self.pos + 1 < self.data.len() {
+            let marker = self.data[self.pos + 1];
+            if self.data[self.pos] == 0xff && (0xd0..=0xd7).contains(&marker) {
+                self.pos += 2;
+                return;
+            }
+            self.pos += 1;
+        }
+    }
+}
+
+struct Component {
+    id: u8,
+    h: usize,
+    v: usize,
+    quant: usize,
+    dc: usize,
+    ac: usize,
+    /// Width of `plane` in pixels.
+    stride: usize,
+    plane: Vec<u8>,
+    pred: i32,
+}
+
+struct Decoder {
+    width: usize,
+    height: usize,
+    quant: [[u16; 64]; 4],
+    dc: [Huffman; 4],
+    ac: [Huffman; 4],
+    components: Vec<Component>,
+    restart_interval: usize,
+    idct: [[f32; 8]; 8],
+}
+
+fn read_u16(data: &[u8], at: usize) -> Option<usize> {
+    Some(u16::from_be_bytes([*data.get(at)?, *data.get(at + 1)?]) as usize)
+}
+
+/// Decodes a baseline JPEG. `None` for anything malformed or unsupported.
+pub fn decode(data: &[u8]) -> Option<Image> {
+    if !data.starts_with(&[0xff, 0xd8]) {
+        return None;
+    }
+    let mut idct = [[0.0; 8]; 8];
+    for (x, row) in idct.iter_mut().enumerate() {
+        for (u, c) in row.iter_mut().enumerate() {
+            let scale = if u == 0 { 1.0 / 2f32.sqrt() } else { 1.0 };
+            *c = scale * ((2 * x + 1) as f32 * u as f32 * PI / 16.0).cos() / 2.0;
+        }
+    }
+    let mut decoder = Decoder {
+        width: 0,
+        height: 0,
+        quant: [[0; 64]; 4],
+        dc: Default::default(),
+        ac: Default::default(),
+        components: Vec::new(),
+        restart_interval: 0,
+        idct,
+    };
+    let mut pos = 2;
+    loop {
+        while *data.get(pos)? != 0xff {
+            pos += 1;
+        }
+        while *data.get(pos)? == 0xff {
+            pos += 1;
+        }
+        let marker = data[pos];
+        pos += 1;
+        if marker == 0xd9 {
+            break;
+        }
+        if (0xd0..=0xd7).contains(&marker) {
+            continue;
+        }
+        let len = read_u16(data, pos)?;
+        let segment = data.get(pos + 2..pos + len)?;
+        match marker {
+            0xc0 | 0xc1 => decoder.frame(segment)?,
+            // Progressive, lossless, hierarchical and arithmetic coding.
+            0xc2 | 0xc3 | 0xc5..=0xc7 | 0xc9..=0xcb | 0xcd..=0xcf => return None,
+            0xc4 => decoder.huffman_tables(segment)?,
+            0xdb => decoder.quant_tables(segment)?,
+            0xdd => decoder.restart_interval = read_u16(segment, 0)?,
+            0xda => {
+                pos = decoder.scan(data, pos + len, segment)?;
+                continue;
+            }
+            _ => {}
+        }
+        pos += len;
+    }
+    decoder.to_image()
+}
+
+impl Decoder {
+    fn frame(&mut self, s: &[u8]) -> Option<()> {
+        if *s.first()? != 8 {
+            return None;
+        }
+        self.height = read_u16(s, 1)?;
+        self.width = read_u16(s, 3)?;
+        let count = *s.get(5)? as usize;
+        if !(1..=MAX_SIDE).contains(&self.width)
+            || !(1..=MAX_SIDE).contains(&self.height)
+            || !(count == 1 || count == 3)
+        {
+            return None;
+        }
+        for i in 0..count {
+            let c = s.get(6 + i * 3..9 + i * 3)?;
+            let (h, v) = ((c[1] >> 4) as usize, (c[1] & 15) as usize);
+            if !(1..=4).contains(&h) || !(1..=4).contains(&v) || c[2] > 3 {
+                return None;
+            }
+            self.components.push(Component {
+                id: c[0],
+                h,
+                v,
+                quant: c[2] as usize,
+                dc: 0,
+                ac: 0,
+                stride: 0,
+                plane: Vec::new(),
+                pred: 0,
+            });
+        }
+        let (mcu_w, mcu_h) = self.mcu_size();
+        let mcus_x = self.width.div_ceil(mcu_w);
+        let mcus_y = self.height.div_ceil(mcu_h);
+        for c in &mut self.components {
+            c.stride = mcus_x * c.h * 8;
+            c.plane = vec![0; c.stride * mcus_y * c.v * 8];
+        }
+        Some(())
+    }
+    fn max_sampling(&self) -> (usize, usize) {
+        let h = self.components.iter().map(|c| c.h).max().unwrap_or(1);
+        let v = self.components.iter().map(|c| c.v).max().unwrap_or(1);
+        (h, v)
+    }
+    fn mcu_size(&self) -> (usize, usize) {
+        let (h, v) = self.max_sampling();
+        (h * 8, v * 8)
+    }
+    fn huffman_tables(&mut self, mut s: &[u8]) -> Option<()> {
+        while !s.is_empty() {
+            let (class, id) = (s[0] >> 4, (s[0] & 15) as usize);
+            let counts = s.get(1..17)?;
+            let total: usize = counts.iter().map(|&c| c as usize).sum();
+            let values = s.get(17..17 + total)?;
+            let table = Huffman::new(counts, values);
+            match class {
+                0 => *self.dc.get_mut(id)? = table,
+                1 => *self.ac.get_mut(id)? = table,
+                _ => return None,
+            }
+            s = &s[17 + total..];
+        }
+        Some(())
+    }
+    fn quant_tables(&mut self, mut s: &[u8]) -> Option<()> {
+        while !s.is_empty() {
+            let (precision, id) = (s[0] >> 4, (s[0] & 15) as usize);
+            let table = self.quant.get_mut(id)?;
+            let size = if precision == 0 { 64 } else { 128 };
+            let values = s.get(1..1 + size)?;
+            for (k, q) in table.iter_mut().enumerate() {
+                *q = if precision == 0 {
+                    values[k] as u16
+                } else {
+                    u16::from_be_bytes([values[k * 2], values[k * 2 + 1]])
+                };
+            }
+            s = &s[1 + size..];
+        }
+        Some(())
+    }
+    /// Decodes one scan starting at `start`; returns where the data after it
+    /// begins.
+    fn scan(&mut self, data: &[u8], start: usize, header: &[u8]) -> Option<usize> {
+        let count = *header.first()? as usize;
+        let mut members = Vec::with_capacity(count);
+        for i in 0..count {
+            let id = *header.get(1 + i * 2)?;
+            let tables = *header.get(2 + i * 2)?;
+            let index = self.components.iter().position(|c| c.id == id)?;
+            let c = &mut self.components[index];
+            c.dc = (tables >> 4) as usize & 3;
+            c.ac = (tables & 15) as usize & 3;
+            c.pred = 0;
+            members.push(index);
+        }
+        let mut bits = Bits {
+            data,
+            pos: start,
+            acc: 0,
+            left: 0,
+        };
+        let (mcu_w, mcu_h) = self.mcu_size();
+        let (max_h, max_v) = self.max_sampling();
+        // A single-component scan covers just that component's blocks, one
+        // per "MCU".
+        let (mcus_x, mcus_y) = if members.len() == 1 {
+            let c = &self.components[members[0]];
+            (
+                (self.width * c.h).div_ceil(max_h * 8),
+                (self.height * c.v).div_ceil(max_v * 8),
+            )
+        } else {
+            (self.width.div_ceil(mcu_w), self.height.div_ceil(mcu_h))
+        };
+        let mut block = [0f32; 64];
+        for mcu in 0..mcus_x * mcus_y {
+            if self.restart_interval > 0 && mcu > 0 && mcu % self.restart_interval == 0 {
+                bits.restart();
+                for &m in &members {
+                    self.components[m].pred = 0;
+                }
+            }
+            let (mx, my) = (mcu % mcus_x, mcu / mcus_x);
+            for &m in &members {
+                let (h, v) = if members.len() == 1 {
+                    (1, 1)
+                } else {
+                    (self.components[m].h, self.components[m].v)
+                };
+                for by in 0..v {
+                    for bx in 0..h {
+                        self.decode_block(&mut bits, m, &mut block)?;
+                        let x = (mx * h + bx) * 8;
+                        let y = (my * v + by) * 8;
+                        self.store_block(m, &block, x, y);
+                    }
+                }
+            }
+        }
+        // Resume at the next marker that isn't stuffing or a restart.
+        let mut pos = bits.pos;
+        while pos + 1 < data.len() {
+            if data[pos] == 0xff && data[pos + 1] != 0 && !(0xd0..=0xd7).contains(&data[pos + 1]) {
+                return Some(pos);
+            }
+            pos += 1;
+        }
+        Some(data.len())
+    }
+    fn decode_block(&mut self, bits: &mut Bits, m: usize, out: &mut [f32; 64]) -> Option<()> {
+        let c = &mut self.components[m];
+        let quant = &self.quant[c.quant];
+        let mut coefs = [0f32; 64];
+        let size = self.dc[c.dc].decode(bits)?;
+        if size > MAX_DC_SIZE {
+            return None;
+        }
+        // Corrupt data can push the predictor anywhere; it only has to not
+        // overflow.
+        c.pred = c.pred.wrapping_add(bits.receive_extend(size));
+        coefs[0] = c.pred.wrapping_mul(quant[0] as i32) as f32;
+        let mut k = 1;
+        while k < 64 {
+            let rs = self.ac[c.ac].decode(bits)?;
+            let (run, size) = ((rs >> 4) as usize, rs & 15);
+            if size > MAX_AC_SIZE {
+                return None;
+            }
+            if size == 0 {
+                if run == 15 {
+                    k += 16;
+                    continue;
+                }
+                break;
+            }
+            k += run;
+            if k > 63 {
+                break;
+            }
+            coefs[ZIGZAG[k]] = bits.receive_extend(size).wrapping_mul(quant[k] as i32) as f32;
+            k += 1;
+        }
+        // Separable inverse DCT: rows, then columns.
+        let mut tmp = [0f32; 64];
+        for y in 0..8 {
+            for x in 0..8 {
+                tmp[y * 8 + x] = (0..8).map(|u| self.idct[x][u] * coefs[y * 8 + u]).sum();
+            }
+        }
+        for x in 0..8 {
+            for y in 0..8 {
+                out[y * 8 + x] = (0..8).map(|v| self.idct[y][v] * tmp[v * 8 + x]).sum();
+            }
+        }
+        Some(())
+    }
+    fn store_block(&mut self, m: usize, block: &[f32; 64], x: usize, y: usize) {
+        let c = &mut self.components[m];
+        let rows = c.plane.len() / c.stride;
+        for dy in 0..8 {
+            if y + dy >= rows || x + 8 > c.stride {
+                break;
+            }
+            let row = (y + dy) * c.stride + x;
+            for dx in 0..8 {
+                c.plane[row + dx] = (block[dy * 8 + dx] + 128.0).round().clamp(0.0, 255.0) as u8;
+            }
+        }
+    }
+    fn to_image(&self) -> Option<Image> {
+        if self.components.is_empty() {
+            return None;
+        }
+        let (max_h, max_v) = self.max_sampling();
+        let sample = |c: &Component, x: usize, y: usize| {
+            c.plane[(y * c.v / max_v) * c.stride + x * c.h / max_h] as f32
+        };
+        let mut pixels = Vec::with_capacity(self.width * self.height);
+        for y in 0..self.height {
+            for x in 0..self.width {
+                let pixel = match self.components.as_slice() {
+                    [gray] => {
+                        let l = sample(gray, x, y) as u8;
+                        [l, l, l]
+                    }
+                    [cy, cb, cr] => {
+                        let (l, b, r) = (
+                            sample(cy, x, y),
+                            sample(cb, x, y) - 128.0,
+                            sample(cr, x, y) - 128.0,
+                        );
+                        [
+                            (l + 1.402 * r).clamp(0.0, 255.0) as u8,
+                            (l - 0.344_136 * b - 0.714_136 * r).clamp(0.0, 255.0) as u8,
+                            (l + 1.772 * b).clamp(0.0, 255.0) as u8,
+                        ]
+                    }
+                    _ => return None,
+                };
+                pixels.push(pixel);
+            }
+        }
+        Some(Image {
+            width: self.width,
+            height: self.height,
+            pixels,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn segment(data: &mut Vec<u8>, marker: u8, body: &[u8]) {
+        data.extend_from_slice(&[0xff, marker]);
+        data.extend_from_slice(&(body.len() as u16 + 2).to_be_bytes());
+        data.extend_from_slice(body);
+    }
+
+    /// Grayscale JPEG `blocks` blocks wide and one high, every quantiser
+    /// `quant`, whose DC and AC tables each hold the single one-bit code `0`
+    /// for `dc` and `ac`. `scan` is the entropy-coded data as bits.
+    fn jpeg(blocks: u16, quant: u16, dc: u8, ac: u8, scan: &[u8]) -> Vec<u8> {
+        let mut data = vec![0xff, 0xd8];
+        let mut dqt = vec![0x10];
+        for _ in 0..64 {
+            dqt.extend_from_slice(&quant.to_be_bytes());
+        }
+        segment(&mut data, 0xdb, &dqt);
+        let width = (blocks * 8).to_be_bytes();
+        segment(
+            &mut data,
+            0xc0,
+            &[8, 0, 8, width[0], width[1], 1, 1, 0x11, 0],
+        );
+        for (class, value) in [(0x00, dc), (0x10, ac)] {
+            let mut dht = vec![class, 1];
+            dht.extend_from_slice(&[0; 15]);
+            dht.push(value);
+            segment(&mut data, 0xc4, &dht);
+        }
+        segment(&mut data, 0xda, &[1, 1, 0x00, 0, 63, 0]);
+        // Padded with ones and 0xff stuffed, as an encoder writes it.
+        let mut scan = scan.to_vec();
+        scan.resize(scan.len().next_multiple_of(8), 1);
+        for chunk in scan.chunks(8) {
+            let byte = chunk.iter().fold(0u8, |b, &bit| (b << 1) | bit);
+            data.push(byte);
+            if byte == 0xff {
+                data.push(0);
+            }
+        }
+        data.extend_from_slice(&[0xff, 0xd9]);
+        data
+    }
+
+    /// Scan data of `blocks` blocks, each a DC difference of `size` bits
+    /// `bit` and an end of block.
+    fn blocks(blocks: usize, size: usize, bit: u8) -> Vec<u8> {
+        let mut scan = Vec::new();
+        for _ in 0..blocks {
+            scan.push(0);
+            scan.extend(std::iter::repeat_n(bit, size));
+            scan.push(0);
+        }
+        scan
+    }
+
+    #[test]
+    fn decodes_a_flat_block() {
+        let image = decode(&jpeg(1, 1, 0, 0x00, &blocks(1, 0, 0))).unwrap();
+        assert_eq!((image.width, image.height), (8, 8));
+        assert!(image.pixels.iter().all(|&p| p == [128; 3]));
+    }
+
+    #[test]
+    fn survives_truncation() {
+        let data = jpeg(4, 1, 0, 0x00, &blocks(4, 0, 0));
+        for len in 0..data.len() {
+            let _ = decode(&data[..len]);
+        }
+        // Cut before the scan header.
+        assert!(decode(&data[..data.len() - 13]).is_none());
+    }
+
+    #[test]
+    fn rejects_oversized_magnitude_categories() {
+        for dc in [12, 15, 200, 255] {
+            assert!(
+                decode(&jpeg(1, 1, dc, 0x00, &blocks(1, 0, 1))).is_none(),
+                "{dc}"
+            );
+        }
+        // Run 0, size 11.
+        let scan = [0, 0, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1, 1];
+        assert!(decode(&jpeg(1, 1, 0, 0x0b, &scan)).is_none());
+    }
+
+    #[test]
+    fn survives_a_runaway_predictor() {
+        // Every block adds the largest DC difference, so predictor times
+        // quantiser overflows an i32 within a few dozen blocks.
+        let image = decode(&jpeg(64, u16::MAX, 11, 0x00, &blocks(64, 11, 1))).unwrap();
+        assert_eq!(image.width, 512);
+    }
+}
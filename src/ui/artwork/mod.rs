@@ -0,0 +1,265 @@
+use std::{
+    fs,
+    io::{self, Write},
+    path::Path,
+};
+
+use ratatui::{buffer::Buffer, layout::Rect, style::Color};
+
+use crate::{audyo::metadata::read_cover, downloader::info::DownloadInfo};
+
+pub mod graphics;
+mod jpeg;
+mod png;
+
+use graphics::Graphics;
+
+/// Scalings of the current image kept around for resizes.
+const SCALED_CACHE: usize = 4;
+/// Kitty image id used for the artwork.
+const KITTY_ID: u32 = 1;
+/// Cell size assumed when the terminal doesn't report its pixel size.
+const DEFAULT_CELL: (usize, usize) = (8, 16);
+/// Largest width or height decoded; cover art is far smaller, so anything
+/// bigger is taken for a corrupt header.
+const MAX_SIDE: usize = 8192;
+
+/// Decoded RGB image.
+#[derive(Debug, Clone)]
+pub struct Image {
+    pub width: usize,
+    pub height: usize,
+    pub pixels: Vec<[u8; 3]>,
+}
+
+impl Image {
+    /// Decodes a JPEG or PNG file.
+    pub fn decode(data: &[u8]) -> Option<Self> {
+        jpeg::decode(data).or_else(|| png::decode(data))
+    }
+    /// Size of this image scaled to fit `width`×`height`, keeping its aspect
+    /// ratio.
+    fn fit_size(&self, width: usize, height: usize) -> (usize, usize) {
+        let scale = (width as f64 / self.width as f64).min(height as f64 / self.height as f64);
+        (
+            ((self.width as f64 * scale).round() as usize).clamp(1, width.max(1)),
+            ((self.height as f64 * scale).round() as usize).clamp(1, height.max(1)),
+        )
+    }
+    /// Resamples to `width`×`height`, averaging every source pixel that
+    /// falls into a target pixel.
+    fn resize(&self, width: usize, height: usize) -> Self {
+        let mut pixels = Vec::with_capacity(width * height);
+        for y in 0..height {
+            let y0 = y * self.height / height;
+            let y1 = ((y + 1) * self.height / height).max(y0 + 1);
+            for x in 0..width {
+                let x0 = x * self.width / width;
+                let x1 = ((x + 1) * self.width / width).max(x0 + 1);
+                let mut sum = [0u32; 3];
+                for row in y0..y1 {
+                    for p in &self.pixels[row * self.width + x0..row * self.width + x1] {
+                        for (s, &v) in sum.iter_mut().zip(p) {
+                            *s += v as u32;
+                        }
+                    }
+                }
+                let n = ((y1 - y0) * (x1 - x0)) as u32;
+                pixels.push(sum.map(|s| (s / n) as u8));
+            }
+        }
+        Self {
+            width,
+            height,
+            pixels,
+        }
+    }
+}
+
+/// Artwork of `audio`: the thumbnail downloaded next to it, or the cover
+/// embedded in the file.
+pub fn load(audio: &str) -> Option<Image> {
+    fs::read(DownloadInfo::thumbnail_path(Path::new(audio)))
+        .ok()
+        .and_then(|data| Image::decode(&data))
+        .or_else(|| read_cover(audio).and_then(|data| Image::decode(&data)))
+}
+
+/// Where an image drawn with a graphics protocol went.
+#[derive(Debug, Clone, Copy, PartialEq)]
+struct Placement {
+    column: u16,
+    row: u16,
+    width: usize,
+    height: usize,
+    generation: u64,
+}
+
+/// Artwork of the current track for the now-playing area, drawn with
+/// half-blocks or a terminal graphics protocol.
+pub struct Artwork {
+    graphics: Graphics,
+    /// Track the artwork belongs to, set as soon as loading starts.
+    path: Option<String>,
+    image: Option<Image>,
+    /// Bumped whenever `image` changes.
+    generation: u64,
+    /// Recently used scalings of `image`, most recent last.
+    scaled: Vec<Image>,
+    /// Where the protocol image goes once the frame is drawn.
+    target: Option<Placement>,
+    shown: Option<Placement>,
+}
+
+impl Artwork {
+    pub fn new(graphics: Graphics) -> Self {
+        Self {
+            graphics,
+            path: None,
+            image: None,
+            generation: 0,
+            scaled: Vec::new(),
+            target: None,
+            shown: None,
+        }
+    }
+    pub fn path(&self) -> Option<&String> {
+        self.path.as_ref()
+    }
+    pub fn has_image(&self) -> bool {
+        self.image.is_some()
+    }
+    /// Forgets the current image while the artwork of `path` loads.
+    pub fn start_loading(&mut self, path: Option<String>) {
+        self.path = path;
+        self.set(None);
+    }
+    /// Shows `image` if it is still for the track being loaded.
+    pub fn loaded(&mut self, path: &str, image: Option<Image>) {
+        if self.path.as_deref() == Some(path) {
+            self.set(image);
+        }
+    }
+    fn set(&mut self, image: Option<Image>) {
+        self.image = image;
+        self.scaled.clear();
+        self.generation += 1;
+    }
+    /// The image scaled to fit `width`×`height` pixels, from the cache when
+    /// that size was drawn recently.
+    fn scaled(&mut self, width: usize, height: usize) -> Option<&Image> {
+        let image = self.image.as_ref()?;
+        let size = image.fit_size(width, height);
+        match self.scaled.iter().position(|s| (s.width, s.height) == size) {
+            Some(i) => {
+                let hit = self.scaled.remove(i);
+                self.scaled.push(hit);
+            }
+            None => {
+                let resized = image.resize(size.0, size.1);
+                if self.scaled.len() == SCALED_CACHE {
+                    self.scaled.remove(0);
+                }
+                self.scaled.push(resized);
+            }
+        }
+        self.scaled.last()
+    }
+    /// Draws into `area`. With a graphics protocol the cells are left blank
+    /// and the image is written by `flush`; half-blocks are used instead
+    /// while a popup covers the area.
+    pub fn render(&mut self, area: Rect, buf: &mut Buffer, overlay: bool) {
+        if area.is_empty() {
+            return;
+        }
+        if self.graphics == Graphics::HalfBlocks || overlay {
+            self.render_half_blocks(area, buf);
+            return;
+        }
+        for y in area.top()..area.bottom() {
+            for x in area.left()..area.right() {
+                buf[(x, y)].reset();
+            }
+        }
+        let (cell_w, cell_h) = cell_size();
+        let generation = self.generation;
+        let Some(image) = self.scaled(area.width as usize * cell_w, area.height as usize * cell_h)
+        else {
+            return;
+        };
+        let columns = image.width.div_ceil(cell_w) as u16;
+        let rows = image.height.div_ceil(cell_h) as u16;
+        self.target = Some(Placement {
+            column: area.x + area.width.saturating_sub(columns) / 2,
+            row: area.y + area.height.saturating_sub(rows) / 2,
+            width: image.width,
+            height: image.height,
+            generation,
+        });
+    }
+    fn render_half_blocks(&mut self, area: Rect, buf: &mut Buffer) {
+        let Some(image) = self.scaled(area.width as usize, area.height as usize * 2) else {
+            return;
+        };
+        let x0 = area.x + (area.width - image.width as u16) / 2;
+        let y0 = area.y + (area.height - image.height.div_ceil(2) as u16) / 2;
+        let rgb = |p: [u8; 3]| Color::Rgb(p[0], p[1], p[2]);
+        for y in (0..image.height).step_by(2) {
+            for x in 0..image.width {
+                let top = image.pixels[y * image.width + x];
+                let cell = &mut buf[(x0 + x as u16, y0 + (y / 2) as u16)];
+                cell.set_symbol("▀").set_fg(rgb(top));
+                match image.pixels.get((y + 1) * image.width + x) {
+                    Some(&bottom) if y + 1 < image.height => cell.set_bg(rgb(bottom)),
+                    _ => cell.set_bg(Color::Reset),
+                };
+            }
+        }
+    }
+    /// Writes the protocol image drawn by the last `render`, or removes it
+    /// when nothing was rendered. Returns whether the whole screen must be
+    /// redrawn to get rid of an old sixel image, which is then shown on the
+    /// next frame.
+    pub fn flush(&mut self, out: &mut impl Write) -> io::Result<bool> {
+        let target = self.target.take();
+        if target == self.shown {
+            return Ok(false);
+        }
+        if self.shown.take().is_some() {
+            match self.graphics {
+                Graphics::Kitty => graphics::kitty_delete(out, KITTY_ID)?,
+                // Sixels stay on screen until their cells are redrawn.
+                Graphics::Sixel => return Ok(true),
+                Graphics::HalfBlocks => {}
+            }
+        }
+        let Some(target) = target else {
+            return Ok(false);
+        };
+        let Some(image) = self
+            .scaled
+            .iter()
+            .find(|s| (s.width, s.height) == (target.width, target.height))
+        else {
+            return Ok(false);
+        };
+        match self.graphics {
+            Graphics::Kitty => graphics::kitty(out, image, KITTY_ID, target.column, target.row)?,
+            Graphics::Sixel => graphics::sixel(out, image, target.column, target.row)?,
+            Graphics::HalfBlocks => {}
+        }
+        self.shown = Some(target);
+        Ok(false)
+    }
+}
+
+/// Pixel size of a terminal cell.
+fn cell_size() -> (usize, usize) {
+    match crossterm::terminal::window_size() {
+        Ok(size) if size.width > 0 && size.height > 0 && size.columns > 0 && size.rows > 0 => (
+            (size.width / size.columns) as usize,
+            (size.height / size.rows) as usize,
+        ),
+        _ => DEFAULT_CELL,
+    }
+}
@@ -0,0 +1,218 @@
+//! PNG decoder for cover art. Interlaced images aren't supported.
+
+use miniz_oxide::inflate::decompress_to_vec_zlib_with_limit;
+
+use super::{Image, MAX_SIDE};
+
+const SIGNATURE: &[u8] = b"\x89PNG\r\n\x1a\n";
+
+/// Decodes a non-interlaced PNG, compositing any transparency over black.
+pub fn decode(data: &[u8]) -> Option<Image> {
+    let mut rest = data.strip_prefix(SIGNATURE)?;
+    let mut header = None;
+    let mut palette: &[u8] = &[];
+    let mut compressed = Vec::new();
+    while rest.len() >= 12 {
+        let len = u32::from_be_bytes(rest[..4].try_into().unwrap()) as usize;
+        let kind = &rest[4..8];
+        // Length, type and CRC around the body.
+        let end = len.checked_add(12)?;
+        let body = rest.get(8..end - 4)?;
+        match kind {
+            b"IHDR" => header = Some(body),
+            b"PLTE" => palette = body,
+            b"IDAT" => compressed.extend_from_slice(body),
+            b"IEND" => break,
+            _ => {}
+        }
+        rest = rest.get(end..)?;
+    }
+    let header = header.filter(|h| h.len() >= 13)?;
+    let width = u32::from_be_bytes(header[0..4].try_into().unwrap()) as usize;
+    let height = u32::from_be_bytes(header[4..8].try_into().unwrap()) as usize;
+    let (depth, color, interlace) = (header[8] as usize, header[9], header[12]);
+    if interlace != 0 || !(1..=MAX_SIDE).contains(&width) || !(1..=MAX_SIDE).contains(&height) {
+        return None;
+    }
+    let (channels, depths): (usize, &[usize]) = match color {
+        0 => (1, &[1, 2, 4, 8, 16]),
+        3 => (1, &[1, 2, 4, 8]),
+        2 => (3, &[8, 16]),
+        4 => (2, &[8, 16]),
+        6 => (4, &[8, 16]),
+        _ => return None,
+    };
+    if !depths.contains(&depth) {
+        return None;
+    }
+    let bits_per_pixel = channels * depth;
+    let stride = width.checked_mul(bits_per_pixel)?.div_ceil(8);
+    // Distance to the corresponding byte of the pixel to the left.
+    let bpp = bits_per_pixel.div_ceil(8);
+    // Every row starts with its filter type.
+    let size = height.checked_mul(stride.checked_add(1)?)?;
+    let raw = decompress_to_vec_zlib_with_limit(&compressed, size).ok()?;
+    if raw.len() < size {
+        return None;
+    }
+
+    let mut rows = vec![0u8; height * stride];
+    for y in 0..height {
+        let filter = raw[y * (stride + 1)];
+        let line = &raw[y * (stride + 1) + 1..(y + 1) * (stride + 1)];
+        let (done, current) = rows.split_at_mut(y * stride);
+        let prior = if y > 0 {
+            &done[(y - 1) * stride..]
+        } else {
+            &[][..]
+        };
+        let current = &mut current[..stride];
+        for x in 0..stride {
+            let a = if x >= bpp { current[x - bpp] } else { 0 };
+            let b = prior.get(x).copied().unwrap_or(0);
+            let c = if x >= bpp {
+                prior.get(x - bpp).copied().unwrap_or(0)
+            } else {
+                0
+            };
+            current[x] = match filter {
+                0 => line[x],
+                1 => line[x].wrapping_add(a),
+                2 => line[x].wrapping_add(b),
+                3 => line[x].wrapping_add(((a as u16 + b as u16) / 2) as u8),
+                4 => line[x].wrapping_add(paeth(a, b, c)),
+                _ => return None,
+            };
+        }
+    }
+
+    // Sample `i` of a row, scaled to 8 bits.
+    let sample = |row: &[u8], i: usize| -> u8 {
+        match depth {
+            8 => row[i],
+            16 => row[i * 2],
+            _ => {
+                let per_byte = 8 / depth;
+                let shift = 8 - depth * (i % per_byte + 1);
+                let v = (row[i / per_byte] >> shift) & ((1 << depth) - 1) as u8;
+                if color == 3 {
+                    v
+                } else {
+                    (v as usize * 255 / ((1 << depth) - 1)) as u8
+                }
+            }
+        }
+    };
+    let mut pixels = Vec::with_capacity(width * height);
+    for row in rows.chunks_exact(stride) {
+        for x in 0..width {
+            let s = |c: usize| sample(row, x * channels + c);
+            let (rgb, alpha) = match color {
+                0 => ([s(0); 3], 255),
+                2 => ([s(0), s(1), s(2)], 255),
+                3 => {
+                    let i = s(0) as usize * 3;
+                    let entry = palette.get(i..i + 3)?;
+                    ([entry[0], entry[1], entry[2]], 255)
+                }
+                4 => ([s(0); 3], s(1)),
+                _ => ([s(0), s(1), s(2)], s(3)),
+            };
+            pixels.push(rgb.map(|v| (v as u16 * alpha as u16 / 255) as u8));
+        }
+    }
+    Some(Image {
+        width,
+        height,
+        pixels,
+    })
+}
+
+fn paeth(a: u8, b: u8, c: u8) -> u8 {
+    let p = a as i16 + b as i16 - c as i16;
+    let (pa, pb, pc) = (
+        (p - a as i16).abs(),
+        (p - b as i16).abs(),
+        (p - c as i16).abs(),
+    );
+    if pa <= pb && pa <= pc {
+        a
+    } else if pb <= pc {
+        b
+    } else {
+        c
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use miniz_oxide::deflate::compress_to_vec_zlib;
+
+    use super::*;
+
+    /// PNG with the given header fields and `rows` of raw scanlines.
+    fn png(width: u32, height: u32, depth: u8, color: u8, rows: &[u8]) -> Vec<u8> {
+        let mut header = Vec::new();
+        header.extend_from_slice(&width.to_be_bytes());
+        header.extend_from_slice(&height.to_be_bytes());
+        header.extend_from_slice(&[depth, color, 0, 0, 0]);
+        let mut data = SIGNATURE.to_vec();
+        for (kind, body) in [
+            (b"IHDR", header),
+            (b"IDAT", compress_to_vec_zlib(rows, 6)),
+            (b"IEND", Vec::new()),
+        ] {
+            data.extend_from_slice(&(body.len() as u32).to_be_bytes());
+            data.extend_from_slice(kind);
+            data.extend_from_slice(&body);
+            // The CRC isn't checked.
+            data.extend_from_slice(&[0; 4]);
+        }
+        data
+    }
+
+    #[test]
+    fn decodes_rgb() {
+        let image = decode(&png(2, 1, 8, 2, &[0, 255, 0, 0, 0, 0, 255])).unwrap();
+        assert_eq!((image.width, image.height), (2, 1));
+        assert_eq!(image.pixels, [[255, 0, 0], [0, 0, 255]]);
+    }
+
+    #[test]
+    fn rejects_bit_depths_the_colour_type_doesnt_allow() {
+        for (depth, color) in [(0, 0), (3, 0), (32, 0), (4, 2), (16, 3), (1, 4), (2, 6)] {
+            let rows = vec![0; 1 + 8];
+            assert!(
+                decode(&png(1, 1, depth, color, &rows)).is_none(),
+                "{depth} {color}"
+            );
+        }
+    }
+
+    #[test]
+    fn rejects_oversized_dimensions() {
+        let rows = vec![0; 1 + 8];
+        assert!(decode(&png(u32::MAX, u32::MAX, 16, 6, &rows)).is_none());
+        assert!(decode(&png(1, MAX_SIDE as u32 + 1, 8, 0, &rows)).is_none());
+        assert!(decode(&png(0, 1, 8, 0, &rows)).is_none());
+    }
+
+    #[test]
+    fn rejects_missing_and_truncated_data() {
+        // Two rows announced, one present.
+        assert!(decode(&png(1, 2, 8, 0, &[0, 7])).is_none());
+        // Unknown filter type.
+        assert!(decode(&png(1, 1, 8, 0, &[9, 7])).is_none());
+        let data = png(2, 1, 8, 2, &[0, 255, 0, 0, 0, 0, 255]);
+        for len in 0..data.len() - 12 {
+            assert!(decode(&data[..len]).is_none(), "{len}");
+        }
+    }
+
+    #[test]
+    fn rejects_chunk_lengths_past_the_end() {
+        let mut data = png(1, 1, 8, 0, &[0, 7]);
+        data[8..12].copy_from_slice(&u32::MAX.to_be_bytes());
+        assert!(decode(&data).is_none());
+    }
+}
@@ -1,5 +1,7 @@
 // donut.rs
 
+use std::f32::consts::TAU;
+
 use ratatui::{prelude::*, widgets::Widget};
 
 pub struct Donut {
@@ -30,9 +32,9 @@ impl Donut {
         let luminance = " .:-=+*#%@";
 
         let mut j = 0.0f32;
-        while j < 6.28 {
+        while j < TAU {
             let mut i = 0.0f32;
-            while i < 6.28 {
+            while i < TAU {
                 let c = i.sin();
                 let d = j.cos();
                 let e = self.a.sin();
@@ -98,7 +100,7 @@ impl Widget for &Donut {
                         _ => Color::White,
                     };
 
-                    buf.get_mut(area.x + x as u16, area.y + y as u16)
+                    buf[(area.x + x as u16, area.y + y as u16)]
                         .set_char(ch)
                         .set_fg(color);
                 }
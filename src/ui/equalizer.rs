@@ -0,0 +1,90 @@
+use ratatui::{prelude::*, widgets::Widget};
+
+use crate::{
+    audyo::equalizer::{BAND_FREQUENCIES, EqPreset, MAX_BAND_GAIN_DB},
+    ui::bar_symbol,
+};
+
+/// Band gains of a preset as bars growing up or down from a 0 dB line, with
+/// the gain above and the frequency below each bar.
+pub struct EqualizerPanel<'a> {
+    preset: &'a EqPreset,
+    selected: usize,
+    focused: bool,
+}
+
+impl<'a> EqualizerPanel<'a> {
+    pub fn new(preset: &'a EqPreset, selected: usize, focused: bool) -> Self {
+        Self {
+            preset,
+            selected,
+            focused,
+        }
+    }
+}
+
+fn frequency_label(hz: f32) -> String {
+    if hz >= 1000.0 {
+        format!("{}k", hz / 1000.0)
+    } else {
+        format!("{}", hz)
+    }
+}
+
+impl Widget for EqualizerPanel<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.height < 4 || area.width < BAND_FREQUENCIES.len() as u16 {
+            return;
+        }
+        let column = area.width / BAND_FREQUENCIES.len() as u16;
+        let bars = Rect::new(area.x, area.y + 1, area.width, area.height - 2);
+        let zero = bars.y + bars.height / 2;
+        // Eighths of a cell available on either side of the 0 dB line.
+        let half = (bars.height / 2) as f32 * 8.0;
+        for (i, (&hz, &gain)) in BAND_FREQUENCIES.iter().zip(&self.preset.gains).enumerate() {
+            let x = area.x + i as u16 * column;
+            let color = match (i == self.selected, self.focused) {
+                (true, true) => Color::Yellow,
+                (true, false) => Color::Cyan,
+                _ => Color::Green,
+            };
+            let style = Style::new().fg(color);
+            buf.set_stringn(x, area.y, format!("{:+.0}", gain), column as usize, style);
+            buf.set_stringn(
+                x,
+                area.bottom() - 1,
+                frequency_label(hz),
+                column as usize,
+                style,
+            );
+
+            let width = column.saturating_sub(1).max(1);
+            let mut eighths = (gain.abs() / MAX_BAND_GAIN_DB * half).round() as usize;
+            for dx in 0..width {
+                buf[(x + dx, zero)].set_symbol("─").set_fg(Color::DarkGray);
+            }
+            if gain > 0.0 {
+                for y in (bars.y..zero).rev() {
+                    let level = eighths.min(8);
+                    eighths -= level;
+                    for dx in 0..width {
+                        buf[(x + dx, y)].set_symbol(bar_symbol(level)).set_fg(color);
+                    }
+                }
+            } else if gain < 0.0 {
+                for y in zero + 1..bars.bottom() {
+                    let level = eighths.min(8);
+                    eighths -= level;
+                    let symbol = match level {
+                        0..=2 => " ",
+                        3..=6 => "▀",
+                        _ => "█",
+                    };
+                    for dx in 0..width {
+                        buf[(x + dx, y)].set_symbol(symbol).set_fg(color);
+                    }
+                }
+            }
+        }
+    }
+}
@@ -0,0 +1,84 @@
+use ratatui::{prelude::*, widgets::Widget};
+
+use crate::audyo::lyrics::Lyrics;
+
+/// Lyrics centred on the line being sung, or on the selected line while the
+/// panel has focus.
+pub struct LyricsPanel<'a> {
+    lyrics: &'a Lyrics,
+    current: Option<usize>,
+    selected: Option<usize>,
+}
+
+impl<'a> LyricsPanel<'a> {
+    pub fn new(lyrics: &'a Lyrics, current: Option<usize>, selected: Option<usize>) -> Self {
+        Self {
+            lyrics,
+            current,
+            selected,
+        }
+    }
+}
+
+/// Splits `text` into rows of at most `width` columns at spaces; words
+/// longer than a row are cut.
+fn wrap(text: &str, width: usize) -> Vec<String> {
+    let mut rows = vec![String::new()];
+    for word in text.split_whitespace() {
+        let row = rows.last_mut().unwrap();
+        let row_width = Span::raw(row.as_str()).width();
+        let word_width = Span::raw(word).width();
+        if row.is_empty() {
+            row.push_str(word);
+        } else if row_width + 1 + word_width <= width {
+            row.push(' ');
+            row.push_str(word);
+        } else {
+            rows.push(word.to_string());
+        }
+    }
+    rows
+}
+
+impl Widget for LyricsPanel<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.is_empty() {
+            return;
+        }
+        // Long transcript lines take several rows.
+        let rows: Vec<(usize, String)> = self
+            .lyrics
+            .lines
+            .iter()
+            .enumerate()
+            .flat_map(|(i, line)| {
+                wrap(&line.text, area.width as usize)
+                    .into_iter()
+                    .map(move |row| (i, row))
+            })
+            .collect();
+        let anchor = self.selected.or(self.current).unwrap_or(0);
+        let anchor_row = rows.iter().position(|(i, _)| *i == anchor).unwrap_or(0);
+        let first = anchor_row.saturating_sub(area.height as usize / 2);
+        for (y, (i, text)) in rows
+            .iter()
+            .skip(first)
+            .take(area.height as usize)
+            .enumerate()
+        {
+            let i = *i;
+            let style = if Some(i) == self.selected {
+                Style::new().fg(Color::Black).bg(Color::Cyan)
+            } else if Some(i) == self.current {
+                Style::new().fg(Color::Yellow).add_modifier(Modifier::BOLD)
+            } else if self.current.is_some_and(|c| i < c) {
+                Style::new().fg(Color::DarkGray)
+            } else {
+                Style::new()
+            };
+            let width = (Span::raw(text.as_str()).width() as u16).min(area.width);
+            let x = area.x + (area.width - width) / 2;
+            buf.set_stringn(x, area.y + y as u16, text, area.width as usize, style);
+        }
+    }
+}
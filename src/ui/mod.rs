@@ -1 +1,23 @@
+use ratatui::symbols::bar::NINE_LEVELS;
+
+pub mod artwork;
 pub mod donut;
+pub mod equalizer;
+pub mod lyrics;
+pub mod spectrum;
+pub mod waveform;
+
+/// Block character filling `eighths` eighths of a cell from the bottom.
+pub fn bar_symbol(eighths: usize) -> &'static str {
+    match eighths {
+        0 => NINE_LEVELS.empty,
+        1 => NINE_LEVELS.one_eighth,
+        2 => NINE_LEVELS.one_quarter,
+        3 => NINE_LEVELS.three_eighths,
+        4 => NINE_LEVELS.half,
+        5 => NINE_LEVELS.five_eighths,
+        6 => NINE_LEVELS.three_quarters,
+        7 => NINE_LEVELS.seven_eighths,
+        _ => NINE_LEVELS.full,
+    }
+}
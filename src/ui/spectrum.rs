@@ -0,0 +1,159 @@
+use ratatui::{prelude::*, widgets::Widget};
+
+use crate::ui::bar_symbol;
+
+/// Samples per FFT (~46ms at 44.1kHz).
+pub const FFT_SIZE: usize = 2048;
+const BANDS: usize = 64;
+const MIN_FREQ: f32 = 40.0;
+const MAX_FREQ: f32 = 16_000.0;
+/// Level mapped to an empty bar.
+const FLOOR_DB: f32 = -70.0;
+/// How much a bar may drop per update.
+const BAR_FALL: f32 = 0.06;
+/// Updates a peak marker stays in place before it starts falling.
+const PEAK_HOLD: u8 = 10;
+const PEAK_FALL: f32 = 0.02;
+
+#[derive(Clone, Copy, Default)]
+struct Peak {
+    level: f32,
+    hold: u8,
+}
+
+/// Log-frequency spectrum analyzer with peak-hold markers.
+pub struct Spectrum {
+    window: Vec<f32>,
+    bars: [f32; BANDS],
+    peaks: [Peak; BANDS],
+}
+
+impl Spectrum {
+    pub fn new() -> Self {
+        let window = (0..FFT_SIZE)
+            .map(|i| 0.5 - 0.5 * (2.0 * std::f32::consts::PI * i as f32 / FFT_SIZE as f32).cos())
+            .collect();
+        Self {
+            window,
+            bars: [0.0; BANDS],
+            peaks: [Peak::default(); BANDS],
+        }
+    }
+
+    /// Analyzes the latest `FFT_SIZE` samples.
+    pub fn update(&mut self, samples: &[f32], sample_rate: u32) {
+        let mut re: Vec<f32> = samples
+            .iter()
+            .zip(&self.window)
+            .map(|(s, w)| s * w)
+            .collect();
+        re.resize(FFT_SIZE, 0.0);
+        let mut im = vec![0.0; FFT_SIZE];
+        fft(&mut re, &mut im);
+
+        // A full-scale sine peaks at half the window's sum.
+        let scale = 2.0 / self.window.iter().sum::<f32>();
+        let bin_width = sample_rate as f32 / FFT_SIZE as f32;
+        for band in 0..BANDS {
+            let lo = band_edge(band) / bin_width;
+            let hi = band_edge(band + 1) / bin_width;
+            let lo = (lo as usize).clamp(1, FFT_SIZE / 2 - 1);
+            let hi = (hi as usize).clamp(lo + 1, FFT_SIZE / 2);
+            let magnitude = (lo..hi)
+                .map(|k| (re[k] * re[k] + im[k] * im[k]).sqrt())
+                .fold(0.0, f32::max)
+                * scale;
+            let db = 20.0 * magnitude.max(1e-9).log10();
+            let level = ((db - FLOOR_DB) / -FLOOR_DB).clamp(0.0, 1.0);
+
+            self.bars[band] = level.max(self.bars[band] - BAR_FALL);
+            let peak = &mut self.peaks[band];
+            if self.bars[band] >= peak.level {
+                *peak = Peak {
+                    level: self.bars[band],
+                    hold: PEAK_HOLD,
+                };
+            } else if peak.hold > 0 {
+                peak.hold -= 1;
+            } else {
+                peak.level = (peak.level - PEAK_FALL).max(0.0);
+            }
+        }
+    }
+}
+
+/// Lower frequency of `band`, log-spaced between `MIN_FREQ` and `MAX_FREQ`.
+fn band_edge(band: usize) -> f32 {
+    MIN_FREQ * (MAX_FREQ / MIN_FREQ).powf(band as f32 / BANDS as f32)
+}
+
+/// In-place iterative radix-2 FFT; the length must be a power of two.
+fn fft(re: &mut [f32], im: &mut [f32]) {
+    let n = re.len();
+    let mut j = 0;
+    for i in 1..n {
+        let mut bit = n >> 1;
+        while j & bit != 0 {
+            j ^= bit;
+            bit >>= 1;
+        }
+        j |= bit;
+        if i < j {
+            re.swap(i, j);
+            im.swap(i, j);
+        }
+    }
+    let mut len = 2;
+    while len <= n {
+        let angle = -2.0 * std::f32::consts::PI / len as f32;
+        for start in (0..n).step_by(len) {
+            for k in 0..len / 2 {
+                let (sin, cos) = (angle * k as f32).sin_cos();
+                let (a, b) = (start + k, start + k + len / 2);
+                let t_re = re[b] * cos - im[b] * sin;
+                let t_im = re[b] * sin + im[b] * cos;
+                re[b] = re[a] - t_re;
+                im[b] = im[a] - t_im;
+                re[a] += t_re;
+                im[a] += t_im;
+            }
+        }
+        len <<= 1;
+    }
+}
+
+fn level_color(height: f32) -> Color {
+    if height > 0.85 {
+        Color::Rgb(255, 50, 0)
+    } else if height > 0.6 {
+        Color::Rgb(255, 200, 0)
+    } else {
+        Color::Rgb(0, 255, 128)
+    }
+}
+
+impl Widget for &Spectrum {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        if area.is_empty() {
+            return;
+        }
+        let rows = area.height as usize;
+        for x in 0..area.width {
+            let band = x as usize * BANDS / area.width as usize;
+            let mut eighths = (self.bars[band] * rows as f32 * 8.0).round() as usize;
+            let peak_row = ((self.peaks[band].level * rows as f32) as usize).min(rows - 1);
+            for row in 0..rows {
+                let y = area.y + area.height - 1 - row as u16;
+                let level = eighths.min(8);
+                eighths -= level;
+                let cell = &mut buf[(area.x + x, y)];
+                if level > 0 {
+                    cell.set_symbol(bar_symbol(level))
+                        .set_fg(level_color(row as f32 / rows as f32));
+                } else if row == peak_row && self.peaks[band].level > 0.0 {
+                    cell.set_symbol("▔").set_fg(Color::White);
+                }
+            }
+        }
+    }
+}
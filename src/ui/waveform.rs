@@ -0,0 +1,46 @@
+use ratatui::{prelude::*, widgets::Widget};
+
+use crate::{audyo::service::WaveFormData, ui::bar_symbol};
+
+/// Seekbar drawn as the amplitude envelope of the track, with the part
+/// already played highlighted.
+pub struct Waveform<'a> {
+    data: &'a WaveFormData,
+    ratio: f64,
+}
+
+impl<'a> Waveform<'a> {
+    pub fn new(data: &'a WaveFormData, ratio: f64) -> Self {
+        Self { data, ratio }
+    }
+}
+
+impl Widget for Waveform<'_> {
+    fn render(self, area: Rect, buf: &mut Buffer) {
+        let samples = &self.data.samples;
+        if area.is_empty() || samples.is_empty() {
+            return;
+        }
+        let loudest = samples.iter().copied().fold(f32::EPSILON, f32::max);
+        let columns = area.width as usize;
+        for x in 0..columns {
+            let from = x * samples.len() / columns;
+            let to = ((x + 1) * samples.len() / columns).clamp(from + 1, samples.len());
+            let peak = samples[from..to].iter().copied().fold(0.0, f32::max) / loudest;
+            let mut eighths = (peak * area.height as f32 * 8.0).round() as usize;
+            let played = (x as f64 + 0.5) / columns as f64 <= self.ratio;
+            let color = if played {
+                Color::Rgb(255, 85, 0)
+            } else {
+                Color::DarkGray
+            };
+            for row in (0..area.height).rev() {
+                let level = eighths.min(8);
+                eighths -= level;
+                buf[(area.x + x as u16, area.y + row)]
+                    .set_symbol(bar_symbol(level))
+                    .set_fg(color);
+            }
+        }
+    }
+}
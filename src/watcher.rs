@@ -0,0 +1,596 @@
+use std::{
+    collections::HashMap,
+    fs, io,
+    path::{Path, PathBuf},
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, Ordering},
+        mpsc::Sender,
+    },
+    thread::{self, JoinHandle},
+};
+
+use crate::{
+    app::SignalMessage,
+    library::{self, is_playable},
+    library_index::Entry,
+};
+
+/// How long to wait for more events after the first of a batch, so the two
+/// halves of a rename arrive together.
+const BATCH_MS: i32 = 100;
+
+/// A change under the library folders.
+#[derive(Debug)]
+pub enum LibraryChange {
+    /// A playable file appeared or was rewritten, freshly probed.
+    Added(String, Entry),
+    /// A file or a whole folder went away.
+    Removed(String),
+    /// A file or folder moved within the library.
+    Renamed(String, String),
+    /// Events were lost; only a full scan catches up.
+    Rescan,
+}
+
+/// Watches the library folders with inotify and sends what changed as
+/// `SignalMessage::Library`.
+pub struct Watcher {
+    inner: Arc<Inner>,
+    thread: Option<JoinHandle<()>>,
+}
+
+struct Inner {
+    fd: i32,
+    /// Becomes readable when the watcher is dropped, waking its thread.
+    wake: i32,
+    /// Set when the watcher is dropped, so folder walks stop early.
+    stop: AtomicBool,
+    /// Watched folder of each watch descriptor.
+    watches: Mutex<HashMap<i32, PathBuf>>,
+}
+
+impl Watcher {
+    /// Starts watching `roots`. Their folders are walked on the watcher's
+    /// thread, so large libraries don't hold up startup.
+    pub fn start(roots: &[PathBuf], tx: Sender<SignalMessage>) -> io::Result<Self> {
+        let (fd, wake) = sys::init()?;
+        let inner = Arc::new(Inner {
+            fd,
+            wake,
+            stop: AtomicBool::new(false),
+            watches: Mutex::new(HashMap::new()),
+        });
+        let thread_inner = inner.clone();
+        let roots = roots.to_vec();
+        let thread = thread::spawn(move || {
+            for root in &roots {
+                thread_inner.watch_tree(root);
+            }
+            while let Ok(Some(events)) =
+                sys::read_events(thread_inner.fd, thread_inner.wake, BATCH_MS)
+            {
+                let changes = thread_inner.changes(events);
+                if !changes.is_empty() && tx.send(SignalMessage::Library(changes)).is_err() {
+                    break;
+                }
+            }
+        });
+        Ok(Self {
+            inner,
+            thread: Some(thread),
+        })
+    }
+    /// Starts watching `root` as well; folders already watched are skipped.
+    /// The walk runs on a thread of its own, like the one in `start`.
+    pub fn watch(&self, root: &Path) {
+        let inner = self.inner.clone();
+        let root = root.to_path_buf();
+        thread::spawn(move || inner.watch_tree(&root));
+    }
+}
+
+impl Drop for Watcher {
+    /// Stops the thread, which lets go of the last other reference to
+    /// `Inner` and with it the descriptors.
+    fn drop(&mut self) {
+        self.inner.stop.store(true, Ordering::Relaxed);
+        sys::wake(self.inner.wake);
+        if let Some(thread) = self.thread.take() {
+            let _ = thread.join();
+        }
+    }
+}
+
+impl Drop for Inner {
+    fn drop(&mut self) {
+        sys::close(self.fd);
+        sys::close(self.wake);
+    }
+}
+
+impl Inner {
+    /// Watches `dir` and every folder below it. Folders reached again
+    /// through a symlink share a descriptor and are not descended into twice.
+    fn watch_tree(&self, dir: &Path) {
+        let mut pending = vec![dir.to_path_buf()];
+        while let Some(dir) = pending.pop() {
+            if self.stop.load(Ordering::Relaxed) {
+                return;
+            }
+            let Ok(wd) = sys::add_watch(self.fd, &dir) else {
+                continue;
+            };
+            let mut watches = self.watches.lock().unwrap();
+            if watches.contains_key(&wd) {
+                continue;
+            }
+            watches.insert(wd, dir.clone());
+            drop(watches);
+            if let Ok(entries) = fs::read_dir(&dir) {
+                for entry in entries.flatten() {
+                    let path = entry.path();
+                    if !is_hidden(&path) && path.is_dir() {
+                        pending.push(path);
+                    }
+                }
+            }
+        }
+    }
+    /// Stops watching `dir` and what lies below it.
+    fn unwatch_tree(&self, dir: &Path) {
+        let mut watches = self.watches.lock().unwrap();
+        watches.retain(|&wd, path| {
+            let inside = path.starts_with(dir);
+            if inside {
+                sys::rm_watch(self.fd, wd);
+            }
+            !inside
+        });
+    }
+    /// A folder moved from `from` to `to`; its watches stay valid.
+    fn rename_tree(&self, from: &Path, to: &Path) {
+        for path in self.watches.lock().unwrap().values_mut() {
+            if let Ok(rest) = path.strip_prefix(from) {
+                *path = join(to, rest);
+            }
+        }
+    }
+    /// Files a new folder arrived with, watching it on the way.
+    fn added_tree(&self, dir: &Path, changes: &mut Vec<LibraryChange>) {
+        self.watch_tree(dir);
+        for file in library::scan(&[dir.to_path_buf()]).files {
+            added(Path::new(&file), changes);
+        }
+    }
+    fn changes(&self, events: Vec<sys::Event>) -> Vec<LibraryChange> {
+        let mut changes = Vec::new();
+        // The first half of renames, by cookie.
+        let mut moved_from: Vec<(u32, PathBuf, bool)> = Vec::new();
+        for event in events {
+            if event.overflow {
+                return vec![LibraryChange::Rescan];
+            }
+            if event.ignored {
+                self.watches.lock().unwrap().remove(&event.wd);
+                continue;
+            }
+            let Some(dir) = self.watches.lock().unwrap().get(&event.wd).cloned() else {
+                continue;
+            };
+            let path = dir.join(&event.name);
+            if event.name.is_empty() || is_hidden(&path) {
+                continue;
+            }
+            match event.kind {
+                sys::Kind::Written if !event.is_dir => added(&path, &mut changes),
+                sys::Kind::Created if event.is_dir => self.added_tree(&path, &mut changes),
+                sys::Kind::Deleted => changes.push(LibraryChange::Removed(display(&path))),
+                sys::Kind::MovedFrom => moved_from.push((event.cookie, path, event.is_dir)),
+                sys::Kind::MovedTo => {
+                    let from = moved_from
+                        .iter()
+                        .position(|(cookie, ..)| *cookie == event.cookie)
+                        .map(|i| moved_from.remove(i).1);
+                    match (from, event.is_dir) {
+                        (Some(from), true) => {
+                            self.rename_tree(&from, &path);
+                            changes.push(LibraryChange::Renamed(display(&from), display(&path)));
+                        }
+                        (Some(from), false) => {
+                            if is_playable(&path) {
+                                changes
+                                    .push(LibraryChange::Renamed(display(&from), display(&path)));
+                                added(&path, &mut changes);
+                            } else {
+                                changes.push(LibraryChange::Removed(display(&from)));
+                            }
+                        }
+                        (None, true) => self.added_tree(&path, &mut changes),
+                        (None, false) => added(&path, &mut changes),
+                    }
+                }
+                _ => {}
+            }
+        }
+        // Moved out of the library.
+        for (_, path, is_dir) in moved_from {
+            if is_dir {
+                self.unwatch_tree(&path);
+            }
+            changes.push(LibraryChange::Removed(display(&path)));
+        }
+        changes
+    }
+}
+
+fn added(path: &Path, changes: &mut Vec<LibraryChange>) {
+    if is_playable(path)
+        && let Some(entry) = Entry::probe(&display(path))
+    {
+        changes.push(LibraryChange::Added(display(path), entry));
+    }
+}
+
+fn is_hidden(path: &Path) -> bool {
+    path.file_name()
+        .is_some_and(|n| n.to_string_lossy().starts_with('.'))
+}
+
+fn display(path: &Path) -> String {
+    path.display().to_string()
+}
+
+/// `base` followed by `rest`, without a trailing separator for an empty one.
+pub fn join(base: &Path, rest: &Path) -> PathBuf {
+    if rest.as_os_str().is_empty() {
+        base.to_path_buf()
+    } else {
+        base.join(rest)
+    }
+}
+
+#[cfg(target_os = "linux")]
+mod sys {
+    use std::{
+        ffi::{CString, OsStr, OsString},
+        io,
+        os::unix::ffi::OsStrExt,
+        path::Path,
+    };
+
+    const MASK: u32 = libc::IN_CREATE
+        | libc::IN_CLOSE_WRITE
+        | libc::IN_DELETE
+        | libc::IN_MOVED_FROM
+        | libc::IN_MOVED_TO;
+    const HEADER: usize = std::mem::size_of::<libc::inotify_event>();
+
+    pub enum Kind {
+        Created,
+        Written,
+        Deleted,
+        MovedFrom,
+        MovedTo,
+        Other,
+    }
+
+    pub struct Event {
+        pub wd: i32,
+        pub kind: Kind,
+        pub is_dir: bool,
+        pub cookie: u32,
+        pub name: OsString,
+        pub overflow: bool,
+        /// The watch went away with its folder.
+        pub ignored: bool,
+    }
+
+    /// An inotify descriptor, and an eventfd that `wake` makes readable.
+    pub fn init() -> io::Result<(i32, i32)> {
+        let fd = unsafe { libc::inotify_init1(libc::IN_CLOEXEC) };
+        if fd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        let wake = unsafe { libc::eventfd(0, libc::EFD_CLOEXEC) };
+        if wake < 0 {
+            let error = io::Error::last_os_error();
+            close(fd);
+            return Err(error);
+        }
+        Ok((fd, wake))
+    }
+
+    pub fn add_watch(fd: i32, dir: &Path) -> io::Result<i32> {
+        let path = CString::new(dir.as_os_str().as_bytes())?;
+        let wd = unsafe { libc::inotify_add_watch(fd, path.as_ptr(), MASK | libc::IN_ONLYDIR) };
+        if wd < 0 {
+            return Err(io::Error::last_os_error());
+        }
+        Ok(wd)
+    }
+
+    pub fn rm_watch(fd: i32, wd: i32) {
+        unsafe { libc::inotify_rm_watch(fd, wd) };
+    }
+
+    pub fn close(fd: i32) {
+        unsafe { libc::close(fd) };
+    }
+
+    pub fn wake(wake: i32) {
+        let one = 1u64;
+        unsafe { libc::write(wake, (&raw const one).cast(), 8) };
+    }
+
+    /// Blocks until there are events, then keeps collecting for `batch_ms`.
+    /// `None` once `wake` was called.
+    pub fn read_events(fd: i32, wake: i32, batch_ms: i32) -> io::Result<Option<Vec<Event>>> {
+        let mut events = Vec::new();
+        let mut timeout = -1;
+        loop {
+            let mut poll = [fd, wake].map(|fd| libc::pollfd {
+                fd,
+                events: libc::POLLIN,
+                revents: 0,
+            });
+            let ready = unsafe { libc::poll(poll.as_mut_ptr(), 2, timeout) };
+            if ready < 0 {
+                let error = io::Error::last_os_error();
+                if error.kind() == io::ErrorKind::Interrupted {
+                    continue;
+                }
+                return Err(error);
+            }
+            if poll[1].revents != 0 {
+                return Ok(None);
+            }
+            if ready == 0 {
+                return Ok(Some(events));
+            }
+            let mut buf = [0u8; 64 * 1024];
+            let len = unsafe { libc::read(fd, buf.as_mut_ptr().cast(), buf.len()) };
+            if len < 0 {
+                return Err(io::Error::last_os_error());
+            }
+            parse(&buf[..len as usize], &mut events);
+            timeout = batch_ms;
+        }
+    }
+
+    fn parse(mut buf: &[u8], events: &mut Vec<Event>) {
+        while buf.len() >= HEADER {
+            let raw: libc::inotify_event = unsafe { std::ptr::read_unaligned(buf.as_ptr().cast()) };
+            let end = HEADER + raw.len as usize;
+            let Some(name) = buf.get(HEADER..end) else {
+                return;
+            };
+            // The name is padded with NULs.
+            let name = name.split(|&b| b == 0).next().unwrap_or_default();
+            let kind = match raw.mask {
+                m if m & libc::IN_CREATE != 0 => Kind::Created,
+                m if m & libc::IN_CLOSE_WRITE != 0 => Kind::Written,
+                m if m & libc::IN_DELETE != 0 => Kind::Deleted,
+                m if m & libc::IN_MOVED_FROM != 0 => Kind::MovedFrom,
+                m if m & libc::IN_MOVED_TO != 0 => Kind::MovedTo,
+                _ => Kind::Other,
+            };
+            events.push(Event {
+                wd: raw.wd,
+                kind,
+                is_dir: raw.mask & libc::IN_ISDIR != 0,
+                cookie: raw.cookie,
+                name: OsStr::from_bytes(name).to_os_string(),
+                overflow: raw.mask & libc::IN_Q_OVERFLOW != 0,
+                ignored: raw.mask & libc::IN_IGNORED != 0,
+            });
+            buf = &buf[end..];
+        }
+    }
+}
+
+/// Without inotify the library only updates on `r` and after downloads.
+/// Nothing here produces events, so their types are never built.
+#[cfg(not(target_os = "linux"))]
+#[allow(dead_code)]
+mod sys {
+    use std::{ffi::OsString, io, path::Path};
+
+    pub enum Kind {
+        Created,
+        Written,
+        Deleted,
+        MovedFrom,
+        MovedTo,
+    }
+
+    pub struct Event {
+        pub wd: i32,
+        pub kind: Kind,
+        pub is_dir: bool,
+        pub cookie: u32,
+        pub name: OsString,
+        pub overflow: bool,
+        pub ignored: bool,
+    }
+
+    pub fn init() -> io::Result<(i32, i32)> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn add_watch(_: i32, _: &Path) -> io::Result<i32> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+
+    pub fn rm_watch(_: i32, _: i32) {}
+
+    pub fn close(_: i32) {}
+
+    pub fn wake(_: i32) {}
+
+    pub fn read_events(_: i32, _: i32, _: i32) -> io::Result<Option<Vec<Event>>> {
+        Err(io::ErrorKind::Unsupported.into())
+    }
+}
+
+#[cfg(all(test, target_os = "linux"))]
+mod tests {
+    use std::{sync::mpsc, time::Duration};
+
+    use super::*;
+
+    /// Library folder with `dirs` in it, watched by an `Inner` whose thread
+    /// isn't running, so events can be fed in by hand.
+    struct Library {
+        inner: Inner,
+        root: PathBuf,
+    }
+
+    impl Library {
+        fn new(test: &str, dirs: &[&str]) -> Self {
+            let root =
+                std::env::temp_dir().join(format!("audyo-watch-{}-{}", test, std::process::id()));
+            for dir in dirs {
+                fs::create_dir_all(root.join(dir)).unwrap();
+            }
+            fs::create_dir_all(&root).unwrap();
+            let (fd, wake) = sys::init().unwrap();
+            let inner = Inner {
+                fd,
+                wake,
+                stop: AtomicBool::new(false),
+                watches: Mutex::new(HashMap::new()),
+            };
+            inner.watch_tree(&root);
+            Self { inner, root }
+        }
+        fn wd(&self, dir: &str) -> i32 {
+            let path = join(&self.root, Path::new(dir));
+            let watches = self.inner.watches.lock().unwrap();
+            *watches.iter().find(|(_, p)| **p == path).unwrap().0
+        }
+        fn watched(&self) -> Vec<PathBuf> {
+            let mut paths: Vec<_> = self
+                .inner
+                .watches
+                .lock()
+                .unwrap()
+                .values()
+                .cloned()
+                .collect();
+            paths.sort();
+            paths
+        }
+        fn path(&self, name: &str) -> String {
+            display(&self.root.join(name))
+        }
+    }
+
+    impl Drop for Library {
+        fn drop(&mut self) {
+            let _ = fs::remove_dir_all(&self.root);
+        }
+    }
+
+    fn event(wd: i32, kind: sys::Kind, name: &str, cookie: u32, is_dir: bool) -> sys::Event {
+        sys::Event {
+            wd,
+            kind,
+            is_dir,
+            cookie,
+            name: name.into(),
+            overflow: false,
+            ignored: false,
+        }
+    }
+
+    #[test]
+    fn renames_a_file_within_the_library() {
+        let library = Library::new("rename-file", &[]);
+        fs::write(library.root.join("b.mp3"), b"").unwrap();
+        let wd = library.wd("");
+        let changes = library.inner.changes(vec![
+            event(wd, sys::Kind::MovedFrom, "a.mp3", 7, false),
+            event(wd, sys::Kind::MovedTo, "b.mp3", 7, false),
+        ]);
+        let (a, b) = (library.path("a.mp3"), library.path("b.mp3"));
+        assert!(
+            matches!(&changes[..], [
+                LibraryChange::Renamed(from, to),
+                LibraryChange::Added(added, _),
+            ] if *from == a && *to == b && *added == b),
+            "{changes:?}"
+        );
+    }
+
+    #[test]
+    fn renames_a_folder_and_its_watches() {
+        let library = Library::new("rename-dir", &["old/inner"]);
+        let wd = library.wd("");
+        let changes = library.inner.changes(vec![
+            event(wd, sys::Kind::MovedFrom, "old", 3, true),
+            event(wd, sys::Kind::MovedTo, "new", 3, true),
+        ]);
+        let (old, new) = (library.path("old"), library.path("new"));
+        assert!(
+            matches!(&changes[..], [LibraryChange::Renamed(from, to)] if *from == old && *to == new),
+            "{changes:?}"
+        );
+        assert_eq!(
+            library.watched(),
+            [
+                library.root.clone(),
+                library.root.join("new"),
+                library.root.join("new/inner")
+            ]
+        );
+    }
+
+    #[test]
+    fn moving_out_removes_and_unwatches() {
+        let library = Library::new("move-out", &["gone/inner", "kept"]);
+        let wd = library.wd("");
+        let changes = library
+            .inner
+            .changes(vec![event(wd, sys::Kind::MovedFrom, "gone", 5, true)]);
+        let gone = library.path("gone");
+        assert!(
+            matches!(&changes[..], [LibraryChange::Removed(path)] if *path == gone),
+            "{changes:?}"
+        );
+        assert_eq!(
+            library.watched(),
+            [library.root.clone(), library.root.join("kept")]
+        );
+    }
+
+    #[test]
+    fn overflow_asks_for_a_rescan() {
+        let library = Library::new("overflow", &[]);
+        fs::write(library.root.join("a.mp3"), b"").unwrap();
+        let wd = library.wd("");
+        let mut overflow = event(-1, sys::Kind::Other, "", 0, false);
+        overflow.overflow = true;
+        let changes = library.inner.changes(vec![
+            event(wd, sys::Kind::Written, "a.mp3", 0, false),
+            overflow,
+        ]);
+        assert!(
+            matches!(&changes[..], [LibraryChange::Rescan]),
+            "{changes:?}"
+        );
+    }
+
+    #[test]
+    fn dropping_the_watcher_ends_its_thread() {
+        let library = Library::new("drop", &["a", "b"]);
+        let (tx, rx) = mpsc::channel();
+        let watcher = Watcher::start(std::slice::from_ref(&library.root), tx).unwrap();
+        drop(watcher);
+        // The thread held the last sender.
+        assert!(matches!(
+            rx.recv_timeout(Duration::from_secs(5)),
+            Err(mpsc::RecvTimeoutError::Disconnected)
+        ));
+    }
+}